@@ -0,0 +1,37 @@
+//! Converts the fonts in `fonts/fonts.txt` into Rust statics in `OUT_DIR`
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::exit;
+
+fn main() {
+    let list = env::var_os("IZZYMONITOR_FONTS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("fonts/fonts.txt"));
+    println!("cargo:rerun-if-env-changed=IZZYMONITOR_FONTS");
+    println!("cargo:rerun-if-changed={}", list.display());
+
+    if let Err(e) = generate(&list) {
+        eprintln!("error: {}: {e}", list.display());
+        exit(1);
+    }
+}
+
+fn generate(list: &PathBuf) -> Result<(), String> {
+    let text = fs::read_to_string(list).map_err(|e| e.to_string())?;
+    let dir = list.parent().unwrap_or(list);
+    let mut out = String::new();
+    for spec in izzymonitor_fontgen::parse_config(&text)? {
+        let source = dir.join(&spec.source);
+        println!("cargo:rerun-if-changed={}", source.display());
+        let font = izzymonitor_fontgen::load(&source, spec.size, &spec.subset)?;
+        out.push_str(&izzymonitor_fontgen::generate(
+            &spec.name,
+            &spec.source,
+            &font,
+        ));
+    }
+    let path = PathBuf::from(env::var_os("OUT_DIR").ok_or("OUT_DIR not set")?).join("fonts.rs");
+    fs::write(path, out).map_err(|e| e.to_string())
+}
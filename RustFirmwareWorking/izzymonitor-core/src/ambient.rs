@@ -0,0 +1,295 @@
+//! Ambient light
+//! Turns light sensor readings into backlight and LED brightness
+//!
+//! Readings are smoothed in stops (powers of two of lux), where the eye works,
+//! and the level only moves once the room has changed by more than
+//! [`HYSTERESIS_STOPS`], so a shadow or a lamp at the threshold can't make the
+//! panel pump.
+
+use core::fmt;
+
+use micromath::F32Ext;
+
+pub mod sensor;
+
+pub use sensor::{LightSensor, SensorModel};
+
+/// Darkest reading that counts, a sensor reading 0 lux reads this
+pub const MIN_LUX: f32 = 0.125;
+/// Light at which the panel is at its dimmest
+pub const DARK_LUX: f32 = 1.0;
+/// Light at which the panel is at full brightness
+pub const BRIGHT_LUX: f32 = 1024.0;
+/// Share of a new reading taken into the average, about a second at 4 Hz
+pub const SMOOTHING: f32 = 0.25;
+/// How far the average must move before the level follows
+pub const HYSTERESIS_STOPS: f32 = 0.5;
+
+/// Backlight level in the dark, still readable across a hallway
+pub const MIN_BACKLIGHT: u8 = 16;
+/// LED level in the dark, the dim hint colors stay visible under calibration
+pub const MIN_LED_LEVEL: u8 = 64;
+
+/// Manual brightness settings in percent, Down from the first goes back to auto
+pub const MANUAL_STEPS: [u8; 5] = [10, 25, 50, 75, 100];
+
+/// Output levels, 255 is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Levels {
+    /// Backlight PWM duty
+    pub backlight: u8,
+    /// Scale applied to every LED color
+    pub leds: u8,
+}
+
+impl Levels {
+    /// Everything at full, what the panel did before it had a sensor
+    pub const FULL: Levels = Levels {
+        backlight: u8::MAX,
+        leds: u8::MAX,
+    };
+
+    /// Levels a share of the way from the dimmest to full
+    pub fn at(share: f32) -> Self {
+        let share = share.clamp(0.0, 1.0);
+        let level =
+            |min: u8| F32Ext::round(f32::from(min) + share * f32::from(u8::MAX - min)) as u8;
+        Self {
+            backlight: level(MIN_BACKLIGHT),
+            leds: level(MIN_LED_LEVEL),
+        }
+    }
+
+    /// Levels for a room, by stops between [`DARK_LUX`] and [`BRIGHT_LUX`]
+    pub fn for_lux(lux: f32) -> Self {
+        let dark = F32Ext::log2(DARK_LUX);
+        let stops = F32Ext::log2(lux.max(MIN_LUX));
+        Self::at((stops - dark) / (F32Ext::log2(BRIGHT_LUX) - dark))
+    }
+}
+
+/// Who decides how bright the panel is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BrightnessMode {
+    /// Follow the light sensor, full without one
+    #[default]
+    Auto,
+    /// Fixed, in percent of the way from the dimmest to full
+    Manual(u8),
+}
+
+impl BrightnessMode {
+    /// Serialize for storage, 0 is auto
+    pub fn to_byte(self) -> u8 {
+        match self {
+            BrightnessMode::Auto => 0,
+            BrightnessMode::Manual(percent) => percent,
+        }
+    }
+
+    /// Deserialize from storage
+    pub fn from_byte(byte: u8) -> Result<Self, &'static str> {
+        match byte {
+            0 => Ok(BrightnessMode::Auto),
+            1..=100 => Ok(BrightnessMode::Manual(byte)),
+            _ => Err("Brightness out of range"),
+        }
+    }
+
+    /// The next setting up or down through [`MANUAL_STEPS`]
+    pub fn step(self, up: bool) -> Self {
+        let last = MANUAL_STEPS.len() - 1;
+        match (self, up) {
+            (BrightnessMode::Auto, true) => BrightnessMode::Manual(MANUAL_STEPS[0]),
+            (BrightnessMode::Auto, false) => BrightnessMode::Auto,
+            (BrightnessMode::Manual(percent), up) => {
+                // First step at or above the setting, which may be off the steps
+                let index = MANUAL_STEPS
+                    .iter()
+                    .position(|&step| step >= percent)
+                    .unwrap_or(last);
+                match (up, index) {
+                    (true, _) if MANUAL_STEPS[index] > percent => {
+                        BrightnessMode::Manual(MANUAL_STEPS[index])
+                    }
+                    (true, _) => BrightnessMode::Manual(MANUAL_STEPS[(index + 1).min(last)]),
+                    (false, 0) => BrightnessMode::Auto,
+                    (false, _) => BrightnessMode::Manual(MANUAL_STEPS[index - 1]),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for BrightnessMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BrightnessMode::Auto => f.write_str("Auto"),
+            BrightnessMode::Manual(percent) => write!(f, "{percent}%"),
+        }
+    }
+}
+
+/// Smoothing and hysteresis on light readings
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LightFilter {
+    // Both in stops
+    average: Option<f32>,
+    held: Option<f32>,
+}
+
+impl LightFilter {
+    /// No readings yet
+    pub const fn new() -> Self {
+        Self {
+            average: None,
+            held: None,
+        }
+    }
+
+    /// Take a reading in lux, returning the light the panel should follow
+    pub fn update(&mut self, lux: f32) -> f32 {
+        let stops = F32Ext::log2(lux.max(MIN_LUX));
+        let average = match self.average {
+            Some(average) => average + (stops - average) * SMOOTHING,
+            // The first reading is all there is
+            None => stops,
+        };
+        self.average = Some(average);
+        if self
+            .held
+            .is_none_or(|held| (average - held).abs() > HYSTERESIS_STOPS)
+        {
+            self.held = Some(average);
+        }
+        self.lux().unwrap_or(lux)
+    }
+
+    /// The light the panel follows, `None` before the first reading
+    pub fn lux(&self) -> Option<f32> {
+        self.held.map(|stops| F32Ext::powf(2.0, stops))
+    }
+}
+
+/// Brightness setting plus what the sensor says
+#[derive(Debug, Clone, Copy)]
+pub struct Brightness {
+    mode: BrightnessMode,
+    filter: LightFilter,
+}
+
+impl Brightness {
+    /// Start with a stored setting and no readings
+    pub const fn new(mode: BrightnessMode) -> Self {
+        Self {
+            mode,
+            filter: LightFilter::new(),
+        }
+    }
+
+    /// The setting in use
+    pub fn mode(&self) -> BrightnessMode {
+        self.mode
+    }
+
+    /// Change the setting, readings carry on in the background
+    pub fn set_mode(&mut self, mode: BrightnessMode) {
+        self.mode = mode;
+    }
+
+    /// Take a sensor reading in lux
+    pub fn sample(&mut self, lux: f32) {
+        self.filter.update(lux);
+    }
+
+    /// The smoothed light, `None` without readings
+    pub fn lux(&self) -> Option<f32> {
+        self.filter.lux()
+    }
+
+    /// Levels to drive the backlight and LEDs with
+    pub fn levels(&self) -> Levels {
+        match self.mode {
+            BrightnessMode::Auto => self.lux().map_or(Levels::FULL, Levels::for_lux),
+            BrightnessMode::Manual(percent) => Levels::at(f32::from(percent) / 100.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_follow_stops() {
+        assert_eq!(
+            Levels::for_lux(0.0),
+            Levels {
+                backlight: MIN_BACKLIGHT,
+                leds: MIN_LED_LEVEL
+            }
+        );
+        assert_eq!(Levels::for_lux(5000.0), Levels::FULL);
+        // 32 lux is half way in stops
+        let half = Levels::for_lux(32.0);
+        assert!((134..=137).contains(&half.backlight), "{half:?}");
+        assert!(half.leds > half.backlight);
+    }
+
+    #[test]
+    fn filter_smooths_and_holds() {
+        let mut filter = LightFilter::new();
+        assert_eq!(filter.update(100.0).round(), 100.0);
+        // Someone walks past: a dip of over a stop moves the average a third
+        assert_eq!(filter.update(40.0).round(), 100.0);
+        // The lights go out for good, it follows within a few readings
+        let readings = (1..=20).find(|_| filter.update(1.0) < 8.0);
+        assert!(readings.is_some_and(|n| n <= 8), "{readings:?}");
+        for _ in 0..20 {
+            filter.update(1.0);
+        }
+        assert!(filter.lux().unwrap() < 1.5);
+
+        // Flicker around one level doesn't move it
+        let mut filter = LightFilter::new();
+        let held = filter.update(1.0);
+        for lux in [1.2, 0.8, 1.3, 0.7] {
+            assert_eq!(filter.update(lux), held);
+        }
+    }
+
+    #[test]
+    fn manual_steps_and_storage() {
+        let mut mode = BrightnessMode::Auto;
+        assert_eq!(mode.step(false), BrightnessMode::Auto);
+        for _ in 0..10 {
+            mode = mode.step(true);
+        }
+        assert_eq!(mode, BrightnessMode::Manual(100));
+        assert_eq!(
+            BrightnessMode::Manual(30).step(true),
+            BrightnessMode::Manual(50)
+        );
+        assert_eq!(
+            BrightnessMode::Manual(30).step(false),
+            BrightnessMode::Manual(25)
+        );
+        assert_eq!(BrightnessMode::Manual(10).step(false), BrightnessMode::Auto);
+
+        for mode in [BrightnessMode::Auto, BrightnessMode::Manual(75)] {
+            assert_eq!(BrightnessMode::from_byte(mode.to_byte()), Ok(mode));
+        }
+        assert!(BrightnessMode::from_byte(101).is_err());
+        assert_eq!(std::format!("{}", BrightnessMode::Manual(25)), "25%");
+    }
+
+    #[test]
+    fn manual_overrides_the_sensor() {
+        let mut brightness = Brightness::new(BrightnessMode::Auto);
+        assert_eq!(brightness.levels(), Levels::FULL);
+        brightness.sample(1.0);
+        assert_eq!(brightness.levels().backlight, MIN_BACKLIGHT);
+        brightness.set_mode(BrightnessMode::Manual(100));
+        assert_eq!(brightness.levels(), Levels::FULL);
+    }
+}
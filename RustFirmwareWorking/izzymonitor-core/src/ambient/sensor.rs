@@ -0,0 +1,192 @@
+//! Light sensors
+//! VEML7700 and BH1750 drivers for the Qwiic I2C port
+//!
+//! Both run continuously once set up, so a reading is a single register
+//! read. The VEML7700 runs at gain 1/8 and 100 ms, which covers up to about
+//! 35000 lux, direct sun through a window.
+
+use embedded_hal::i2c::I2c;
+
+/// VEML7700 bus address, fixed
+pub const VEML7700_ADDRESS: u8 = 0x10;
+/// BH1750 bus addresses, ADDR low then high
+pub const BH1750_ADDRESSES: [u8; 2] = [0x23, 0x5c];
+
+// VEML7700 registers and settings
+const VEML7700_ALS_CONF: u8 = 0x00;
+const VEML7700_ALS: u8 = 0x04;
+// Gain 1/8, 100 ms integration, powered on
+const VEML7700_CONFIG: u16 = 0b10 << 11;
+const VEML7700_LUX_PER_COUNT: f32 = 0.5376;
+
+// BH1750 instructions
+const BH1750_POWER_ON: u8 = 0x01;
+const BH1750_CONTINUOUS_HIGH_RES: u8 = 0x10;
+const BH1750_COUNTS_PER_LUX: f32 = 1.2;
+
+/// Supported light sensors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SensorModel {
+    Veml7700,
+    /// At the address its ADDR pin selects
+    Bh1750(u8),
+}
+
+impl SensorModel {
+    /// Name for the log
+    pub fn name(self) -> &'static str {
+        match self {
+            SensorModel::Veml7700 => "VEML7700",
+            SensorModel::Bh1750(_) => "BH1750",
+        }
+    }
+
+    /// Bus address
+    pub fn address(self) -> u8 {
+        match self {
+            SensorModel::Veml7700 => VEML7700_ADDRESS,
+            SensorModel::Bh1750(address) => address,
+        }
+    }
+
+    /// Light from a raw reading
+    pub fn lux(self, raw: u16) -> f32 {
+        match self {
+            SensorModel::Veml7700 => {
+                // Vishay's correction for the nonlinearity at low gain
+                let x = f32::from(raw) * VEML7700_LUX_PER_COUNT;
+                (((6.0135e-13 * x - 9.3924e-9) * x + 8.1488e-5) * x + 1.0023) * x
+            }
+            SensorModel::Bh1750(_) => f32::from(raw) / BH1750_COUNTS_PER_LUX,
+        }
+    }
+}
+
+/// A light sensor found on the bus
+pub struct LightSensor<I> {
+    bus: I,
+    model: SensorModel,
+}
+
+impl<I: I2c> LightSensor<I> {
+    /// Look for a sensor and start it measuring
+    pub fn probe(mut bus: I) -> Result<Self, &'static str> {
+        let [lo, hi] = VEML7700_CONFIG.to_le_bytes();
+        if bus
+            .write(VEML7700_ADDRESS, &[VEML7700_ALS_CONF, lo, hi])
+            .is_ok()
+        {
+            return Ok(Self {
+                bus,
+                model: SensorModel::Veml7700,
+            });
+        }
+        for address in BH1750_ADDRESSES {
+            // Only a sensor that is there acknowledges
+            if bus.write(address, &[BH1750_POWER_ON]).is_ok() {
+                bus.write(address, &[BH1750_CONTINUOUS_HIGH_RES])
+                    .map_err(|_| "Failed to start light sensor")?;
+                return Ok(Self {
+                    bus,
+                    model: SensorModel::Bh1750(address),
+                });
+            }
+        }
+        Err("No light sensor found")
+    }
+
+    /// The sensor that answered
+    pub fn model(&self) -> SensorModel {
+        self.model
+    }
+
+    /// Latest reading in lux
+    pub fn lux(&mut self) -> Result<f32, &'static str> {
+        let mut data = [0; 2];
+        let raw = match self.model {
+            SensorModel::Veml7700 => {
+                self.bus
+                    .write_read(VEML7700_ADDRESS, &[VEML7700_ALS], &mut data)
+                    .map_err(|_| "Light sensor read failed")?;
+                u16::from_le_bytes(data)
+            }
+            SensorModel::Bh1750(address) => {
+                self.bus
+                    .read(address, &mut data)
+                    .map_err(|_| "Light sensor read failed")?;
+                u16::from_be_bytes(data)
+            }
+        };
+        Ok(self.model.lux(raw))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, ErrorType, NoAcknowledgeSource, Operation};
+    use std::vec::Vec;
+
+    /// One device on a bus, recording what was written to it
+    struct FakeBus {
+        address: u8,
+        reading: [u8; 2],
+        written: Vec<Vec<u8>>,
+    }
+
+    impl ErrorType for FakeBus {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for FakeBus {
+        fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), ErrorKind> {
+            if address != self.address {
+                return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+            }
+            for operation in operations {
+                match operation {
+                    Operation::Write(data) => self.written.push(data.to_vec()),
+                    Operation::Read(buf) => buf.copy_from_slice(&self.reading),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn bus(address: u8, reading: [u8; 2]) -> FakeBus {
+        FakeBus {
+            address,
+            reading,
+            written: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn finds_and_reads_a_veml7700() {
+        // 1000 counts, little endian
+        let mut sensor = LightSensor::probe(bus(0x10, [0xe8, 0x03])).unwrap();
+        assert_eq!(sensor.model(), SensorModel::Veml7700);
+        assert_eq!(sensor.bus.written[0], [0x00, 0x00, 0x10]);
+        let lux = sensor.lux().unwrap();
+        assert!((555.0..566.0).contains(&lux), "{lux}");
+        assert_eq!(sensor.bus.written[1], [VEML7700_ALS]);
+    }
+
+    #[test]
+    fn finds_and_reads_a_bh1750() {
+        // 120 counts, big endian
+        let mut sensor = LightSensor::probe(bus(0x5c, [0x00, 0x78])).unwrap();
+        assert_eq!(sensor.model(), SensorModel::Bh1750(0x5c));
+        assert_eq!(sensor.bus.written, [[0x01], [0x10]]);
+        assert_eq!(sensor.lux().map(f32::round), Ok(100.0));
+
+        assert_eq!(
+            LightSensor::probe(bus(0x40, [0, 0])).err(),
+            Some("No light sensor found")
+        );
+    }
+}
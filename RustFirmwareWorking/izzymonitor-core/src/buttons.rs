@@ -0,0 +1,189 @@
+//! Button handling module
+//! Turns sampled key levels into button state changes
+
+use embedded_hal::digital::InputPin;
+
+use crate::KEY_COUNT;
+
+pub mod channel;
+pub mod chord;
+pub mod debounce;
+pub mod repeat;
+pub mod stats;
+
+pub use channel::{
+    next_event, wait_for_button_press, ButtonChannel, ButtonPublisher, ButtonStates,
+    ButtonSubscriber, KeyEventQueue, RepeatSignal,
+};
+pub use chord::{
+    ButtonEvent, ChordBinding, ChordDetector, ChordEvent, ChordId, ChordRegistry, KeySet,
+};
+pub use debounce::{DebounceConfig, Debouncer, KeyEvent, KeyEventKind};
+pub use repeat::RepeatCurve;
+pub use stats::KeyStats;
+
+/// Human-readable key names, left to right
+pub const KEY_NAMES: [&str; KEY_COUNT] = ["key 1", "key 2", "key 3", "key 4", "key 5", "key 6"];
+
+/// The key labelled "Up" on every screen
+pub const UP_KEY: usize = 4;
+/// The key labelled "Down" on every screen
+pub const DOWN_KEY: usize = 5;
+
+/// Button state for tracking button status
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonState {
+    Idle,
+    Pressed,
+    Released,
+}
+
+/// Level tracker for a single key
+///
+/// A key reports `Pressed` once when it goes down and `Released` once when it
+/// comes back up, then settles to `Idle` on the following sample.
+#[derive(Debug, Clone, Copy)]
+pub struct ButtonTracker {
+    state: ButtonState,
+}
+
+impl ButtonTracker {
+    /// Create a new tracker in the idle state
+    pub const fn new() -> Self {
+        Self {
+            state: ButtonState::Idle,
+        }
+    }
+
+    /// Get the current state
+    pub fn state(&self) -> ButtonState {
+        self.state
+    }
+
+    /// Feed one level sample, returning the new state if it changed
+    pub fn update(&mut self, pressed: bool) -> Option<ButtonState> {
+        let next = match (self.state, pressed) {
+            (ButtonState::Pressed, true) => return None,
+            (_, true) => ButtonState::Pressed,
+            (ButtonState::Pressed, false) => ButtonState::Released,
+            (ButtonState::Released, false) => ButtonState::Idle,
+            (ButtonState::Idle, false) => return None,
+        };
+
+        self.state = next;
+        Some(next)
+    }
+}
+
+impl Default for ButtonTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Button structure storing pin and state
+///
+/// The pin is active low, matching the pulled-up Kailh switches.
+pub struct Button<P> {
+    pin: P,
+    name: &'static str,
+    id: usize,
+    tracker: ButtonTracker,
+}
+
+impl<P: InputPin> Button<P> {
+    /// Create a new button
+    pub fn new(pin: P, name: &'static str, id: usize) -> Self {
+        Self {
+            pin,
+            name,
+            id,
+            tracker: ButtonTracker::new(),
+        }
+    }
+
+    /// Check if button is pressed
+    pub fn is_pressed(&mut self) -> bool {
+        self.pin.is_low().unwrap_or(false)
+    }
+
+    /// Get the button ID
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Get the button name
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Get button state
+    pub fn state(&self) -> ButtonState {
+        self.tracker.state()
+    }
+
+    /// Sample the pin and return the new state if it changed
+    pub fn poll(&mut self) -> Option<ButtonState> {
+        let pressed = self.is_pressed();
+        self.tracker.update(pressed)
+    }
+}
+
+/// Get the active button index (first one that's pressed)
+pub fn active_button(states: &[ButtonState]) -> Option<usize> {
+    states.iter().position(|&s| s == ButtonState::Pressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+
+    struct FakePin(bool);
+
+    impl embedded_hal::digital::ErrorType for FakePin {
+        type Error = Infallible;
+    }
+
+    impl InputPin for FakePin {
+        fn is_high(&mut self) -> Result<bool, Infallible> {
+            Ok(self.0)
+        }
+
+        fn is_low(&mut self) -> Result<bool, Infallible> {
+            Ok(!self.0)
+        }
+    }
+
+    #[test]
+    fn press_release_settles_to_idle() {
+        let mut tracker = ButtonTracker::new();
+        assert_eq!(tracker.update(false), None);
+        assert_eq!(tracker.update(true), Some(ButtonState::Pressed));
+        assert_eq!(tracker.update(true), None);
+        assert_eq!(tracker.update(false), Some(ButtonState::Released));
+        assert_eq!(tracker.update(false), Some(ButtonState::Idle));
+        assert_eq!(tracker.update(false), None);
+    }
+
+    #[test]
+    fn button_is_active_low() {
+        let mut button = Button::new(FakePin(true), "Button 1", 0);
+        assert_eq!(button.poll(), None);
+        button.pin.0 = false;
+        assert_eq!(button.poll(), Some(ButtonState::Pressed));
+        assert_eq!(button.state(), ButtonState::Pressed);
+    }
+
+    #[test]
+    fn first_pressed_button_is_active() {
+        let states = [
+            ButtonState::Idle,
+            ButtonState::Released,
+            ButtonState::Pressed,
+            ButtonState::Pressed,
+        ];
+        assert_eq!(active_button(&states), Some(2));
+        assert_eq!(active_button(&[ButtonState::Idle; 6]), None);
+    }
+}
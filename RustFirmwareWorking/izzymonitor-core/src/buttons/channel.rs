@@ -0,0 +1,197 @@
+//! Button event channels
+//! Delivers every button event, in order, to every subscribed task
+//!
+//! The key tasks push raw key events into a [`KeyEventQueue`], the chord task
+//! turns them into [`ButtonEvent`]s and publishes those on a [`ButtonChannel`].
+//! Publishers wait for room instead of overwriting, so a subscriber that falls
+//! behind slows the key tasks down rather than silently losing presses.
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::pubsub::{PubSubChannel, Publisher, Subscriber, WaitResult};
+use embassy_sync::signal::Signal;
+
+use super::chord::ButtonEvent;
+use super::debounce::{KeyEvent, KeyEventKind};
+use super::repeat::RepeatCurve;
+use super::ButtonState;
+use crate::KEY_COUNT;
+
+/// Events buffered per channel before publishers have to wait
+pub const EVENT_CAPACITY: usize = 16;
+/// Tasks that may listen to button events at the same time
+pub const MAX_SUBSCRIBERS: usize = 4;
+/// Only the chord task publishes
+pub const MAX_PUBLISHERS: usize = 1;
+
+/// Raw key events from the key tasks to the chord task
+pub type KeyEventQueue = Channel<CriticalSectionRawMutex, KeyEvent, EVENT_CAPACITY>;
+
+/// Hands a new repeat curve to a key task
+pub type RepeatSignal = Signal<CriticalSectionRawMutex, RepeatCurve>;
+
+/// Channel carrying button events from the chord task to their consumers
+pub type ButtonChannel = PubSubChannel<
+    CriticalSectionRawMutex,
+    ButtonEvent,
+    EVENT_CAPACITY,
+    MAX_SUBSCRIBERS,
+    MAX_PUBLISHERS,
+>;
+
+/// Receiving end of a [`ButtonChannel`]
+pub type ButtonSubscriber<'a> = Subscriber<
+    'a,
+    CriticalSectionRawMutex,
+    ButtonEvent,
+    EVENT_CAPACITY,
+    MAX_SUBSCRIBERS,
+    MAX_PUBLISHERS,
+>;
+
+/// Sending end of a [`ButtonChannel`]
+pub type ButtonPublisher<'a> = Publisher<
+    'a,
+    CriticalSectionRawMutex,
+    ButtonEvent,
+    EVENT_CAPACITY,
+    MAX_SUBSCRIBERS,
+    MAX_PUBLISHERS,
+>;
+
+/// Wait for the next button event
+pub async fn next_event(subscriber: &mut ButtonSubscriber<'_>) -> ButtonEvent {
+    loop {
+        // Publishers wait for room, so lagging only happens if someone
+        // used an immediate publisher; skip ahead in that case
+        if let WaitResult::Message(event) = subscriber.next_message().await {
+            return event;
+        }
+    }
+}
+
+/// Wait for any button to be pressed and return its ID
+pub async fn wait_for_button_press(subscriber: &mut ButtonSubscriber<'_>) -> usize {
+    loop {
+        if let ButtonEvent::Key(event) = next_event(subscriber).await {
+            if event.kind == KeyEventKind::Press {
+                return event.key;
+            }
+        }
+    }
+}
+
+/// Press/release state of every key, rebuilt from the event stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ButtonStates([ButtonState; KEY_COUNT]);
+
+impl ButtonStates {
+    /// All keys idle
+    pub const fn new() -> Self {
+        Self([ButtonState::Idle; KEY_COUNT])
+    }
+
+    /// Update from one event, returning whether any state changed
+    pub fn apply(&mut self, event: &ButtonEvent) -> bool {
+        let ButtonEvent::Key(event) = event else {
+            return false;
+        };
+        let state = match event.kind {
+            KeyEventKind::Press => ButtonState::Pressed,
+            KeyEventKind::Release => ButtonState::Released,
+            _ => return false,
+        };
+        match self.0.get_mut(event.key) {
+            Some(slot) if *slot != state => {
+                *slot = state;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Get the state of every key
+    pub fn states(&self) -> &[ButtonState; KEY_COUNT] {
+        &self.0
+    }
+}
+
+impl Default for ButtonStates {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_futures::block_on;
+    use embassy_time::Instant;
+
+    fn event(key: usize, kind: KeyEventKind, t: u64) -> ButtonEvent {
+        ButtonEvent::Key(KeyEvent {
+            key,
+            kind,
+            at: Instant::from_millis(t),
+        })
+    }
+
+    fn kind(event: ButtonEvent) -> KeyEventKind {
+        match event {
+            ButtonEvent::Key(event) => event.kind,
+            ButtonEvent::Chord(_) => panic!("unexpected chord"),
+        }
+    }
+
+    #[test]
+    fn every_subscriber_sees_every_event_in_order() {
+        let channel = ButtonChannel::new();
+        let mut display = channel.subscriber().unwrap();
+        let mut leds = channel.subscriber().unwrap();
+        let publisher = channel.publisher().unwrap();
+
+        // A press and release shorter than any polling period
+        publisher.publish_immediate(event(3, KeyEventKind::Press, 0));
+        publisher.publish_immediate(event(3, KeyEventKind::Release, 5));
+        publisher.publish_immediate(event(3, KeyEventKind::Click, 5));
+
+        for subscriber in [&mut display, &mut leds] {
+            let kinds = [
+                kind(block_on(next_event(subscriber))),
+                kind(block_on(next_event(subscriber))),
+                kind(block_on(next_event(subscriber))),
+            ];
+            assert_eq!(
+                kinds,
+                [
+                    KeyEventKind::Press,
+                    KeyEventKind::Release,
+                    KeyEventKind::Click
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn wait_for_press_skips_other_events() {
+        let channel = ButtonChannel::new();
+        let mut subscriber = channel.subscriber().unwrap();
+        let publisher = channel.publisher().unwrap();
+
+        publisher.publish_immediate(event(0, KeyEventKind::Release, 0));
+        publisher.publish_immediate(event(1, KeyEventKind::Repeat, 1));
+        publisher.publish_immediate(event(4, KeyEventKind::Press, 2));
+
+        assert_eq!(block_on(wait_for_button_press(&mut subscriber)), 4);
+    }
+
+    #[test]
+    fn states_follow_press_and_release() {
+        let mut states = ButtonStates::new();
+        assert!(states.apply(&event(2, KeyEventKind::Press, 0)));
+        assert!(!states.apply(&event(2, KeyEventKind::LongPress, 800)));
+        assert_eq!(states.states()[2], ButtonState::Pressed);
+        assert!(states.apply(&event(2, KeyEventKind::Release, 900)));
+        assert_eq!(states.states()[2], ButtonState::Released);
+    }
+}
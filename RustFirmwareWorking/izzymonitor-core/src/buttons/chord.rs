@@ -0,0 +1,595 @@
+//! Multi-key chords
+//! Recognises keys held together and keeps them from firing single-key actions
+//!
+//! A press of a key that belongs to a registered chord is held back for the
+//! chord window. If the rest of the chord follows, the single-key events of
+//! all its keys are swallowed until they are released, and the chord fires
+//! once all its keys have been held for its hold time. Otherwise the
+//! held-back events are let through unchanged and in order. A key whose press
+//! went out before the chord formed loses the rest of its events, its release
+//! and click included.
+
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+
+use super::debounce::{KeyEvent, KeyEventKind};
+use crate::KEY_COUNT;
+
+/// A set of keys as a bitmask, bit `n` is key `n`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KeySet(u8);
+
+impl KeySet {
+    /// No keys
+    pub const EMPTY: KeySet = KeySet(0);
+    /// Every key
+    pub const ALL: KeySet = KeySet((1 << KEY_COUNT) - 1);
+
+    /// Build a set from key indices
+    pub const fn of(keys: &[usize]) -> Self {
+        let mut mask = 0;
+        let mut i = 0;
+        while i < keys.len() {
+            mask |= 1 << keys[i];
+            i += 1;
+        }
+        Self(mask)
+    }
+
+    /// Get the raw bitmask
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Build a set from a raw bitmask, `None` if it names a key that doesn't exist
+    pub const fn from_bits(bits: u8) -> Option<Self> {
+        if bits & !Self::ALL.0 == 0 {
+            Some(Self(bits))
+        } else {
+            None
+        }
+    }
+
+    /// Whether the key is in the set
+    pub fn contains(self, key: usize) -> bool {
+        key < KEY_COUNT && self.0 & (1 << key) != 0
+    }
+
+    /// Add a key
+    pub fn insert(&mut self, key: usize) {
+        if key < KEY_COUNT {
+            self.0 |= 1 << key;
+        }
+    }
+
+    /// Remove a key
+    pub fn remove(&mut self, key: usize) {
+        if key < KEY_COUNT {
+            self.0 &= !(1 << key);
+        }
+    }
+
+    /// Whether no key is in the set
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Number of keys in the set
+    pub fn len(self) -> u32 {
+        self.0.count_ones()
+    }
+
+    /// Whether every key of this set is also in `other`
+    pub fn is_subset(self, other: KeySet) -> bool {
+        self.0 & !other.0 == 0
+    }
+
+    /// Whether the sets share a key
+    pub fn intersects(self, other: KeySet) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+/// Which chord fired
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChordId {
+    /// Keys 1 and 6, held long, erases the settings and restarts
+    FactoryReset,
+    /// Keys 5 and 6, steps the brightness up and round to Auto
+    Brightness,
+    /// Bound by an app, numbered by the app
+    App(u8),
+}
+
+/// Who a chord belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChordOwner {
+    System,
+    App,
+}
+
+impl ChordId {
+    /// Get the owner of this chord
+    pub fn owner(self) -> ChordOwner {
+        match self {
+            ChordId::App(_) => ChordOwner::App,
+            _ => ChordOwner::System,
+        }
+    }
+}
+
+/// A chord and how long it must be held
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChordBinding {
+    pub id: ChordId,
+    pub keys: KeySet,
+    pub hold: Duration,
+}
+
+/// Reserved system chords
+pub const SYSTEM_CHORDS: [ChordBinding; 2] = [
+    ChordBinding {
+        id: ChordId::FactoryReset,
+        keys: KeySet::of(&[0, 5]),
+        hold: Duration::from_secs(5),
+    },
+    ChordBinding {
+        id: ChordId::Brightness,
+        keys: KeySet::of(&[4, 5]),
+        hold: Duration::from_millis(300),
+    },
+];
+
+/// Most chords the registry can hold, system ones included
+pub const MAX_CHORDS: usize = 8;
+
+/// The chords the button subsystem recognises
+#[derive(Debug, Clone)]
+pub struct ChordRegistry {
+    bindings: Vec<ChordBinding, MAX_CHORDS>,
+    window: Duration,
+}
+
+impl ChordRegistry {
+    /// A registry with only the reserved system chords
+    pub fn new() -> Self {
+        let mut bindings = Vec::new();
+        for binding in SYSTEM_CHORDS {
+            let _ = bindings.push(binding);
+        }
+        Self {
+            bindings,
+            window: Duration::from_millis(60),
+        }
+    }
+
+    /// How long a press waits for the rest of a chord
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Set how long a press waits for the rest of a chord
+    pub fn set_window(&mut self, window: Duration) {
+        self.window = window;
+    }
+
+    /// All registered chords
+    pub fn bindings(&self) -> &[ChordBinding] {
+        &self.bindings
+    }
+
+    /// Whether these exact keys are taken by a system chord
+    pub fn is_reserved(&self, keys: KeySet) -> bool {
+        self.bindings
+            .iter()
+            .any(|b| b.id.owner() == ChordOwner::System && b.keys == keys)
+    }
+
+    /// Bind an app chord
+    pub fn bind(&mut self, binding: ChordBinding) -> Result<(), &'static str> {
+        if binding.id.owner() != ChordOwner::App {
+            return Err("System chords cannot be rebound");
+        }
+        if binding.keys.len() < 2 {
+            return Err("A chord needs at least two keys");
+        }
+        if self.is_reserved(binding.keys) {
+            return Err("Chord is reserved for the system");
+        }
+        if self
+            .bindings
+            .iter()
+            .any(|b| b.keys == binding.keys || b.id == binding.id)
+        {
+            return Err("Chord is already bound");
+        }
+        self.bindings
+            .push(binding)
+            .map_err(|_| "Too many chords bound")
+    }
+
+    /// Remove an app chord
+    pub fn unbind(&mut self, id: ChordId) -> Result<(), &'static str> {
+        if id.owner() != ChordOwner::App {
+            return Err("System chords cannot be unbound");
+        }
+        let index = self
+            .bindings
+            .iter()
+            .position(|b| b.id == id)
+            .ok_or("Chord is not bound")?;
+        self.bindings.swap_remove(index);
+        Ok(())
+    }
+
+    fn exact(&self, keys: KeySet) -> Option<&ChordBinding> {
+        self.bindings.iter().find(|b| b.keys == keys)
+    }
+
+    fn could_become_chord(&self, keys: KeySet) -> bool {
+        self.bindings.iter().any(|b| keys.is_subset(b.keys))
+    }
+}
+
+impl Default for ChordRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A chord that has been held for its hold time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChordEvent {
+    pub id: ChordId,
+    pub keys: KeySet,
+    pub at: Instant,
+}
+
+/// Everything the button subsystem reports to its consumers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonEvent {
+    Key(KeyEvent),
+    Chord(ChordEvent),
+}
+
+/// Events produced by one call into the [`ChordDetector`]
+pub type ButtonEvents = Vec<ButtonEvent, 16>;
+
+/// Held-back events waiting to see whether a chord forms
+const MAX_PENDING: usize = 8;
+
+/// Sits between the key debouncers and the event consumers
+#[derive(Debug, Clone)]
+pub struct ChordDetector {
+    registry: ChordRegistry,
+    // Keys physically down according to the events seen
+    held: KeySet,
+    pending: Vec<KeyEvent, MAX_PENDING>,
+    pending_until: Option<Instant>,
+    // Keys of a started chord whose events are dropped until released
+    swallowed: KeySet,
+    // Keys whose click, following a swallowed release, is dropped too
+    drop_clicks: KeySet,
+    active: Option<(ChordBinding, Instant)>,
+}
+
+impl ChordDetector {
+    /// Create a detector for the given chords
+    pub fn new(registry: ChordRegistry) -> Self {
+        Self {
+            registry,
+            held: KeySet::EMPTY,
+            pending: Vec::new(),
+            pending_until: None,
+            swallowed: KeySet::EMPTY,
+            drop_clicks: KeySet::EMPTY,
+            active: None,
+        }
+    }
+
+    /// Get the chord registry
+    pub fn registry(&self) -> &ChordRegistry {
+        &self.registry
+    }
+
+    /// Change the chord registry, e.g. to bind app chords
+    pub fn registry_mut(&mut self) -> &mut ChordRegistry {
+        &mut self.registry
+    }
+
+    /// Earliest time at which [`poll`](Self::poll) has work to do
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let chord = self.active.map(|(binding, since)| since + binding.hold);
+        [self.pending_until, chord].into_iter().flatten().min()
+    }
+
+    /// Feed one key event from a debouncer
+    pub fn event(&mut self, event: KeyEvent) -> ButtonEvents {
+        let mut out = ButtonEvents::new();
+        let key = event.key;
+
+        if event.kind == KeyEventKind::Release {
+            // Letting go of any key of a chord calls it off
+            if self
+                .active
+                .is_some_and(|(binding, _)| binding.keys.contains(key))
+            {
+                self.active = None;
+            }
+        }
+
+        if self.swallowed.contains(key) {
+            if event.kind == KeyEventKind::Release {
+                self.held.remove(key);
+                self.swallowed.remove(key);
+                self.drop_clicks.insert(key);
+            }
+            return out;
+        }
+
+        match event.kind {
+            KeyEventKind::Click | KeyEventKind::DoubleClick if self.drop_clicks.contains(key) => {
+                self.drop_clicks.remove(key);
+                return out;
+            }
+            _ => self.drop_clicks.remove(key),
+        }
+
+        match event.kind {
+            KeyEventKind::Press => {
+                self.held.insert(key);
+
+                if let Some(&binding) = self.registry.exact(self.held) {
+                    // Chord complete, the held-back presses never happened
+                    self.swallowed = binding.keys;
+                    self.pending.retain(|e| !binding.keys.contains(e.key));
+                    self.active = Some((binding, event.at));
+                    self.flush(&mut out);
+                } else if self.registry.could_become_chord(self.held) {
+                    self.hold_back(event, &mut out);
+                    if self.pending_until.is_none() {
+                        self.pending_until = Some(event.at + self.registry.window());
+                    }
+                } else {
+                    self.flush(&mut out);
+                    push(&mut out, ButtonEvent::Key(event));
+                }
+            }
+            kind => {
+                if kind == KeyEventKind::Release {
+                    self.held.remove(key);
+                }
+                if self.pending.is_empty() {
+                    push(&mut out, ButtonEvent::Key(event));
+                } else {
+                    self.hold_back(event, &mut out);
+                    // A released key can't be part of a chord any more
+                    if kind == KeyEventKind::Release {
+                        self.flush(&mut out);
+                    }
+                }
+            }
+        }
+
+        out
+    }
+
+    /// Advance timers to `now`
+    pub fn poll(&mut self, now: Instant) -> ButtonEvents {
+        let mut out = ButtonEvents::new();
+
+        if self.pending_until.is_some_and(|until| now >= until) {
+            self.flush(&mut out);
+        }
+
+        if let Some((binding, since)) = self.active {
+            let fires_at = since + binding.hold;
+            if now >= fires_at {
+                // Fire once, the keys stay swallowed until released
+                self.active = None;
+                push(
+                    &mut out,
+                    ButtonEvent::Chord(ChordEvent {
+                        id: binding.id,
+                        keys: binding.keys,
+                        at: fires_at,
+                    }),
+                );
+            }
+        }
+
+        out
+    }
+
+    fn hold_back(&mut self, event: KeyEvent, out: &mut ButtonEvents) {
+        if self.pending.push(event).is_err() {
+            // Too much going on for a chord, give up waiting
+            self.flush(out);
+            push(out, ButtonEvent::Key(event));
+        }
+    }
+
+    fn flush(&mut self, out: &mut ButtonEvents) {
+        for event in self.pending.iter() {
+            push(out, ButtonEvent::Key(*event));
+        }
+        self.pending.clear();
+        self.pending_until = None;
+    }
+}
+
+fn push(out: &mut ButtonEvents, event: ButtonEvent) {
+    // Sized for a full pending queue plus the event that flushed it
+    let _ = out.push(event);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: usize, kind: KeyEventKind, t: u64) -> KeyEvent {
+        KeyEvent {
+            key,
+            kind,
+            at: Instant::from_millis(t),
+        }
+    }
+
+    fn keys_of(events: &[ButtonEvent]) -> std::vec::Vec<(usize, KeyEventKind)> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                ButtonEvent::Key(k) => Some((k.key, k.kind)),
+                ButtonEvent::Chord(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chord_fires_after_hold_and_swallows_keys() {
+        let mut d = ChordDetector::new(ChordRegistry::new());
+
+        assert!(d.event(key(4, KeyEventKind::Press, 0)).is_empty());
+        assert!(d.event(key(5, KeyEventKind::Press, 20)).is_empty());
+        assert!(d.poll(Instant::from_millis(100)).is_empty());
+        assert_eq!(d.next_deadline(), Some(Instant::from_millis(320)));
+
+        let fired = d.poll(Instant::from_millis(320));
+        assert_eq!(
+            fired.as_slice(),
+            &[ButtonEvent::Chord(ChordEvent {
+                id: ChordId::Brightness,
+                keys: KeySet::of(&[4, 5]),
+                at: Instant::from_millis(320),
+            })]
+        );
+
+        // Neither the repeats nor the releases reach the single-key consumers
+        assert!(d.event(key(4, KeyEventKind::Repeat, 500)).is_empty());
+        assert!(d.event(key(5, KeyEventKind::Release, 600)).is_empty());
+        assert!(d.event(key(4, KeyEventKind::Release, 610)).is_empty());
+        assert!(d.event(key(4, KeyEventKind::Click, 610)).is_empty());
+
+        // Afterwards key 4 works on its own again
+        d.event(key(4, KeyEventKind::Press, 1000));
+        let flushed = d.poll(Instant::from_millis(1060));
+        assert_eq!(keys_of(&flushed), [(4, KeyEventKind::Press)]);
+    }
+
+    #[test]
+    fn early_release_cancels_chord_without_single_keys() {
+        let mut d = ChordDetector::new(ChordRegistry::new());
+        d.event(key(0, KeyEventKind::Press, 0));
+        d.event(key(5, KeyEventKind::Press, 10));
+        assert!(d.event(key(0, KeyEventKind::Release, 1000)).is_empty());
+        assert!(d.event(key(5, KeyEventKind::Release, 1010)).is_empty());
+        assert!(d.poll(Instant::from_millis(6000)).is_empty());
+        assert_eq!(d.next_deadline(), None);
+    }
+
+    #[test]
+    fn releasing_one_key_cancels_the_chord() {
+        let mut d = ChordDetector::new(ChordRegistry::new());
+        d.event(key(0, KeyEventKind::Press, 0));
+        d.event(key(5, KeyEventKind::Press, 10));
+        // Key 5 stays down past the hold time
+        assert!(d.event(key(0, KeyEventKind::Release, 1000)).is_empty());
+        assert_eq!(d.next_deadline(), None);
+        assert!(d.poll(Instant::from_millis(5010)).is_empty());
+        assert!(d.event(key(5, KeyEventKind::Release, 6000)).is_empty());
+    }
+
+    #[test]
+    fn late_second_key_swallows_the_rest_of_the_first() {
+        let mut d = ChordDetector::new(ChordRegistry::new());
+        d.event(key(4, KeyEventKind::Press, 0));
+        let out = d.poll(Instant::from_millis(60));
+        assert_eq!(keys_of(&out), [(4, KeyEventKind::Press)]);
+
+        // The chord still forms, and from then on neither key acts alone
+        assert!(d.event(key(5, KeyEventKind::Press, 100)).is_empty());
+        let fired = d.poll(Instant::from_millis(400));
+        assert!(matches!(fired.as_slice(), [ButtonEvent::Chord(_)]));
+        assert!(d.event(key(4, KeyEventKind::Repeat, 450)).is_empty());
+        assert!(d.event(key(5, KeyEventKind::Release, 500)).is_empty());
+        assert!(d.event(key(4, KeyEventKind::Release, 510)).is_empty());
+        assert!(d.event(key(4, KeyEventKind::Click, 510)).is_empty());
+
+        // Afterwards key 4 works on its own again
+        d.event(key(4, KeyEventKind::Press, 1000));
+        let out = d.poll(Instant::from_millis(1060));
+        assert_eq!(keys_of(&out), [(4, KeyEventKind::Press)]);
+    }
+
+    #[test]
+    fn lone_press_is_delayed_by_window_only() {
+        let mut d = ChordDetector::new(ChordRegistry::new());
+        assert!(d.event(key(4, KeyEventKind::Press, 0)).is_empty());
+        let out = d.poll(Instant::from_millis(60));
+        assert_eq!(keys_of(&out), [(4, KeyEventKind::Press)]);
+        if let ButtonEvent::Key(k) = out[0] {
+            assert_eq!(k.at, Instant::from_millis(0));
+        }
+    }
+
+    #[test]
+    fn quick_click_is_flushed_in_order() {
+        let mut d = ChordDetector::new(ChordRegistry::new());
+        d.event(key(4, KeyEventKind::Press, 0));
+        d.event(key(4, KeyEventKind::Release, 30));
+        let out = d.event(key(4, KeyEventKind::Click, 30));
+        assert_eq!(keys_of(&out), [(4, KeyEventKind::Click)]);
+        let mut d = ChordDetector::new(ChordRegistry::new());
+        d.event(key(4, KeyEventKind::Press, 0));
+        let out = d.event(key(4, KeyEventKind::Release, 30));
+        assert_eq!(
+            keys_of(&out),
+            [(4, KeyEventKind::Press), (4, KeyEventKind::Release)]
+        );
+    }
+
+    #[test]
+    fn keys_outside_chords_pass_straight_through() {
+        let mut d = ChordDetector::new(ChordRegistry::new());
+        let out = d.event(key(3, KeyEventKind::Press, 0));
+        assert_eq!(keys_of(&out), [(3, KeyEventKind::Press)]);
+    }
+
+    #[test]
+    fn apps_cannot_take_system_chords() {
+        let mut registry = ChordRegistry::new();
+        let hold = Duration::from_millis(200);
+
+        assert!(registry
+            .bind(ChordBinding {
+                id: ChordId::App(1),
+                keys: KeySet::of(&[4, 5]),
+                hold,
+            })
+            .is_err());
+        assert!(registry
+            .bind(ChordBinding {
+                id: ChordId::Brightness,
+                keys: KeySet::of(&[0, 3]),
+                hold,
+            })
+            .is_err());
+        assert!(registry
+            .bind(ChordBinding {
+                id: ChordId::App(1),
+                keys: KeySet::of(&[3]),
+                hold,
+            })
+            .is_err());
+
+        let app = ChordBinding {
+            id: ChordId::App(1),
+            keys: KeySet::of(&[0, 3]),
+            hold,
+        };
+        assert!(registry.bind(app).is_ok());
+        assert!(registry.bind(app).is_err());
+        assert!(registry.unbind(ChordId::FactoryReset).is_err());
+        assert!(registry.unbind(ChordId::App(1)).is_ok());
+    }
+}
@@ -0,0 +1,517 @@
+//! Debounced key events
+//! Turns raw edges plus a settle window into typed, timestamped key events
+//!
+//! The driver feeds every edge interrupt to [`Debouncer::edge`] and calls
+//! [`Debouncer::poll`] whenever an edge arrived or [`Debouncer::next_deadline`]
+//! passed. All timing lives here, so it can be tested from synthetic timelines.
+
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+
+use super::repeat::RepeatCurve;
+use super::stats::KeyStats;
+
+/// What happened to a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEventKind {
+    /// The key went down
+    Press,
+    /// The key came back up
+    Release,
+    /// A short press was released
+    Click,
+    /// A second click followed the first within the double-click window
+    DoubleClick,
+    /// The key has been held for the long-press time
+    LongPress,
+    /// The key is still held, fired periodically after the repeat delay
+    Repeat,
+    /// The key has been held past the stuck threshold and is ignored until
+    /// it comes back up; a `Release` is sent just before this
+    Stuck,
+}
+
+/// A key event with the time it happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: usize,
+    pub kind: KeyEventKind,
+    pub at: Instant,
+}
+
+/// Timing parameters for a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DebounceConfig {
+    /// The level must be stable this long before it counts
+    pub debounce: Duration,
+    /// Holding at least this long is a long press instead of a click
+    pub long_press: Duration,
+    /// Longest gap between two clicks that still makes a double click
+    pub double_click: Duration,
+    /// Hold time before the first repeat, `None` disables repeating
+    pub repeat_delay: Option<Duration>,
+    /// How the time between repeats shrinks while the key stays held
+    pub repeat: RepeatCurve,
+    /// Holding longer than this marks the key as stuck, `None` never does
+    pub stuck_after: Option<Duration>,
+}
+
+impl Default for DebounceConfig {
+    fn default() -> Self {
+        Self {
+            debounce: Duration::from_millis(10),
+            long_press: Duration::from_millis(800),
+            double_click: Duration::from_millis(300),
+            repeat_delay: Some(Duration::from_millis(500)),
+            repeat: RepeatCurve::default(),
+            stuck_after: Some(Duration::from_secs(30)),
+        }
+    }
+}
+
+/// Events produced by one call to [`Debouncer::poll`]
+pub type KeyEvents = Vec<KeyEvent, 4>;
+
+/// Debounce and gesture state machine for one key
+#[derive(Debug, Clone)]
+pub struct Debouncer {
+    key: usize,
+    config: DebounceConfig,
+    // Last level seen on an edge and the debounced level
+    raw: bool,
+    stable: bool,
+    // Pending settle deadline and the first edge of the current bounce burst
+    settle_at: Option<Instant>,
+    edge_at: Instant,
+    burst_edges: u16,
+    press_bounces: u16,
+    pressed_at: Instant,
+    long_press_fired: bool,
+    held_action: bool,
+    next_repeat: Option<Instant>,
+    repeat_interval: Duration,
+    last_click: Option<Instant>,
+    stuck: bool,
+    stats: KeyStats,
+}
+
+impl Debouncer {
+    /// Create a debouncer for a released key
+    pub fn new(key: usize, config: DebounceConfig) -> Self {
+        Self {
+            key,
+            config,
+            raw: false,
+            stable: false,
+            settle_at: None,
+            edge_at: Instant::from_ticks(0),
+            burst_edges: 0,
+            press_bounces: 0,
+            pressed_at: Instant::from_ticks(0),
+            long_press_fired: false,
+            held_action: false,
+            next_repeat: None,
+            repeat_interval: Duration::from_ticks(0),
+            last_click: None,
+            stuck: false,
+            stats: KeyStats::new(),
+        }
+    }
+
+    /// Get the key index
+    pub fn key(&self) -> usize {
+        self.key
+    }
+
+    /// Get the timing parameters
+    pub fn config(&self) -> &DebounceConfig {
+        &self.config
+    }
+
+    /// Replace the timing parameters
+    pub fn set_config(&mut self, config: DebounceConfig) {
+        self.config = config;
+    }
+
+    /// Replace the repeat curve, taking effect from the next press
+    pub fn set_repeat(&mut self, repeat: RepeatCurve) {
+        self.config.repeat = repeat;
+    }
+
+    /// Debounced level of the key
+    pub fn is_pressed(&self) -> bool {
+        self.stable
+    }
+
+    /// Whether the key is held past the stuck threshold and being ignored
+    pub fn is_stuck(&self) -> bool {
+        self.stuck
+    }
+
+    /// Press, bounce and hold statistics since power-up
+    pub fn stats(&self) -> &KeyStats {
+        &self.stats
+    }
+
+    /// Record the level read right after an edge interrupt
+    pub fn edge(&mut self, pressed: bool, now: Instant) {
+        if self.settle_at.is_none() {
+            self.edge_at = now;
+            self.burst_edges = 0;
+        }
+        self.burst_edges = self.burst_edges.saturating_add(1);
+        self.raw = pressed;
+        self.settle_at = Some(now + self.config.debounce);
+    }
+
+    /// Earliest time at which [`poll`](Self::poll) has work to do
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let long_press = (self.stable && !self.long_press_fired)
+            .then(|| self.pressed_at + self.config.long_press);
+        let stuck = self
+            .config
+            .stuck_after
+            .filter(|_| self.stable && !self.stuck)
+            .map(|after| self.pressed_at + after);
+
+        [self.settle_at, long_press, self.next_repeat, stuck]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+
+    /// Advance the state machine to `now` and return the events that fired
+    pub fn poll(&mut self, now: Instant) -> KeyEvents {
+        let mut events = KeyEvents::new();
+
+        if let Some(settle_at) = self.settle_at {
+            if now >= settle_at {
+                self.settle_at = None;
+                if self.raw != self.stable {
+                    // Every edge past the first of a burst is a bounce
+                    self.press_bounces = self
+                        .press_bounces
+                        .saturating_add(self.burst_edges.saturating_sub(1));
+                    self.stable = self.raw;
+                    if self.stable {
+                        self.on_press(&mut events);
+                    } else {
+                        self.on_release(&mut events);
+                    }
+                }
+            }
+        }
+
+        if self.stable && !self.stuck {
+            if let Some(after) = self.config.stuck_after {
+                let stuck_at = self.pressed_at + after;
+                if now >= stuck_at {
+                    self.on_stuck(&mut events, stuck_at);
+                    return events;
+                }
+            }
+
+            let long_press_at = self.pressed_at + self.config.long_press;
+            if !self.long_press_fired && now >= long_press_at {
+                self.long_press_fired = true;
+                self.held_action = true;
+                self.push(&mut events, KeyEventKind::LongPress, long_press_at);
+            }
+
+            if let Some(repeat_at) = self.next_repeat {
+                if now >= repeat_at {
+                    self.held_action = true;
+                    self.push(&mut events, KeyEventKind::Repeat, repeat_at);
+
+                    // Don't try to catch up on repeats we were too late for
+                    let interval = self.repeat_interval;
+                    self.repeat_interval = self.config.repeat.next(interval);
+                    let next = repeat_at + interval;
+                    self.next_repeat = Some(if next > now { next } else { now + interval });
+                }
+            }
+        }
+
+        events
+    }
+
+    fn on_press(&mut self, events: &mut KeyEvents) {
+        self.stats.presses = self.stats.presses.saturating_add(1);
+        self.pressed_at = self.edge_at;
+        self.long_press_fired = false;
+        self.held_action = false;
+        self.repeat_interval = self.config.repeat.start();
+        self.next_repeat = self
+            .config
+            .repeat_delay
+            .map(|delay| self.pressed_at + delay);
+        self.push(events, KeyEventKind::Press, self.pressed_at);
+    }
+
+    fn on_release(&mut self, events: &mut KeyEvents) {
+        let released_at = self.edge_at;
+        self.next_repeat = None;
+        self.stats
+            .record_press(self.press_bounces, released_at - self.pressed_at);
+        self.press_bounces = 0;
+
+        // The release was already reported when the key got stuck
+        if self.stuck {
+            self.stuck = false;
+            return;
+        }
+        self.push(events, KeyEventKind::Release, released_at);
+
+        if self.held_action {
+            self.last_click = None;
+            return;
+        }
+
+        let double = self
+            .last_click
+            .is_some_and(|last| released_at - last <= self.config.double_click);
+        if double {
+            self.last_click = None;
+            self.push(events, KeyEventKind::DoubleClick, released_at);
+        } else {
+            self.last_click = Some(released_at);
+            self.push(events, KeyEventKind::Click, released_at);
+        }
+    }
+
+    fn on_stuck(&mut self, events: &mut KeyEvents, at: Instant) {
+        self.stuck = true;
+        self.stats.stuck = self.stats.stuck.saturating_add(1);
+        self.long_press_fired = true;
+        self.next_repeat = None;
+        self.last_click = None;
+
+        // Let everyone downstream forget the key is held
+        self.push(events, KeyEventKind::Release, at);
+        self.push(events, KeyEventKind::Stuck, at);
+    }
+
+    fn push(&self, events: &mut KeyEvents, kind: KeyEventKind, at: Instant) {
+        // Capacity covers the worst case of one poll, see `poll`
+        let _ = events.push(KeyEvent {
+            key: self.key,
+            kind,
+            at,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(t: u64) -> Instant {
+        Instant::from_millis(t)
+    }
+
+    /// Replay `(time, level)` edges, polling at every edge and deadline
+    fn replay(
+        debouncer: &mut Debouncer,
+        edges: &[(u64, bool)],
+        until: u64,
+    ) -> Vec<(KeyEventKind, u64), 64> {
+        let mut out = Vec::new();
+        let mut edges = edges.iter().peekable();
+        loop {
+            let next_edge = edges.peek().map(|&&(t, _)| ms(t));
+            let next = match (next_edge, debouncer.next_deadline()) {
+                (Some(e), Some(d)) => e.min(d),
+                (Some(e), None) => e,
+                (None, Some(d)) => d,
+                (None, None) => break,
+            };
+            if next > ms(until) {
+                break;
+            }
+            if Some(next) == next_edge {
+                let &(_, level) = edges.next().unwrap();
+                debouncer.edge(level, next);
+            }
+            for event in debouncer.poll(next) {
+                out.push((event.kind, event.at.as_millis())).unwrap();
+            }
+        }
+        out
+    }
+
+    fn no_repeat() -> DebounceConfig {
+        DebounceConfig {
+            repeat_delay: None,
+            ..DebounceConfig::default()
+        }
+    }
+
+    #[test]
+    fn bouncy_press_is_one_click() {
+        let mut d = Debouncer::new(2, no_repeat());
+        let edges = [
+            (100, true),
+            (102, false),
+            (104, true),
+            (200, false),
+            (203, true),
+            (205, false),
+        ];
+        let events = replay(&mut d, &edges, 1000);
+        assert_eq!(
+            events.as_slice(),
+            &[
+                (KeyEventKind::Press, 100),
+                (KeyEventKind::Release, 200),
+                (KeyEventKind::Click, 200)
+            ]
+        );
+        assert!(!d.is_pressed());
+    }
+
+    #[test]
+    fn glitch_shorter_than_debounce_is_ignored() {
+        let mut d = Debouncer::new(0, no_repeat());
+        let events = replay(&mut d, &[(50, true), (53, false)], 500);
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn two_quick_clicks_make_double_click() {
+        let mut d = Debouncer::new(0, no_repeat());
+        let edges = [
+            (0, true),
+            (80, false),
+            (200, true),
+            (260, false),
+            (400, true),
+            (450, false),
+        ];
+        let kinds: Vec<KeyEventKind, 16> = replay(&mut d, &edges, 2000)
+            .iter()
+            .map(|&(k, _)| k)
+            .filter(|&k| k != KeyEventKind::Press && k != KeyEventKind::Release)
+            .collect();
+        assert_eq!(
+            kinds.as_slice(),
+            &[
+                KeyEventKind::Click,
+                KeyEventKind::DoubleClick,
+                KeyEventKind::Click
+            ]
+        );
+    }
+
+    #[test]
+    fn hold_fires_long_press_and_repeats_without_click() {
+        let mut d = Debouncer::new(5, DebounceConfig::default());
+        let events = replay(&mut d, &[(0, true), (1000, false)], 2000);
+        assert_eq!(
+            events.as_slice(),
+            &[
+                (KeyEventKind::Press, 0),
+                (KeyEventKind::Repeat, 500),
+                (KeyEventKind::Repeat, 600),
+                (KeyEventKind::Repeat, 700),
+                (KeyEventKind::LongPress, 800),
+                (KeyEventKind::Repeat, 800),
+                (KeyEventKind::Repeat, 900),
+                (KeyEventKind::Repeat, 1000),
+                (KeyEventKind::Release, 1000),
+            ]
+        );
+    }
+
+    #[test]
+    fn late_poll_does_not_burst_repeats() {
+        let mut d = Debouncer::new(0, DebounceConfig::default());
+        d.edge(true, ms(0));
+        d.poll(ms(10));
+        let events = d.poll(ms(2000));
+        let repeats = events
+            .iter()
+            .filter(|e| e.kind == KeyEventKind::Repeat)
+            .count();
+        assert_eq!(repeats, 1);
+        assert_eq!(d.next_deadline(), Some(ms(2100)));
+    }
+
+    #[test]
+    fn accelerating_repeat_follows_the_curve() {
+        let config = DebounceConfig {
+            long_press: Duration::from_secs(10),
+            repeat: RepeatCurve::Linear {
+                start: Duration::from_millis(200),
+                min: Duration::from_millis(50),
+                step: Duration::from_millis(50),
+            },
+            ..DebounceConfig::default()
+        };
+        let mut d = Debouncer::new(4, config);
+        let events = replay(&mut d, &[(0, true), (1300, false)], 2000);
+        let repeats: Vec<u64, 16> = events
+            .iter()
+            .filter(|&&(k, _)| k == KeyEventKind::Repeat)
+            .map(|&(_, t)| t)
+            .collect();
+        assert_eq!(
+            repeats.as_slice(),
+            &[500, 700, 850, 950, 1000, 1050, 1100, 1150, 1200, 1250, 1300]
+        );
+
+        // The next press starts slow again
+        let events = replay(&mut d, &[(3000, true), (3800, false)], 4000);
+        let repeats = events
+            .iter()
+            .filter(|&&(k, _)| k == KeyEventKind::Repeat)
+            .count();
+        assert_eq!(repeats, 2);
+    }
+
+    #[test]
+    fn bounces_and_hold_are_counted() {
+        let mut d = Debouncer::new(1, no_repeat());
+        let edges = [
+            (0, true),
+            (2, false),
+            (4, true),
+            (300, false),
+            (302, true),
+            (304, false),
+            (1000, true),
+            (1100, false),
+        ];
+        replay(&mut d, &edges, 2000);
+
+        let stats = d.stats();
+        assert_eq!(stats.presses, 2);
+        assert_eq!(stats.bounces, 4);
+        assert_eq!(stats.max_bounces, 4);
+        assert_eq!(stats.longest_hold, Duration::from_millis(300));
+        assert_eq!(stats.stuck, 0);
+    }
+
+    #[test]
+    fn stuck_key_is_released_and_ignored() {
+        let config = DebounceConfig {
+            stuck_after: Some(Duration::from_secs(5)),
+            ..DebounceConfig::default()
+        };
+        let mut d = Debouncer::new(0, config);
+        let events = replay(&mut d, &[(0, true)], 10_000);
+        let tail = &events[events.len() - 2..];
+        assert_eq!(
+            tail,
+            &[(KeyEventKind::Release, 5000), (KeyEventKind::Stuck, 5000)]
+        );
+        assert!(d.is_stuck());
+        assert_eq!(d.next_deadline(), None);
+
+        // Coming back up is silent and the key works again afterwards
+        let events = replay(&mut d, &[(12_000, false), (13_000, true)], 13_100);
+        assert_eq!(events.as_slice(), &[(KeyEventKind::Press, 13_000)]);
+        assert!(!d.is_stuck());
+        assert_eq!(d.stats().stuck, 1);
+        assert_eq!(d.stats().longest_hold, Duration::from_secs(12));
+    }
+}
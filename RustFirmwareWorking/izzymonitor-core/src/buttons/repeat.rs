@@ -0,0 +1,118 @@
+//! Auto-repeat curves
+//! How the time between repeats of a held key shrinks the longer it is held
+
+use embassy_time::Duration;
+
+/// Shape of the auto-repeat acceleration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepeatCurve {
+    /// Same interval for every repeat
+    Fixed(Duration),
+    /// Each interval is `step` shorter than the last, down to `min`
+    Linear {
+        start: Duration,
+        min: Duration,
+        step: Duration,
+    },
+    /// Each interval is `percent` percent shorter than the last, down to `min`
+    Exponential {
+        start: Duration,
+        min: Duration,
+        percent: u8,
+    },
+}
+
+impl RepeatCurve {
+    /// Interval between the first and second repeat
+    pub fn start(&self) -> Duration {
+        match *self {
+            RepeatCurve::Fixed(interval) => interval,
+            RepeatCurve::Linear { start, min, .. }
+            | RepeatCurve::Exponential { start, min, .. } => start.max(min),
+        }
+    }
+
+    /// Interval that follows `current`
+    pub fn next(&self, current: Duration) -> Duration {
+        match *self {
+            RepeatCurve::Fixed(interval) => interval,
+            RepeatCurve::Linear { min, step, .. } => {
+                Duration::from_ticks(current.as_ticks().saturating_sub(step.as_ticks())).max(min)
+            }
+            RepeatCurve::Exponential { min, percent, .. } => {
+                let keep = 100 - u64::from(percent.min(100));
+                Duration::from_ticks(current.as_ticks() * keep / 100).max(min)
+            }
+        }
+    }
+
+    /// Interval after the `n`th repeat, counting from zero
+    pub fn interval(&self, n: u32) -> Duration {
+        let mut interval = self.start();
+        for _ in 0..n {
+            let next = self.next(interval);
+            if next == interval {
+                break;
+            }
+            interval = next;
+        }
+        interval
+    }
+}
+
+impl Default for RepeatCurve {
+    fn default() -> Self {
+        RepeatCurve::Fixed(Duration::from_millis(100))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(t: u64) -> Duration {
+        Duration::from_millis(t)
+    }
+
+    #[test]
+    fn fixed_never_changes() {
+        let curve = RepeatCurve::Fixed(ms(100));
+        assert_eq!(curve.interval(0), ms(100));
+        assert_eq!(curve.interval(50), ms(100));
+    }
+
+    #[test]
+    fn linear_steps_down_to_the_floor() {
+        let curve = RepeatCurve::Linear {
+            start: ms(200),
+            min: ms(40),
+            step: ms(50),
+        };
+        let intervals: [Duration; 5] = core::array::from_fn(|n| curve.interval(n as u32));
+        assert_eq!(intervals, [ms(200), ms(150), ms(100), ms(50), ms(40)]);
+        assert_eq!(curve.interval(1000), ms(40));
+    }
+
+    #[test]
+    fn exponential_shrinks_by_percent_down_to_the_floor() {
+        let curve = RepeatCurve::Exponential {
+            start: ms(200),
+            min: ms(30),
+            percent: 50,
+        };
+        assert_eq!(curve.interval(1), ms(100));
+        assert_eq!(curve.interval(2), ms(50));
+        assert_eq!(curve.interval(3), ms(30));
+        assert_eq!(curve.interval(u32::MAX), ms(30));
+    }
+
+    #[test]
+    fn start_respects_the_floor() {
+        let curve = RepeatCurve::Linear {
+            start: ms(10),
+            min: ms(40),
+            step: ms(5),
+        };
+        assert_eq!(curve.start(), ms(40));
+    }
+}
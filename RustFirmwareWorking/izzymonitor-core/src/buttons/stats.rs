@@ -0,0 +1,91 @@
+//! Key statistics
+//! Per-key counters for spotting worn or failing switches
+
+use core::fmt;
+
+use embassy_time::Duration;
+
+/// Press, bounce and hold counters of one key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyStats {
+    /// Debounced presses
+    pub presses: u32,
+    /// Extra edges seen while the key settled, over all presses
+    pub bounces: u32,
+    /// Most extra edges of a single press, down and up together
+    pub max_bounces: u16,
+    /// Longest time the key was held down
+    pub longest_hold: Duration,
+    /// Times the key was held past the stuck threshold
+    pub stuck: u16,
+}
+
+impl KeyStats {
+    /// No presses yet
+    pub const fn new() -> Self {
+        Self {
+            presses: 0,
+            bounces: 0,
+            max_bounces: 0,
+            longest_hold: Duration::from_ticks(0),
+            stuck: 0,
+        }
+    }
+
+    /// Record a finished press
+    pub fn record_press(&mut self, bounces: u16, hold: Duration) {
+        self.bounces = self.bounces.saturating_add(u32::from(bounces));
+        self.max_bounces = self.max_bounces.max(bounces);
+        self.longest_hold = self.longest_hold.max(hold);
+    }
+
+    /// Average bounces per press, in hundredths
+    pub fn bounces_per_press_x100(&self) -> u32 {
+        match self.presses {
+            0 => 0,
+            presses => self.bounces.saturating_mul(100) / presses,
+        }
+    }
+}
+
+impl Default for KeyStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for KeyStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let per_press = self.bounces_per_press_x100();
+        let hold = self.longest_hold.as_millis();
+        write!(
+            f,
+            "presses {}, bounces {} ({}.{:02}/press, max {}), longest hold {}.{:03} s, stuck {}",
+            self.presses,
+            self.bounces,
+            per_press / 100,
+            per_press % 100,
+            self.max_bounces,
+            hold / 1000,
+            hold % 1000,
+            self.stuck
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_for_the_console() {
+        let mut stats = KeyStats::new();
+        stats.presses = 3;
+        stats.record_press(1, Duration::from_millis(250));
+        stats.record_press(3, Duration::from_millis(1500));
+        assert_eq!(
+            std::format!("{stats}"),
+            "presses 3, bounces 4 (1.33/press, max 3), longest hold 1.500 s, stuck 0"
+        );
+    }
+}
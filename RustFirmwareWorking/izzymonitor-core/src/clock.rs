@@ -0,0 +1,118 @@
+//! Wall clock
+//! Time of day, kept from a reference set over the console or the network
+//!
+//! The panel has no battery-backed RTC, so the clock is unknown after a
+//! reboot until something sets it.
+
+use core::fmt;
+
+use embassy_time::Instant;
+
+/// Minutes in a day
+pub const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// A time of day in minutes after midnight
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct TimeOfDay(u16);
+
+impl TimeOfDay {
+    /// Time from hours and minutes, `None` if out of range
+    pub const fn new(hours: u8, minutes: u8) -> Option<Self> {
+        if hours < 24 && minutes < 60 {
+            Some(Self(hours as u16 * 60 + minutes as u16))
+        } else {
+            None
+        }
+    }
+
+    /// Time from minutes after midnight, wrapping at a day
+    pub const fn from_minutes(minutes: u16) -> Self {
+        Self(minutes % MINUTES_PER_DAY)
+    }
+
+    /// Minutes after midnight
+    pub const fn minutes(self) -> u16 {
+        self.0
+    }
+
+    /// Parse `H:MM` or `HH:MM`
+    pub fn parse(text: &str) -> Option<Self> {
+        let (hours, minutes) = text.split_once(':')?;
+        let digits = |text: &str| text.bytes().all(|b| b.is_ascii_digit());
+        if !(1..=2).contains(&hours.len()) || minutes.len() != 2 {
+            return None;
+        }
+        // `str::parse` would also take a leading `+`
+        if !digits(hours) || !digits(minutes) {
+            return None;
+        }
+        Self::new(hours.parse().ok()?, minutes.parse().ok()?)
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.0 / 60, self.0 % 60)
+    }
+}
+
+/// Time of day that runs on from when it was set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WallClock {
+    set_at: Instant,
+    time: TimeOfDay,
+}
+
+impl WallClock {
+    /// Clock showing `time` at `now`
+    pub const fn new(time: TimeOfDay, now: Instant) -> Self {
+        Self { set_at: now, time }
+    }
+
+    /// Time of day at `now`
+    pub fn time_at(&self, now: Instant) -> TimeOfDay {
+        let passed = now.saturating_duration_since(self.set_at).as_secs() / 60;
+        let minutes = (u64::from(self.time.minutes()) + passed) % u64::from(MINUTES_PER_DAY);
+        TimeOfDay::from_minutes(minutes as u16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_prints_times() {
+        assert_eq!(TimeOfDay::parse("07:05"), TimeOfDay::new(7, 5));
+        assert_eq!(TimeOfDay::parse("24:00"), None);
+        assert_eq!(TimeOfDay::parse("7:5"), None);
+        assert_eq!(TimeOfDay::parse("7:05"), TimeOfDay::new(7, 5));
+        assert_eq!(std::format!("{}", TimeOfDay::new(9, 3).unwrap()), "09:03");
+    }
+
+    #[test]
+    fn hours_are_one_or_two_digits() {
+        assert_eq!(TimeOfDay::parse("+7:05"), None);
+        assert_eq!(TimeOfDay::parse("007:05"), None);
+        assert_eq!(TimeOfDay::parse(":05"), None);
+        assert_eq!(TimeOfDay::parse("07:+5"), None);
+    }
+
+    #[test]
+    fn clock_runs_past_midnight() {
+        let clock = WallClock::new(TimeOfDay::new(23, 58).unwrap(), Instant::from_secs(100));
+        assert_eq!(
+            clock.time_at(Instant::from_secs(100)),
+            TimeOfDay::new(23, 58).unwrap()
+        );
+        assert_eq!(
+            clock.time_at(Instant::from_secs(280)),
+            TimeOfDay::new(0, 1).unwrap()
+        );
+        // Before it was set the clock stays where it was set
+        assert_eq!(
+            clock.time_at(Instant::from_secs(0)),
+            TimeOfDay::new(23, 58).unwrap()
+        );
+    }
+}
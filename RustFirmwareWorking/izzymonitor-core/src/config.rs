@@ -0,0 +1,288 @@
+//! Persistent configuration
+//! Settings that survive a reboot, stored as tagged records
+//!
+//! The image is a header (magic, version, record length), a list of
+//! `tag, length, data` records and a Fletcher-16 checksum. Unknown records are
+//! skipped and missing or invalid ones fall back to their defaults, so adding
+//! a setting never throws away the ones already stored.
+
+use crate::ambient::BrightnessMode;
+use crate::display::ThemeId;
+use crate::keymap::Keymap;
+use crate::leds::calibration::Calibration;
+use crate::leds::format::StripConfig;
+use crate::leds::notify::QuietHours;
+use crate::leds::power::{DEFAULT_LIMIT_MA, MAX_LIMIT_MA, MIN_LIMIT_MA};
+
+/// Marks an initialized configuration image
+pub const CONFIG_MAGIC: [u8; 4] = *b"IZMC";
+/// Layout version of the image header
+pub const CONFIG_VERSION: u8 = 1;
+/// Size of the stored image
+pub const CONFIG_SIZE: usize = 256;
+
+// Magic, version and a 16 bit record length
+const HEADER_SIZE: usize = 7;
+const CHECKSUM_SIZE: usize = 2;
+
+// Record tags, never reuse a retired one
+const TAG_KEYMAP: u8 = 1;
+const TAG_LED_LIMIT: u8 = 2;
+const TAG_CALIBRATION: u8 = 3;
+const TAG_STRIP: u8 = 4;
+const TAG_QUIET_HOURS: u8 = 5;
+const TAG_BRIGHTNESS: u8 = 6;
+const TAG_THEME: u8 = 7;
+
+/// Everything that is kept across reboots
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    pub keymap: Keymap,
+    /// LED supply current budget in mA
+    pub led_limit_ma: u16,
+    /// Color correction of every key LED
+    pub calibration: Calibration,
+    /// LEDs hung off the enclosure
+    pub strip: StripConfig,
+    /// When notification lights stay dark
+    pub quiet_hours: QuietHours,
+    /// Backlight and LED brightness, automatic or fixed
+    pub brightness: BrightnessMode,
+    /// Colors and fonts of the screens
+    pub theme: ThemeId,
+}
+
+impl Config {
+    /// Factory settings
+    pub const fn new() -> Self {
+        Self {
+            keymap: Keymap::new(),
+            led_limit_ma: DEFAULT_LIMIT_MA,
+            calibration: Calibration::new(),
+            strip: StripConfig::new(),
+            quiet_hours: QuietHours::OFF,
+            brightness: BrightnessMode::Auto,
+            theme: ThemeId::Dark,
+        }
+    }
+
+    /// Serialize into a storage image
+    pub fn encode(&self) -> Result<[u8; CONFIG_SIZE], &'static str> {
+        let mut writer = Writer::new();
+        writer.record(TAG_KEYMAP, &self.keymap.to_bytes())?;
+        writer.record(TAG_LED_LIMIT, &self.led_limit_ma.to_le_bytes())?;
+        writer.record(TAG_CALIBRATION, &self.calibration.to_bytes())?;
+        writer.record(TAG_STRIP, &self.strip.to_bytes())?;
+        writer.record(TAG_QUIET_HOURS, &self.quiet_hours.to_bytes())?;
+        writer.record(TAG_BRIGHTNESS, &[self.brightness.to_byte()])?;
+        writer.record(TAG_THEME, &[self.theme.to_byte()])?;
+        writer.finish()
+    }
+
+    /// Deserialize a storage image
+    pub fn decode(image: &[u8]) -> Result<Self, &'static str> {
+        let records = records(image)?;
+        let mut config = Config::default();
+
+        let mut rest = records;
+        while let [tag, len, tail @ ..] = rest {
+            let len = usize::from(*len);
+            if tail.len() < len {
+                return Err("Truncated config record");
+            }
+            let (data, tail) = tail.split_at(len);
+            rest = tail;
+
+            // A bad record only costs that one setting
+            match (*tag, data) {
+                (TAG_KEYMAP, _) => {
+                    if let Ok(keymap) = Keymap::from_bytes(data) {
+                        config.keymap = keymap;
+                    }
+                }
+                (TAG_LED_LIMIT, &[lo, hi]) => {
+                    let limit = u16::from_le_bytes([lo, hi]);
+                    if (MIN_LIMIT_MA..=MAX_LIMIT_MA).contains(&limit) {
+                        config.led_limit_ma = limit;
+                    }
+                }
+                (TAG_CALIBRATION, _) => {
+                    if let Ok(calibration) = Calibration::from_bytes(data) {
+                        config.calibration = calibration;
+                    }
+                }
+                (TAG_STRIP, _) => {
+                    if let Ok(strip) = StripConfig::from_bytes(data) {
+                        config.strip = strip;
+                    }
+                }
+                (TAG_QUIET_HOURS, _) => {
+                    if let Ok(quiet_hours) = QuietHours::from_bytes(data) {
+                        config.quiet_hours = quiet_hours;
+                    }
+                }
+                (TAG_BRIGHTNESS, &[byte]) => {
+                    if let Ok(brightness) = BrightnessMode::from_byte(byte) {
+                        config.brightness = brightness;
+                    }
+                }
+                (TAG_THEME, &[byte]) => {
+                    if let Ok(theme) = ThemeId::from_byte(byte) {
+                        config.theme = theme;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(config)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Check the header and checksum, returning the record area
+fn records(image: &[u8]) -> Result<&[u8], &'static str> {
+    if image.len() < HEADER_SIZE + CHECKSUM_SIZE || image[..4] != CONFIG_MAGIC {
+        return Err("No stored config");
+    }
+    if image[4] != CONFIG_VERSION {
+        return Err("Unsupported config version");
+    }
+    let len = usize::from(u16::from_le_bytes([image[5], image[6]]));
+    let end = HEADER_SIZE + len;
+    if end + CHECKSUM_SIZE > image.len() {
+        return Err("Config length out of range");
+    }
+    let stored = u16::from_le_bytes([image[end], image[end + 1]]);
+    if fletcher16(&image[..end]) != stored {
+        return Err("Config checksum mismatch");
+    }
+    Ok(&image[HEADER_SIZE..end])
+}
+
+/// Fletcher-16 checksum
+pub(crate) fn fletcher16(data: &[u8]) -> u16 {
+    let (mut a, mut b) = (0u16, 0u16);
+    for &byte in data {
+        a = (a + u16::from(byte)) % 255;
+        b = (b + a) % 255;
+    }
+    (b << 8) | a
+}
+
+/// Builds a storage image record by record
+struct Writer {
+    image: [u8; CONFIG_SIZE],
+    len: usize,
+}
+
+impl Writer {
+    fn new() -> Self {
+        // Unused space stays erased
+        let mut image = [0xff; CONFIG_SIZE];
+        image[..4].copy_from_slice(&CONFIG_MAGIC);
+        image[4] = CONFIG_VERSION;
+        Self {
+            image,
+            len: HEADER_SIZE,
+        }
+    }
+
+    fn record(&mut self, tag: u8, data: &[u8]) -> Result<(), &'static str> {
+        let len = u8::try_from(data.len()).map_err(|_| "Config record too long")?;
+        let end = self.len + 2 + data.len();
+        if end + CHECKSUM_SIZE > CONFIG_SIZE {
+            return Err("Config too large");
+        }
+        self.image[self.len] = tag;
+        self.image[self.len + 1] = len;
+        self.image[self.len + 2..end].copy_from_slice(data);
+        self.len = end;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<[u8; CONFIG_SIZE], &'static str> {
+        let records = (self.len - HEADER_SIZE) as u16;
+        self.image[5..7].copy_from_slice(&records.to_le_bytes());
+        let checksum = fletcher16(&self.image[..self.len]);
+        self.image[self.len..self.len + 2].copy_from_slice(&checksum.to_le_bytes());
+        Ok(self.image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap::Action;
+    use crate::menu::MenuScreen;
+
+    #[test]
+    fn round_trip() {
+        let mut config = Config {
+            keymap: Keymap::mirrored(),
+            led_limit_ma: 90,
+            calibration: Calibration::new(),
+            strip: StripConfig {
+                format: crate::leds::PixelFormat::Apa102,
+                len: 24,
+            },
+            quiet_hours: QuietHours::parse("22:00-06:30").unwrap(),
+            brightness: BrightnessMode::Manual(25),
+            theme: ThemeId::HighContrast,
+        };
+        config.calibration.leds[3].gamma_x10 = 22;
+        config.calibration.leds[3].floor = 2;
+        assert_eq!(Config::decode(&config.encode().unwrap()), Ok(config));
+    }
+
+    #[test]
+    fn erased_flash_is_not_a_config() {
+        assert_eq!(
+            Config::decode(&[0xff; CONFIG_SIZE]),
+            Err("No stored config")
+        );
+    }
+
+    #[test]
+    fn corruption_is_detected() {
+        let mut image = Config::default().encode().unwrap();
+        image[HEADER_SIZE + 3] ^= 1;
+        assert_eq!(Config::decode(&image), Err("Config checksum mismatch"));
+    }
+
+    #[test]
+    fn unknown_records_are_skipped() {
+        let mut writer = Writer::new();
+        writer.record(200, &[1, 2, 3]).unwrap();
+        writer
+            .record(TAG_KEYMAP, &Keymap::mirrored().to_bytes())
+            .unwrap();
+        let config = Config::decode(&writer.finish().unwrap()).unwrap();
+        assert_eq!(config.keymap, Keymap::mirrored());
+    }
+
+    #[test]
+    fn keymap_without_theme_key_falls_back() {
+        let mut old = Keymap::mirrored();
+        let key = old.key_for(MenuScreen::Settings, Action::Theme).unwrap();
+        old.set(MenuScreen::Settings, key, Action::Wifi);
+        let mut writer = Writer::new();
+        writer.record(TAG_KEYMAP, &old.to_bytes()).unwrap();
+        let config = Config::decode(&writer.finish().unwrap()).unwrap();
+        assert_eq!(config.keymap, Keymap::new());
+    }
+
+    #[test]
+    fn out_of_range_limit_falls_back() {
+        let mut writer = Writer::new();
+        writer
+            .record(TAG_LED_LIMIT, &5000u16.to_le_bytes())
+            .unwrap();
+        let config = Config::decode(&writer.finish().unwrap()).unwrap();
+        assert_eq!(config.led_limit_ma, DEFAULT_LIMIT_MA);
+    }
+}
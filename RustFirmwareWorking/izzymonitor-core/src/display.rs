@@ -0,0 +1,579 @@
+//! Display module for the 160×128 panel
+//! Screen composition on top of any embedded-graphics draw target
+
+use embedded_graphics::{
+    mono_font::{
+        ascii::{FONT_10X20, FONT_8X13},
+        MonoTextStyle,
+    },
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::{PrimitiveStyleBuilder, Rectangle},
+    text::{Alignment, Text},
+};
+
+use core::fmt::Write;
+use heapless::String;
+
+use embassy_time::{Duration, Instant};
+
+use crate::buttons::KeyStats;
+use crate::leds::calibration::CalibrationItem;
+use crate::leds::RgbColor as LedColor;
+use crate::menu::{Menu, MenuScreen};
+use crate::timeline::{Easing, Timeline};
+use crate::KEY_COUNT;
+
+pub mod font;
+pub mod fonts;
+pub mod layout;
+pub mod partial;
+pub mod text;
+pub mod theme;
+pub mod widgets;
+
+pub use font::{BitmapFont, BitmapTextStyle, Font};
+pub use layout::ScreenLayout;
+pub use partial::{Frame, PartialRedraw};
+pub use theme::{Theme, ThemeId};
+pub use widgets::{
+    Label, Modal, Panel, ProgressBar, ScrollList, SoftKeyBar, Spinner, TextViewer, Toast,
+};
+
+// Screen size for ST7735S 1.8" LCD
+pub const SCREEN_WIDTH: u32 = 160;
+pub const SCREEN_HEIGHT: u32 = 128;
+
+/// Size of the calibration screen's test color swatch
+const SWATCH_SIZE: Size = Size::new(24, 13);
+
+/// How long the wipe between two screens takes
+pub const TRANSITION: Duration = Duration::from_millis(180);
+
+/// Right edge of a wipe that uncovers the new screen left to right
+pub fn screen_wipe(start: Instant) -> Timeline<i32> {
+    Timeline::tween(start, 0, SCREEN_WIDTH as i32, TRANSITION, Easing::Cubic)
+}
+
+/// Areas of the panel in a theme's spacing
+pub fn screen_layout(theme: &Theme) -> ScreenLayout {
+    let size = Size::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+    ScreenLayout::with_spacing(size, theme.margin, theme.padding)
+}
+
+// Button layout definition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ButtonLayout {
+    pub labels: [&'static str; KEY_COUNT],
+    pub active_index: usize,
+}
+
+/// Screen renderer over a board-provided draw target
+pub struct Display<D> {
+    target: D,
+    theme: Theme,
+}
+
+impl<D> Display<D>
+where
+    D: DrawTarget<Color = Rgb565>,
+{
+    /// Wrap an already initialized draw target, drawing in the dark theme
+    pub fn new(target: D) -> Self {
+        Self {
+            target,
+            theme: Theme::DARK,
+        }
+    }
+
+    /// The theme screens are drawn in
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    /// Draw in another theme from the next screen on
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+
+    /// Borrow the underlying draw target
+    pub fn target(&self) -> &D {
+        &self.target
+    }
+
+    /// Mutably borrow the underlying draw target
+    pub fn target_mut(&mut self) -> &mut D {
+        &mut self.target
+    }
+
+    /// Give back the underlying draw target
+    pub fn release(self) -> D {
+        self.target
+    }
+
+    /// Clear the display
+    pub fn clear(&mut self) -> Result<(), &'static str> {
+        self.target
+            .clear(self.theme.background)
+            .map_err(|_| "Failed to clear display")
+    }
+
+    /// Areas of the screen, worked out from the target's size
+    pub fn layout(&self) -> ScreenLayout {
+        let size = self.target.bounding_box().size;
+        ScreenLayout::with_spacing(size, self.theme.margin, self.theme.padding)
+    }
+
+    /// A label in the theme
+    fn label(&self, area: Rectangle, text: &str) -> Label {
+        Label::new(area, text).with_theme(&self.theme)
+    }
+
+    /// Draw a widget, or any other drawable
+    pub fn draw_widget<W>(&mut self, widget: &W) -> Result<(), &'static str>
+    where
+        W: Drawable<Color = Rgb565>,
+    {
+        widget
+            .draw(&mut self.target)
+            .map(|_| ())
+            .map_err(|_| "Failed to draw widget")
+    }
+
+    /// Draw a text
+    pub fn draw_text(
+        &mut self,
+        text: &str,
+        x: i32,
+        y: i32,
+        color: Rgb565,
+        large: bool,
+    ) -> Result<(), &'static str> {
+        let font = if large { &FONT_10X20 } else { &FONT_8X13 };
+        let style = MonoTextStyle::new(font, color);
+
+        Text::new(text, Point::new(x, y), style)
+            .draw(&mut self.target)
+            .map(|_| ())
+            .map_err(|_| "Failed to draw text")
+    }
+
+    /// Draw a title at the top of the screen
+    pub fn draw_title(&mut self, title: &str) -> Result<(), &'static str> {
+        let label = self
+            .label(self.layout().title, title)
+            .with_color(self.theme.button_text)
+            .with_background(self.theme.button)
+            .with_alignment(Alignment::Center);
+        self.draw_widget(&label)
+    }
+
+    /// Draw a bordered box
+    pub fn draw_box(
+        &mut self,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    ) -> Result<(), &'static str> {
+        let area = Rectangle::new(Point::new(x, y), Size::new(width, height));
+        self.draw_widget(&Panel::new(area).with_theme(&self.theme))
+    }
+
+    /// Draw button labels at the bottom of the screen
+    pub fn draw_buttons(&mut self, layout: &ButtonLayout) -> Result<(), &'static str> {
+        self.draw_widget(&SoftKeyBar::new(self.layout().keys, *layout).with_theme(&self.theme))
+    }
+
+    /// Clear the screen and draw the title and the content frame
+    fn draw_frame(&mut self, screen: MenuScreen) -> Result<ScreenLayout, &'static str> {
+        let layout = self.layout();
+        self.clear()?;
+        self.draw_title(screen.title())?;
+        self.draw_widget(&Panel::new(layout.frame).with_theme(&self.theme))?;
+        Ok(layout)
+    }
+
+    /// Draw the startup screen
+    pub fn draw_startup(&mut self) -> Result<(), &'static str> {
+        let layout = self.draw_frame(MenuScreen::Startup)?;
+        let [_, welcome, status, _]: [Rectangle; 4] = layout::rows(layout.content);
+        self.draw_widget(
+            &self
+                .label(welcome, "Welcome")
+                .with_alignment(Alignment::Center),
+        )?;
+        self.draw_widget(
+            &self
+                .label(status, "Initializing...")
+                .with_alignment(Alignment::Center),
+        )?;
+
+        // Version in the corner where the keys go once started
+        let margin = self.theme.margin;
+        let corner = layout::inset(layout.keys, margin, margin);
+        self.draw_widget(
+            &self
+                .label(corner, "v0.1.0")
+                .with_font(self.theme.small_font)
+                .with_alignment(Alignment::Right),
+        )
+    }
+
+    /// Draw the main screen
+    pub fn draw_main_screen(&mut self, layout: &ButtonLayout) -> Result<(), &'static str> {
+        let screen = self.draw_frame(MenuScreen::Main)?;
+        let [_, ready, _, _]: [Rectangle; 4] = layout::rows(screen.content);
+        self.draw_widget(&self.label(ready, "Ready").with_alignment(Alignment::Center))?;
+        self.draw_buttons(layout)
+    }
+
+    /// Draw the trip planner screen, Up/Down scroll the route description
+    pub fn draw_trip_screen(&mut self, menu: &Menu) -> Result<(), &'static str> {
+        let screen = self.draw_frame(MenuScreen::Trip)?;
+        if menu.trip_text().is_empty() {
+            let [_, message, _, _]: [Rectangle; 4] = layout::rows(screen.content);
+            self.draw_widget(
+                &self
+                    .label(message, "No trips scheduled")
+                    .with_alignment(Alignment::Center),
+            )?;
+        } else {
+            self.draw_widget(&menu.trip_viewer())?;
+        }
+        self.draw_buttons(menu.layout())
+    }
+
+    /// Draw the settings screen, Up/Down set the brightness, Look the theme
+    pub fn draw_settings_screen(&mut self, menu: &Menu) -> Result<(), &'static str> {
+        let screen = self.draw_frame(MenuScreen::Settings)?;
+        let [wifi, light, look, user]: [Rectangle; 4] = layout::rows(screen.content);
+        self.draw_widget(&self.label(wifi, "WiFi: Not Connected"))?;
+
+        let mut line: String<16> = String::new();
+        // Always fits: "Light: " and at most four characters
+        let _ = write!(line, "Light: {}", menu.brightness());
+        self.draw_widget(&self.label(light, &line))?;
+
+        line.clear();
+        // Always fits: "Theme: " and at most eight characters
+        let _ = write!(line, "Theme: {}", menu.theme_id());
+        self.draw_widget(&self.label(look, &line))?;
+
+        self.draw_widget(&self.label(user, "User: Guest"))?;
+        self.draw_buttons(menu.layout())
+    }
+
+    /// Draw the key map editor, three keys per column
+    pub fn draw_keymap_screen(&mut self, menu: &Menu) -> Result<(), &'static str> {
+        let editor = menu.editor();
+        let screen = self.draw_frame(MenuScreen::Keymap)?;
+        let (header, cells) = grid(screen.content);
+        self.draw_widget(&self.label(header, editor.screen.title()))?;
+
+        let mut line: String<16> = String::new();
+        for (key, cell) in cells.into_iter().enumerate() {
+            line.clear();
+            let action = menu.keymap().action(editor.screen, key);
+            // Always fits: a digit, two characters and a four letter label
+            let _ = write!(line, "{}: {}", key + 1, action.label());
+
+            let color = if key == editor.key {
+                self.theme.highlight
+            } else {
+                self.theme.text
+            };
+            self.draw_widget(&self.label(cell, &line).with_color(color))?;
+        }
+
+        self.draw_buttons(menu.layout())
+    }
+
+    /// Draw the calibration screen: test color, LED and the values being tuned
+    pub fn draw_calibration_screen(&mut self, menu: &Menu) -> Result<(), &'static str> {
+        let editor = menu.calibration_editor();
+        let led = &menu.calibration().leds[editor.led];
+        let (name, color) = editor.test_color();
+        let screen = self.draw_frame(MenuScreen::Calibration)?;
+        let (header, cells) = grid(screen.content);
+
+        let mut line: String<16> = String::new();
+        // Always fits: a five letter name and the LED number
+        let _ = write!(line, "{:<6}LED {}", name, editor.led + 1);
+        self.draw_widget(&self.label(header, &line))?;
+
+        // The panel's idea of the test color, to hold the keys against, at
+        // the right end of the header
+        let (_, swatch) = layout::split_right(header, SWATCH_SIZE.width);
+        Rectangle::new(layout::centered(swatch, SWATCH_SIZE).top_left, SWATCH_SIZE)
+            .into_styled(
+                PrimitiveStyleBuilder::new()
+                    .fill_color(rgb565(color))
+                    .stroke_color(self.theme.border)
+                    .stroke_width(1)
+                    .build(),
+            )
+            .draw(&mut self.target)
+            .map_err(|_| "Failed to draw test color")?;
+
+        for (index, (item, cell)) in CalibrationItem::ALL.into_iter().zip(cells).enumerate() {
+            line.clear();
+            // Always fits: a three letter label and at most four characters
+            let _ = match item {
+                CalibrationItem::RedGain
+                | CalibrationItem::GreenGain
+                | CalibrationItem::BlueGain => {
+                    // The gains come first, in channel order
+                    let gain = i32::from(led.matrix[index][index]) * 100 / 256;
+                    write!(line, "{} {}.{:02}", item.label(), gain / 100, gain % 100)
+                }
+                CalibrationItem::Gamma => write!(
+                    line,
+                    "{} {}.{}",
+                    item.label(),
+                    led.gamma_x10 / 10,
+                    led.gamma_x10 % 10
+                ),
+                CalibrationItem::Floor => write!(line, "{} {}", item.label(), led.floor),
+            };
+
+            let color = if item == editor.item {
+                self.theme.highlight
+            } else {
+                self.theme.text
+            };
+            self.draw_widget(&self.label(cell, &line).with_color(color))?;
+        }
+
+        self.draw_buttons(menu.layout())
+    }
+
+    /// Draw the key diagnostics screen with the menu's latest key statistics
+    pub fn draw_diagnostics_screen(&mut self, menu: &Menu) -> Result<(), &'static str> {
+        self.draw_frame(MenuScreen::Diagnostics)?;
+        self.draw_key_stats(menu.key_stats())?;
+        self.draw_buttons(menu.layout())
+    }
+
+    /// Draw one row of press, bounce and hold counters per key
+    pub fn draw_key_stats(&mut self, stats: &[KeyStats; KEY_COUNT]) -> Result<(), &'static str> {
+        let rows: [Rectangle; KEY_COUNT] = layout::rows(self.layout().content);
+        let mut line: String<32> = String::new();
+        for (key, (stats, row)) in stats.iter().zip(rows).enumerate() {
+            line.clear();
+            let per_press = stats.bounces_per_press_x100();
+            let hold = stats.longest_hold.as_millis() / 100;
+            // Always fits: at most 26 characters
+            let _ = write!(
+                line,
+                "{} {:>5} {:>2}.{:02} {:>3}.{}s",
+                key + 1,
+                stats.presses.min(99_999),
+                (per_press / 100).min(99),
+                per_press % 100,
+                (hold / 10).min(999),
+                hold % 10
+            );
+            if stats.stuck > 0 {
+                let _ = line.push_str(" !");
+            }
+
+            let color = if stats.stuck > 0 {
+                self.theme.highlight
+            } else {
+                self.theme.text
+            };
+            // The background replaces the previous counts
+            self.draw_widget(
+                &self
+                    .label(row, &line)
+                    .with_font(self.theme.small_font)
+                    .with_color(color)
+                    .with_background(self.theme.background),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Draw whichever screen the menu is on, in the menu's theme
+    pub fn draw_menu(&mut self, menu: &Menu) -> Result<(), &'static str> {
+        self.theme = menu.theme();
+        match menu.screen() {
+            MenuScreen::Startup => self.draw_startup(),
+            MenuScreen::Main => self.draw_main_screen(menu.layout()),
+            MenuScreen::Trip => self.draw_trip_screen(menu),
+            MenuScreen::Settings => self.draw_settings_screen(menu),
+            MenuScreen::Keymap => self.draw_keymap_screen(menu),
+            MenuScreen::Calibration => self.draw_calibration_screen(menu),
+            MenuScreen::Diagnostics => self.draw_diagnostics_screen(menu),
+        }
+    }
+}
+
+impl<D> Display<PartialRedraw<'_, D>>
+where
+    D: DrawTarget<Color = Rgb565>,
+{
+    /// Send the panel what changed since the last flush
+    pub fn flush(&mut self) -> Result<(), &'static str> {
+        self.target.flush().map_err(|_| "Failed to update display")
+    }
+
+    /// Send the panel what changed left of `edge`, one step of a screen wipe
+    ///
+    /// Each tile goes out once, as the wipe reaches it, so a wipe costs no
+    /// more than drawing the new screen straight away.
+    pub fn flush_wipe(&mut self, edge: i32) -> Result<(), &'static str> {
+        let width = edge.clamp(0, SCREEN_WIDTH as i32) as u32;
+        let area = Rectangle::new(Point::zero(), Size::new(width, SCREEN_HEIGHT));
+        self.target
+            .flush_area(&area)
+            .map_err(|_| "Failed to update display")
+    }
+}
+
+/// Header row over two columns of three cells, left column first
+fn grid(content: Rectangle) -> (Rectangle, [Rectangle; KEY_COUNT]) {
+    let [header, rows @ ..]: [Rectangle; 4] = layout::rows(content);
+    let [left, right]: [Rectangle; 2] = layout::columns(content);
+    let cell = |column: Rectangle, row: Rectangle| {
+        Rectangle::new(
+            Point::new(column.top_left.x, row.top_left.y),
+            Size::new(column.size.width, row.size.height),
+        )
+    };
+    let cells = core::array::from_fn(|i| cell([left, right][i / 3], rows[i % 3]));
+    (header, cells)
+}
+
+/// Nearest panel color to an LED color
+fn rgb565(color: LedColor) -> Rgb565 {
+    Rgb565::new(color.r >> 3, color.g >> 2, color.b >> 3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::mock_display::MockDisplay;
+    use embedded_graphics::Pixel;
+
+    /// Full-size in-memory screen, MockDisplay is only 64×64
+    struct Screen(Vec<Rgb565>);
+
+    impl Screen {
+        fn new() -> Self {
+            Self(vec![
+                Rgb565::CSS_HOT_PINK;
+                (SCREEN_WIDTH * SCREEN_HEIGHT) as usize
+            ])
+        }
+
+        fn pixel(&self, x: u32, y: u32) -> Rgb565 {
+            self.0[(y * SCREEN_WIDTH + x) as usize]
+        }
+    }
+
+    impl OriginDimensions for Screen {
+        fn size(&self) -> Size {
+            Size::new(SCREEN_WIDTH, SCREEN_HEIGHT)
+        }
+    }
+
+    impl DrawTarget for Screen {
+        type Color = Rgb565;
+        type Error = core::convert::Infallible;
+
+        fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+        where
+            I: IntoIterator<Item = Pixel<Rgb565>>,
+        {
+            for Pixel(p, color) in pixels {
+                if self.bounding_box().contains(p) {
+                    self.0[(p.y as u32 * SCREEN_WIDTH + p.x as u32) as usize] = color;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn draws_box_outline() {
+        let mut mock = MockDisplay::<Rgb565>::new();
+        mock.set_allow_overdraw(true);
+        let mut display = Display::new(mock);
+        display.draw_box(0, 0, 10, 10).unwrap();
+
+        let mock = display.release();
+        assert_eq!(mock.get_pixel(Point::new(5, 0)), Some(Theme::DARK.border));
+        assert_eq!(mock.get_pixel(Point::new(5, 5)), None);
+    }
+
+    #[test]
+    fn main_screen_covers_whole_panel() {
+        let mut menu = Menu::new();
+        menu.finish_startup();
+
+        let mut display = Display::new(Screen::new());
+        display.draw_menu(&menu).unwrap();
+        let screen = display.release();
+
+        assert!(screen.0.iter().all(|&c| c != Rgb565::CSS_HOT_PINK));
+        assert_eq!(screen.pixel(1, 1), Theme::DARK.button);
+        assert_eq!(screen.pixel(80, 22), Theme::DARK.background);
+        // Active indicator around the first soft key
+        assert_eq!(screen.pixel(4, SCREEN_HEIGHT - 22), Rgb565::WHITE);
+    }
+
+    #[test]
+    fn keymap_screen_highlights_the_cursor() {
+        let mut menu = Menu::new();
+        menu.finish_startup();
+        menu.press(2);
+        menu.press(3);
+
+        let mut display = Display::new(Screen::new());
+        display.draw_menu(&menu).unwrap();
+        let screen = display.release();
+
+        let row = |y: u32| (12..80).any(|x| screen.pixel(x, y) == Theme::DARK.highlight);
+        assert!((44..56).any(row));
+        assert!(!(57..82).any(row));
+    }
+
+    #[test]
+    fn calibration_screen_shows_the_test_color() {
+        let mut menu = Menu::new();
+        menu.finish_startup();
+        menu.press(2);
+        menu.press(2);
+        menu.press(1);
+
+        let mut display = Display::new(Screen::new());
+        display.draw_menu(&menu).unwrap();
+        let screen = display.release();
+
+        assert_eq!(screen.pixel(130, 34), Rgb565::RED);
+        // Red gain is the first item, in the left column
+        let row = |y: u32| (12..80).any(|x| screen.pixel(x, y) == Theme::DARK.highlight);
+        assert!((44..56).any(row));
+        assert!(!(57..82).any(row));
+    }
+
+    #[test]
+    fn screens_follow_the_menu_theme() {
+        let mut menu = Menu::new();
+        menu.finish_startup();
+        menu.press(2);
+        menu.press(1);
+
+        let mut display = Display::new(Screen::new());
+        display.draw_menu(&menu).unwrap();
+        let screen = display.release();
+
+        assert_eq!(screen.pixel(1, 1), Theme::LIGHT.button);
+        assert_eq!(screen.pixel(80, 22), Theme::LIGHT.background);
+        // "Theme: Light" in the third row
+        let row = |y: u32| (8..100).any(|x| screen.pixel(x, y) == Theme::LIGHT.text);
+        assert!((60..75).any(row));
+    }
+}
@@ -0,0 +1,359 @@
+//! Bitmap fonts
+//! Proportional fonts with Unicode glyphs, converted at build time
+//!
+//! The fonts themselves are in [`fonts`](super::fonts), generated by
+//! `izzymonitor-fontgen` from the list in `fonts/fonts.txt`. [`Font`] lets a
+//! widget take either one of them or an embedded-graphics mono font.
+
+use embedded_graphics::{
+    mono_font::{MonoFont, MonoTextStyle},
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::Rectangle,
+    text::{
+        renderer::{CharacterStyle, TextMetrics as RenderMetrics, TextRenderer},
+        Baseline, Text, TextStyleBuilder,
+    },
+};
+
+use super::text::TextMetrics;
+
+/// Placement and bitmap of one character
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Glyph {
+    pub c: char,
+    /// Distance to the next character
+    pub advance: u8,
+    /// Left of the bitmap from the pen
+    pub x: i8,
+    /// Top of the bitmap from the top of the line
+    pub y: i8,
+    pub width: u8,
+    pub height: u8,
+    /// First bit of the bitmap in the font's bits
+    pub offset: u32,
+}
+
+impl Glyph {
+    /// A glyph, for the generated fonts
+    pub const fn new(
+        c: char,
+        advance: u8,
+        x: i8,
+        y: i8,
+        width: u8,
+        height: u8,
+        offset: u32,
+    ) -> Self {
+        Self {
+            c,
+            advance,
+            x,
+            y,
+            width,
+            height,
+            offset,
+        }
+    }
+}
+
+/// A proportional font of one-bit glyphs
+#[derive(Debug)]
+pub struct BitmapFont {
+    // Sorted by character
+    glyphs: &'static [Glyph],
+    replacement: Glyph,
+    // Every glyph's rows, one bit per pixel, first pixel in the high bit
+    bits: &'static [u8],
+    line_height: u8,
+    baseline: u8,
+}
+
+impl BitmapFont {
+    /// A font, for the generated fonts
+    pub const fn new(
+        glyphs: &'static [Glyph],
+        replacement: Glyph,
+        bits: &'static [u8],
+        line_height: u8,
+        baseline: u8,
+    ) -> Self {
+        Self {
+            glyphs,
+            replacement,
+            bits,
+            line_height,
+            baseline,
+        }
+    }
+
+    /// The glyph for a character, the replacement if the font lacks it
+    pub fn glyph(&self, c: char) -> &Glyph {
+        self.glyphs
+            .binary_search_by_key(&c, |glyph| glyph.c)
+            .map_or(&self.replacement, |index| &self.glyphs[index])
+    }
+
+    /// Whether the font has a character
+    pub fn contains(&self, c: char) -> bool {
+        self.glyphs
+            .binary_search_by_key(&c, |glyph| glyph.c)
+            .is_ok()
+    }
+
+    /// Baseline from the top of the line
+    pub fn baseline(&self) -> u32 {
+        u32::from(self.baseline)
+    }
+
+    /// Pixels set in a glyph, from the pen at the top of the line
+    fn pixels(&self, glyph: &Glyph) -> impl Iterator<Item = Point> + '_ {
+        let (width, height) = (u32::from(glyph.width), u32::from(glyph.height));
+        let origin = Point::new(i32::from(glyph.x), i32::from(glyph.y));
+        let offset = glyph.offset;
+        (0..width * height).filter_map(move |i| {
+            let bit = (offset + i) as usize;
+            let set = self.bits[bit / 8] & (0x80 >> (bit % 8)) != 0;
+            set.then(|| origin + Point::new((i % width) as i32, (i / width) as i32))
+        })
+    }
+}
+
+impl TextMetrics for BitmapFont {
+    fn char_width(&self, c: char) -> u32 {
+        u32::from(self.glyph(c).advance)
+    }
+
+    fn spacing(&self) -> u32 {
+        // The advance includes the space after a glyph
+        0
+    }
+
+    fn line_height(&self) -> u32 {
+        u32::from(self.line_height)
+    }
+}
+
+/// Text style drawing a [`BitmapFont`] in one color
+#[derive(Debug, Clone, Copy)]
+pub struct BitmapTextStyle<'a, C> {
+    font: &'a BitmapFont,
+    color: C,
+}
+
+impl<'a, C> BitmapTextStyle<'a, C> {
+    /// A style for a font and color
+    pub fn new(font: &'a BitmapFont, color: C) -> Self {
+        Self { font, color }
+    }
+
+    /// Top of the line for a position and baseline
+    fn line_top(&self, position: Point, baseline: Baseline) -> Point {
+        let height = self.font.line_height() as i32;
+        let y = match baseline {
+            Baseline::Top => 0,
+            Baseline::Bottom => height - 1,
+            Baseline::Middle => (height - 1) / 2,
+            Baseline::Alphabetic => self.font.baseline() as i32,
+        };
+        position - Point::new(0, y)
+    }
+}
+
+impl<C: PixelColor> CharacterStyle for BitmapTextStyle<'_, C> {
+    type Color = C;
+
+    fn set_text_color(&mut self, color: Option<C>) {
+        if let Some(color) = color {
+            self.color = color;
+        }
+    }
+}
+
+impl<C: PixelColor> TextRenderer for BitmapTextStyle<'_, C> {
+    type Color = C;
+
+    fn draw_string<D>(
+        &self,
+        text: &str,
+        position: Point,
+        baseline: Baseline,
+        target: &mut D,
+    ) -> Result<Point, D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        let top = self.line_top(position, baseline);
+        let mut pen = top;
+        for c in text.chars() {
+            let glyph = self.font.glyph(c);
+            let color = self.color;
+            let origin = pen;
+            target.draw_iter(
+                self.font
+                    .pixels(glyph)
+                    .map(|point| Pixel(origin + point, color)),
+            )?;
+            pen.x += i32::from(glyph.advance);
+        }
+        Ok(position + Point::new(pen.x - top.x, 0))
+    }
+
+    fn draw_whitespace<D>(
+        &self,
+        width: u32,
+        position: Point,
+        _baseline: Baseline,
+        _target: &mut D,
+    ) -> Result<Point, D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        Ok(position + Point::new(width as i32, 0))
+    }
+
+    fn measure_string(&self, text: &str, position: Point, baseline: Baseline) -> RenderMetrics {
+        let width: u32 = text.chars().map(|c| self.font.char_width(c)).sum();
+        RenderMetrics {
+            bounding_box: Rectangle::new(
+                self.line_top(position, baseline),
+                Size::new(width, self.font.line_height()),
+            ),
+            next_position: position + Point::new(width as i32, 0),
+        }
+    }
+
+    fn line_height(&self) -> u32 {
+        self.font.line_height()
+    }
+}
+
+/// A font for widgets, mono or bitmap
+#[derive(Debug, Clone, Copy)]
+pub enum Font {
+    Mono(&'static MonoFont<'static>),
+    Bitmap(&'static BitmapFont),
+}
+
+impl Font {
+    /// Draw `text` with the top of the line at `position`, returning where the next text goes
+    pub fn draw<D>(
+        &self,
+        text: &str,
+        position: Point,
+        color: Rgb565,
+        target: &mut D,
+    ) -> Result<Point, D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        let style = TextStyleBuilder::new().baseline(Baseline::Top).build();
+        match *self {
+            Font::Mono(font) => {
+                Text::with_text_style(text, position, MonoTextStyle::new(font, color), style)
+                    .draw(target)
+            }
+            Font::Bitmap(font) => {
+                Text::with_text_style(text, position, BitmapTextStyle::new(font, color), style)
+                    .draw(target)
+            }
+        }
+    }
+}
+
+impl From<&'static MonoFont<'static>> for Font {
+    fn from(font: &'static MonoFont<'static>) -> Self {
+        Font::Mono(font)
+    }
+}
+
+impl From<&'static BitmapFont> for Font {
+    fn from(font: &'static BitmapFont) -> Self {
+        Font::Bitmap(font)
+    }
+}
+
+impl TextMetrics for Font {
+    fn char_width(&self, c: char) -> u32 {
+        match self {
+            Font::Mono(font) => font.char_width(c),
+            Font::Bitmap(font) => font.char_width(c),
+        }
+    }
+
+    fn spacing(&self) -> u32 {
+        match self {
+            Font::Mono(font) => font.spacing(),
+            Font::Bitmap(font) => font.spacing(),
+        }
+    }
+
+    fn line_height(&self) -> u32 {
+        match self {
+            Font::Mono(font) => font.line_height(),
+            Font::Bitmap(font) => font.line_height(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::fonts::{SANS_10, SANS_13};
+    use crate::display::text;
+    use embedded_graphics::mock_display::MockDisplay;
+
+    fn draw(text: &str) -> MockDisplay<Rgb565> {
+        let mut display = MockDisplay::new();
+        display.set_allow_overdraw(true);
+        Font::from(&SANS_13)
+            .draw(text, Point::zero(), Rgb565::WHITE, &mut display)
+            .unwrap();
+        display
+    }
+
+    #[test]
+    fn glyphs_cover_accents_and_other_scripts() {
+        for c in "Düsseldorf Straße Łódź Αθήνα Москва".chars() {
+            assert!(SANS_13.contains(c) || c == ' ', "{c}");
+            assert!(SANS_10.contains(c) || c == ' ', "{c}");
+        }
+        // Outside the subset: the replacement
+        assert!(!SANS_13.contains('東'));
+        assert_eq!(SANS_13.glyph('東').c, '\u{fffd}');
+        assert_ne!(
+            draw("東").affected_area().size,
+            Size::zero(),
+            "nothing drawn for a missing glyph"
+        );
+    }
+
+    #[test]
+    fn widths_are_proportional() {
+        assert!(SANS_13.width("iiii") < SANS_13.width("WWWW"));
+        // What is measured is what is drawn
+        let display = draw("Köln");
+        let drawn = display.affected_area();
+        let width = SANS_13.width("Köln") as i32;
+        assert!(drawn.bottom_right().unwrap().x < width);
+        assert!(drawn.top_left.x >= 0);
+        // Wrapping works on the measured widths
+        let lines: std::vec::Vec<_> = text::wrap("Königsallee Düsseldorf", &SANS_13, 80)
+            .map(|line| line.text)
+            .collect();
+        assert_eq!(lines, ["Königsallee", "Düsseldorf"]);
+    }
+
+    #[test]
+    fn baselines_line_up_with_mono_text() {
+        let style = BitmapTextStyle::new(&SANS_13, Rgb565::WHITE);
+        let at = Point::new(0, 20);
+        let top = style
+            .measure_string("Ab", at, Baseline::Alphabetic)
+            .bounding_box
+            .top_left;
+        assert_eq!(top.y, 20 - SANS_13.baseline() as i32);
+        let next = style.measure_string("Ab", at, Baseline::Top).next_position;
+        assert_eq!(next, at + Point::new(SANS_13.width("Ab") as i32, 0));
+    }
+}
@@ -0,0 +1,6 @@
+//! Generated fonts
+//! Statics for the fonts listed in `fonts/fonts.txt`, made by the build script
+
+use super::font::{BitmapFont, Glyph};
+
+include!(concat!(env!("OUT_DIR"), "/fonts.rs"));
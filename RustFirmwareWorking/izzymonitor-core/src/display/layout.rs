@@ -0,0 +1,174 @@
+//! Screen layout
+//! Works out where the title, content and soft keys go from the screen size
+//!
+//! Every screen is a title bar, a framed content area and the soft-key bar.
+//! Inside the frame, areas are split into equal rows and columns; the
+//! leftover pixels of an uneven split go one each to the first parts.
+
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+
+/// Height of the title bar
+pub const TITLE_HEIGHT: u32 = 20;
+/// Height of the soft-key bar
+pub const SOFT_KEY_HEIGHT: u32 = 30;
+/// Space between the bars and the content frame
+pub const MARGIN: u32 = 5;
+/// Space between the content frame and what is in it
+pub const PADDING: u32 = 3;
+
+/// Areas of a screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScreenLayout {
+    /// Title bar across the top
+    pub title: Rectangle,
+    /// The frame around the content
+    pub frame: Rectangle,
+    /// Inside the frame, where content goes
+    pub content: Rectangle,
+    /// Soft-key bar across the bottom
+    pub keys: Rectangle,
+}
+
+impl ScreenLayout {
+    /// Layout of a screen of `size`
+    pub fn new(size: Size) -> Self {
+        Self::with_spacing(size, MARGIN, PADDING)
+    }
+
+    /// Layout of a screen of `size` with a theme's margin and padding
+    pub fn with_spacing(size: Size, margin: u32, padding: u32) -> Self {
+        let screen = Rectangle::new(Point::zero(), size);
+        let (title, rest) = split_top(screen, TITLE_HEIGHT);
+        let (body, keys) = split_bottom(rest, SOFT_KEY_HEIGHT);
+        let frame = inset(body, margin, margin);
+        Self {
+            title,
+            frame,
+            content: inset(frame, padding, padding),
+            keys,
+        }
+    }
+}
+
+/// Take `height` off the top of an area, returning it and the rest
+pub fn split_top(area: Rectangle, height: u32) -> (Rectangle, Rectangle) {
+    let height = height.min(area.size.height);
+    let top = Rectangle::new(area.top_left, Size::new(area.size.width, height));
+    let rest = Rectangle::new(
+        area.top_left + Point::new(0, height as i32),
+        Size::new(area.size.width, area.size.height - height),
+    );
+    (top, rest)
+}
+
+/// Take `height` off the bottom of an area, returning the rest and it
+pub fn split_bottom(area: Rectangle, height: u32) -> (Rectangle, Rectangle) {
+    let (rest, bottom) = split_top(area, area.size.height.saturating_sub(height));
+    (rest, bottom)
+}
+
+/// Take `width` off the right of an area, returning the rest and it
+pub fn split_right(area: Rectangle, width: u32) -> (Rectangle, Rectangle) {
+    let width = width.min(area.size.width);
+    let rest = Rectangle::new(
+        area.top_left,
+        Size::new(area.size.width - width, area.size.height),
+    );
+    let right = Rectangle::new(
+        area.top_left + Point::new(rest.size.width as i32, 0),
+        Size::new(width, area.size.height),
+    );
+    (rest, right)
+}
+
+/// An area shrunk by `x` on the left and right and `y` on the top and bottom
+pub fn inset(area: Rectangle, x: u32, y: u32) -> Rectangle {
+    Rectangle::new(
+        area.top_left + Point::new(x as i32, y as i32),
+        Size::new(
+            area.size.width.saturating_sub(2 * x),
+            area.size.height.saturating_sub(2 * y),
+        ),
+    )
+}
+
+/// An area of `size` in the middle of another
+pub fn centered(area: Rectangle, size: Size) -> Rectangle {
+    let size = size.component_min(area.size);
+    Rectangle::new(area.top_left + (area.size - size) / 2, size)
+}
+
+/// Start and length of part `i` of `n` of `length`
+fn part(length: u32, n: usize, i: usize) -> (u32, u32) {
+    let (n, i) = (n as u32, i as u32);
+    let start = |i: u32| i * (length / n) + i.min(length % n);
+    (start(i), start(i + 1) - start(i))
+}
+
+/// An area split into `N` rows, top to bottom
+pub fn rows<const N: usize>(area: Rectangle) -> [Rectangle; N] {
+    core::array::from_fn(|i| {
+        let (y, height) = part(area.size.height, N, i);
+        Rectangle::new(
+            area.top_left + Point::new(0, y as i32),
+            Size::new(area.size.width, height),
+        )
+    })
+}
+
+/// An area split into `N` columns, left to right
+pub fn columns<const N: usize>(area: Rectangle) -> [Rectangle; N] {
+    core::array::from_fn(|i| {
+        let (x, width) = part(area.size.width, N, i);
+        Rectangle::new(
+            area.top_left + Point::new(x as i32, 0),
+            Size::new(width, area.size.height),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    #[test]
+    fn screen_areas_fill_the_panel() {
+        let layout = ScreenLayout::new(Size::new(SCREEN_WIDTH, SCREEN_HEIGHT));
+        assert_eq!(
+            layout.title,
+            Rectangle::new(Point::zero(), Size::new(160, 20))
+        );
+        assert_eq!(
+            layout.keys,
+            Rectangle::new(Point::new(0, 98), Size::new(160, 30))
+        );
+        assert_eq!(
+            layout.frame,
+            Rectangle::new(Point::new(5, 25), Size::new(150, 68))
+        );
+        assert_eq!(
+            layout.content,
+            Rectangle::new(Point::new(8, 28), Size::new(144, 62))
+        );
+    }
+
+    #[test]
+    fn uneven_splits_cover_the_area() {
+        let area = Rectangle::new(Point::new(8, 28), Size::new(144, 62));
+        let parts: [Rectangle; 4] = rows(area);
+        assert_eq!(parts.map(|r| r.size.height), [16, 16, 15, 15]);
+        assert_eq!(parts[0].top_left, area.top_left);
+        assert_eq!(parts[3].bottom_right(), area.bottom_right());
+
+        let parts: [Rectangle; 6] = columns(Rectangle::new(Point::zero(), Size::new(160, 30)));
+        assert_eq!(parts.map(|r| r.size.width), [27, 27, 27, 27, 26, 26]);
+        assert_eq!(parts[5].top_left.x, 134);
+
+        assert_eq!(
+            centered(area, Size::new(40, 10)),
+            Rectangle::new(Point::new(60, 54), Size::new(40, 10))
+        );
+    }
+}
@@ -0,0 +1,482 @@
+//! Partial redraw
+//! Draws into a frame in RAM and pushes only the tiles that changed
+//!
+//! Screens are drawn whole, clear and all, which over SPI shows as flicker.
+//! Here they land in [`Frame`] instead, and [`PartialRedraw::flush`] sends
+//! the panel the 16×16 tiles that differ from what it already shows, joined
+//! into one window per run of tiles along a row.
+//!
+//! What the panel shows is kept as a 64 bit hash per tile rather than a
+//! second frame, which would cost another 40 KiB.
+
+use core::ops::Range;
+
+use embedded_graphics::pixelcolor::raw::RawU16;
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+
+use super::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// Side of a tile in pixels
+pub const TILE_SIZE: u32 = 16;
+
+const TILES_X: usize = (SCREEN_WIDTH / TILE_SIZE) as usize;
+const TILES_Y: usize = (SCREEN_HEIGHT / TILE_SIZE) as usize;
+const PIXELS: usize = (SCREEN_WIDTH * SCREEN_HEIGHT) as usize;
+
+/// The screen in RAM plus what is known to be on the panel
+pub struct Frame {
+    pixels: [Rgb565; PIXELS],
+    // Tiles drawn to since the last flush
+    touched: [[bool; TILES_X]; TILES_Y],
+    // Hash of every tile on the panel, `None` until it was pushed
+    shown: [[Option<u64>; TILES_X]; TILES_Y],
+}
+
+impl Frame {
+    /// A black frame, nothing known about the panel
+    ///
+    /// Big enough to belong in a static, not on a task's stack.
+    pub const fn new() -> Self {
+        Self {
+            pixels: [Rgb565::BLACK; PIXELS],
+            touched: [[false; TILES_X]; TILES_Y],
+            shown: [[None; TILES_X]; TILES_Y],
+        }
+    }
+
+    /// Color of a pixel as drawn
+    pub fn pixel(&self, point: Point) -> Option<Rgb565> {
+        bounds().contains(point).then(|| self.pixels[index(point)])
+    }
+
+    fn set(&mut self, point: Point, color: Rgb565) {
+        self.pixels[index(point)] = color;
+        self.touched[point.y as usize / TILE_SIZE as usize]
+            [point.x as usize / TILE_SIZE as usize] = true;
+    }
+
+    /// FNV-1a over a tile's pixels
+    fn hash(&self, tile_x: usize, tile_y: usize) -> u64 {
+        let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+        for point in tile(tile_x, tile_y).points() {
+            let raw = RawU16::from(self.pixels[index(point)]).into_inner();
+            for byte in raw.to_le_bytes() {
+                hash = (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+        hash
+    }
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn bounds() -> Rectangle {
+    Rectangle::new(Point::zero(), Size::new(SCREEN_WIDTH, SCREEN_HEIGHT))
+}
+
+fn index(point: Point) -> usize {
+    point.y as usize * SCREEN_WIDTH as usize + point.x as usize
+}
+
+fn tile(tile_x: usize, tile_y: usize) -> Rectangle {
+    Rectangle::new(
+        Point::new(
+            (tile_x as u32 * TILE_SIZE) as i32,
+            (tile_y as u32 * TILE_SIZE) as i32,
+        ),
+        Size::new(TILE_SIZE, TILE_SIZE),
+    )
+}
+
+/// Draw target that buffers in a [`Frame`] and flushes the changes to a panel
+pub struct PartialRedraw<'a, D> {
+    target: D,
+    frame: &'a mut Frame,
+}
+
+impl<'a, D> PartialRedraw<'a, D>
+where
+    D: DrawTarget<Color = Rgb565>,
+{
+    /// Buffer drawing for a panel, the first flush sends everything
+    pub fn new(target: D, frame: &'a mut Frame) -> Self {
+        frame.shown = [[None; TILES_X]; TILES_Y];
+        Self { target, frame }
+    }
+
+    /// Borrow the panel
+    pub fn target(&self) -> &D {
+        &self.target
+    }
+
+    /// Borrow the frame as drawn
+    pub fn frame(&self) -> &Frame {
+        self.frame
+    }
+
+    /// Forget what the panel shows, so the next flush sends everything
+    pub fn invalidate(&mut self) {
+        self.frame.shown = [[None; TILES_X]; TILES_Y];
+    }
+
+    /// Send the panel every changed tile
+    ///
+    /// If a window fails to go out the panel may hold anything, so the next
+    /// flush sends everything again.
+    pub fn flush(&mut self) -> Result<(), D::Error> {
+        self.flush_area(&bounds())
+    }
+
+    /// Send the panel the changed tiles that overlap `area`, the others wait
+    /// for a later flush
+    pub fn flush_area(&mut self, area: &Rectangle) -> Result<(), D::Error> {
+        let area = area.intersection(&bounds());
+        let Some(bottom_right) = area.bottom_right() else {
+            return Ok(());
+        };
+        let tiles = |from: i32, to: i32| {
+            from as usize / TILE_SIZE as usize..to as usize / TILE_SIZE as usize + 1
+        };
+        let columns = tiles(area.top_left.x, bottom_right.x);
+        let rows = tiles(area.top_left.y, bottom_right.y);
+
+        let sent = self.send_changes(columns, rows);
+        if sent.is_err() {
+            self.invalidate();
+        }
+        sent
+    }
+
+    fn send_changes(&mut self, columns: Range<usize>, rows: Range<usize>) -> Result<(), D::Error> {
+        for tile_y in rows {
+            // Start of the run of changed tiles being collected
+            let mut run: Option<usize> = None;
+            let mut hashes = [None; TILES_X];
+            for tile_x in columns.start..=columns.end {
+                let hash = (tile_x < columns.end)
+                    .then(|| self.changed(tile_x, tile_y))
+                    .flatten();
+                if let Some(hash) = hash {
+                    hashes[tile_x] = Some(hash);
+                    run.get_or_insert(tile_x);
+                } else if let Some(start) = run.take() {
+                    let width = (tile_x - start) as u32 * TILE_SIZE;
+                    let area =
+                        Rectangle::new(tile(start, tile_y).top_left, Size::new(width, TILE_SIZE));
+                    let pixels = area.points().map(|point| self.frame.pixels[index(point)]);
+                    self.target.fill_contiguous(&area, pixels)?;
+                    // Only now are they on the panel
+                    self.frame.shown[tile_y][start..tile_x].copy_from_slice(&hashes[start..tile_x]);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Hash of a tile that has to be sent, `None` if the panel shows it
+    fn changed(&mut self, tile_x: usize, tile_y: usize) -> Option<u64> {
+        let known = self.frame.shown[tile_y][tile_x];
+        if !core::mem::take(&mut self.frame.touched[tile_y][tile_x]) && known.is_some() {
+            return None;
+        }
+        let hash = self.frame.hash(tile_x, tile_y);
+        (known != Some(hash)).then_some(hash)
+    }
+
+    /// Give back the panel
+    pub fn release(self) -> D {
+        self.target
+    }
+}
+
+impl<D> OriginDimensions for PartialRedraw<'_, D> {
+    fn size(&self) -> Size {
+        Size::new(SCREEN_WIDTH, SCREEN_HEIGHT)
+    }
+}
+
+impl<D> DrawTarget for PartialRedraw<'_, D>
+where
+    D: DrawTarget<Color = Rgb565>,
+{
+    type Color = Rgb565;
+    type Error = D::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Rgb565>>,
+    {
+        let bounds = bounds();
+        for Pixel(point, color) in pixels {
+            if bounds.contains(point) {
+                self.frame.set(point, color);
+            }
+        }
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Rgb565) -> Result<(), Self::Error> {
+        for point in area.intersection(&bounds()).points() {
+            self.frame.set(point, color);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buttons::UP_KEY;
+    use crate::display::{layout, screen_wipe, ButtonLayout, Display};
+    use crate::menu::{Menu, Redraw, MAIN_BUTTONS};
+    use embassy_time::Instant;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    /// A panel that remembers every window it was sent
+    struct Panel {
+        pixels: Vec<Rgb565>,
+        pushed: Vec<Rectangle>,
+        // Fail every write, like a SPI error
+        broken: bool,
+    }
+
+    impl Panel {
+        fn new() -> Self {
+            Self {
+                pixels: std::vec![Rgb565::CSS_HOT_PINK; PIXELS],
+                pushed: Vec::new(),
+                broken: false,
+            }
+        }
+
+        /// Whether a pixel was part of any window sent
+        fn was_pushed(&self, point: Point) -> bool {
+            self.pushed.iter().any(|area| area.contains(point))
+        }
+    }
+
+    impl OriginDimensions for Panel {
+        fn size(&self) -> Size {
+            Size::new(SCREEN_WIDTH, SCREEN_HEIGHT)
+        }
+    }
+
+    impl DrawTarget for Panel {
+        type Color = Rgb565;
+        type Error = ();
+
+        fn draw_iter<I>(&mut self, _pixels: I) -> Result<(), Self::Error>
+        where
+            I: IntoIterator<Item = Pixel<Rgb565>>,
+        {
+            panic!("pixels have to go out in windows");
+        }
+
+        fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+        where
+            I: IntoIterator<Item = Rgb565>,
+        {
+            if self.broken {
+                return Err(());
+            }
+            for (point, color) in area.points().zip(colors) {
+                self.pixels[index(point)] = color;
+            }
+            self.pushed.push(*area);
+            Ok(())
+        }
+    }
+
+    fn main_screen(frame: &mut Frame) -> Display<PartialRedraw<'_, Panel>> {
+        let mut menu = Menu::new();
+        menu.finish_startup();
+        let mut display = Display::new(PartialRedraw::new(Panel::new(), frame));
+        display.draw_menu(&menu).unwrap();
+        display.flush().unwrap();
+        display
+    }
+
+    #[test]
+    fn first_flush_sends_everything() {
+        let mut frame = Box::new(Frame::new());
+        let display = main_screen(&mut frame);
+        let panel = display.target().target();
+        // One window per row of tiles
+        assert_eq!(panel.pushed.len(), TILES_Y);
+        assert!(bounds().points().all(|p| panel.was_pushed(p)));
+        assert!(bounds()
+            .points()
+            .all(|p| Some(panel.pixels[index(p)]) == display.target().frame().pixel(p)));
+    }
+
+    #[test]
+    fn moving_the_highlight_sends_only_the_first_two_keys() {
+        let mut frame = Box::new(Frame::new());
+        let mut display = main_screen(&mut frame);
+        display.target_mut().target.pushed.clear();
+
+        display
+            .draw_buttons(&ButtonLayout {
+                labels: MAIN_BUTTONS,
+                active_index: 1,
+            })
+            .unwrap();
+        display.flush().unwrap();
+
+        let panel = display.target().target();
+        // The indicator left key 1 for key 2, both under the first four
+        // columns of tiles in the bottom two rows
+        assert_eq!(
+            panel.pushed,
+            [
+                Rectangle::new(Point::new(0, 96), Size::new(64, 16)),
+                Rectangle::new(Point::new(0, 112), Size::new(64, 16)),
+            ]
+        );
+        assert!(panel.was_pushed(Point::new(40, 106)));
+        assert!(!panel.was_pushed(Point::new(100, 110)));
+        assert!(!panel.was_pushed(Point::new(80, 40)));
+    }
+
+    #[test]
+    fn redrawing_the_same_screen_sends_nothing() {
+        let mut frame = Box::new(Frame::new());
+        let mut display = main_screen(&mut frame);
+        display.target_mut().target.pushed.clear();
+
+        let mut menu = Menu::new();
+        menu.finish_startup();
+        display.draw_menu(&menu).unwrap();
+        display.flush().unwrap();
+        assert!(display.target().target().pushed.is_empty());
+
+        // Unless the panel lost its contents
+        display.target_mut().invalidate();
+        display.flush().unwrap();
+        assert_eq!(display.target().target().pushed.len(), TILES_Y);
+    }
+
+    #[test]
+    fn failed_write_is_sent_again() {
+        let mut frame = Box::new(Frame::new());
+        let mut display = main_screen(&mut frame);
+        let mut menu = Menu::new();
+        menu.finish_startup();
+        menu.press(1);
+        display.draw_menu(&menu).unwrap();
+        display.target_mut().target.broken = true;
+        assert!(display.flush().is_err());
+
+        // Nothing was taken as shown, the whole screen goes out again
+        display.target_mut().target.broken = false;
+        display.target_mut().target.pushed.clear();
+        display.flush().unwrap();
+        let panel = display.target().target();
+        assert_eq!(panel.pushed.len(), TILES_Y);
+        assert!(bounds()
+            .points()
+            .all(|p| Some(panel.pixels[index(p)]) == display.target().frame().pixel(p)));
+    }
+
+    #[test]
+    fn brightness_step_sends_only_its_row() {
+        let mut frame = Box::new(Frame::new());
+        let mut display = main_screen(&mut frame);
+        let mut menu = Menu::new();
+        menu.finish_startup();
+        assert_eq!(menu.press(2), Redraw::Screen);
+        display.draw_menu(&menu).unwrap();
+        display.flush().unwrap();
+        display.target_mut().target.pushed.clear();
+
+        // An edit in place, the whole screen is drawn again
+        assert_eq!(menu.press(UP_KEY), Redraw::Content);
+        display.draw_menu(&menu).unwrap();
+        display.flush().unwrap();
+
+        let panel = display.target().target();
+        let screen = display.layout();
+        let [_, light, ..] = layout::rows::<4>(screen.content);
+        // The tiles the Light row's text reaches into, and no others
+        assert!(!panel.pushed.is_empty());
+        for area in &panel.pushed {
+            assert!(area.intersection(&light).size != Size::zero(), "{area:?}");
+        }
+        assert!(panel.pushed.len() <= 2);
+        assert!(!panel.was_pushed(screen.title.center()));
+        assert!(!panel.was_pushed(screen.keys.center()));
+    }
+
+    #[test]
+    fn wipe_sends_each_changed_tile_once() {
+        let mut frame = Box::new(Frame::new());
+        let mut display = main_screen(&mut frame);
+        display.target_mut().target.pushed.clear();
+
+        let mut menu = Menu::new();
+        menu.finish_startup();
+        menu.press(2);
+        display.draw_menu(&menu).unwrap();
+        let wipe = screen_wipe(Instant::from_millis(0));
+        let edge = wipe.value_at(Instant::from_millis(40)).unwrap();
+        display.flush_wipe(edge).unwrap();
+
+        // Nothing right of the edge's tile yet
+        let limit = (edge as u32).div_ceil(TILE_SIZE) * TILE_SIZE;
+        let panel = &display.target().target().pushed;
+        assert!(!panel.is_empty());
+        assert!(panel
+            .iter()
+            .all(|area| area.bottom_right().unwrap().x < limit as i32));
+        assert!(!display.target().target().was_pushed(Point::new(150, 60)));
+
+        for t in (56..=200).step_by(16) {
+            let edge = wipe.value_at(Instant::from_millis(t)).unwrap();
+            display.flush_wipe(edge).unwrap();
+        }
+        display.flush().unwrap();
+        let panel = display.target().target();
+        // Every pixel went out at most once, and the panel shows the new screen
+        for point in bounds().points() {
+            let windows = panel.pushed.iter().filter(|a| a.contains(point)).count();
+            assert!(windows <= 1, "{point:?}");
+            assert_eq!(
+                Some(panel.pixels[index(point)]),
+                display.target().frame().pixel(point)
+            );
+        }
+    }
+
+    #[test]
+    fn screen_change_sends_what_differs() {
+        let mut frame = Box::new(Frame::new());
+        let mut display = main_screen(&mut frame);
+        display.target_mut().target.pushed.clear();
+        let before: Vec<Rgb565> = display.target().target().pixels.clone();
+
+        let mut menu = Menu::new();
+        menu.finish_startup();
+        menu.press(1);
+        display.draw_menu(&menu).unwrap();
+        display.flush().unwrap();
+
+        let panel = display.target().target();
+        for point in bounds().points() {
+            let drawn = display.target().frame().pixel(point).unwrap();
+            // Every change made it out, and the panel matches the frame
+            if drawn != before[index(point)] {
+                assert!(panel.was_pushed(point), "{point:?}");
+            }
+            assert_eq!(panel.pixels[index(point)], drawn);
+        }
+        // The box's left edge is the same on both screens
+        assert!(!panel.was_pushed(Point::new(5, 85)));
+    }
+}
@@ -0,0 +1,319 @@
+//! Text layout
+//! Word wrapping, hyphenation and ellipsis for text longer than a line
+//!
+//! Lines break after spaces, which are dropped, and after hyphens, which stay.
+//! A word too long for a line of its own is split with a hyphen added. Lines
+//! are produced lazily as slices of the text, so a long response is laid out
+//! without copying it.
+
+use embedded_graphics::mono_font::MonoFont;
+
+/// Appended to text cut short
+pub const ELLIPSIS: &str = "...";
+
+/// Sizes of a font's glyphs
+pub trait TextMetrics {
+    /// Width of a glyph
+    fn char_width(&self, c: char) -> u32;
+
+    /// Space between glyphs
+    fn spacing(&self) -> u32;
+
+    /// Distance from one line to the next
+    fn line_height(&self) -> u32;
+
+    /// Width of `text` on one line
+    fn width(&self, text: &str) -> u32 {
+        let mut chars = text.chars();
+        let first = chars.next().map_or(0, |c| self.char_width(c));
+        chars.fold(first, |width, c| {
+            width + self.spacing() + self.char_width(c)
+        })
+    }
+
+    /// Width of `text` with `suffix` drawn right after it
+    fn width_with(&self, text: &str, suffix: &str) -> u32 {
+        match (self.width(text), self.width(suffix)) {
+            (0, width) | (width, 0) => width,
+            (text, suffix) => text + self.spacing() + suffix,
+        }
+    }
+}
+
+impl TextMetrics for MonoFont<'_> {
+    fn char_width(&self, _c: char) -> u32 {
+        self.character_size.width
+    }
+
+    fn spacing(&self) -> u32 {
+        self.character_spacing
+    }
+
+    fn line_height(&self) -> u32 {
+        self.character_size.height
+    }
+}
+
+/// One line of wrapped text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Line<'a> {
+    /// The text on the line
+    pub text: &'a str,
+    /// Whether a hyphen has to be drawn after it, the word goes on below
+    pub hyphen: bool,
+}
+
+/// Lines of `text` no wider than `width`, `\n` starts a new line
+pub fn wrap<'a, M: TextMetrics>(text: &'a str, metrics: &'a M, width: u32) -> Wrap<'a, M> {
+    Wrap {
+        rest: text,
+        done: text.is_empty(),
+        metrics,
+        width,
+    }
+}
+
+/// Iterator over wrapped lines, from [`wrap`]
+#[derive(Debug, Clone)]
+pub struct Wrap<'a, M> {
+    rest: &'a str,
+    done: bool,
+    metrics: &'a M,
+    width: u32,
+}
+
+impl<'a, M: TextMetrics> Wrap<'a, M> {
+    /// Take the start of a paragraph that fits, returning it and the bytes used
+    fn fit(&self, paragraph: &'a str) -> (Line<'a>, usize) {
+        let fits = |text: &str| self.metrics.width(text) <= self.width;
+        if fits(paragraph) {
+            let line = Line {
+                text: paragraph.trim_end_matches(' '),
+                hyphen: false,
+            };
+            return (line, paragraph.len());
+        }
+
+        // Last place to break that still fits
+        let mut best = None;
+        for (i, c) in paragraph.char_indices() {
+            let (text, used) = match c {
+                ' ' => (paragraph[..i].trim_end_matches(' '), i),
+                '-' => (&paragraph[..=i], i + 1),
+                _ => continue,
+            };
+            if !fits(text) {
+                break;
+            }
+            if !text.is_empty() {
+                best = Some((text, used));
+            }
+        }
+        if let Some((text, used)) = best {
+            return (
+                Line {
+                    text,
+                    hyphen: false,
+                },
+                used,
+            );
+        }
+
+        // A word too long for any line, hyphenated where there is room
+        let mut end = 0;
+        let mut hyphenated = 0;
+        for (i, c) in paragraph.char_indices() {
+            let next = i + c.len_utf8();
+            if !fits(&paragraph[..next]) {
+                break;
+            }
+            end = next;
+            if self.metrics.width_with(&paragraph[..next], "-") <= self.width {
+                hyphenated = next;
+            }
+        }
+        let alphanumeric = |text: &str, last: bool| {
+            let c = if last {
+                text.chars().next_back()
+            } else {
+                text.chars().next()
+            };
+            c.is_some_and(char::is_alphanumeric)
+        };
+        let split = &paragraph[..hyphenated];
+        if split.chars().count() >= 2
+            && alphanumeric(split, true)
+            && alphanumeric(&paragraph[hyphenated..], false)
+        {
+            let line = Line {
+                text: split,
+                hyphen: true,
+            };
+            return (line, hyphenated);
+        }
+        // Too narrow for even that: at least one character per line
+        let end = if end == 0 {
+            paragraph.chars().next().map_or(0, char::len_utf8)
+        } else {
+            end
+        };
+        let line = Line {
+            text: &paragraph[..end],
+            hyphen: false,
+        };
+        (line, end)
+    }
+}
+
+impl<'a, M: TextMetrics> Iterator for Wrap<'a, M> {
+    type Item = Line<'a>;
+
+    fn next(&mut self) -> Option<Line<'a>> {
+        if self.done {
+            return None;
+        }
+        let (paragraph, after) = match self.rest.split_once('\n') {
+            Some((paragraph, after)) => (paragraph.trim_end_matches('\r'), Some(after)),
+            None => (self.rest, None),
+        };
+        let trimmed = paragraph.trim_start_matches(' ');
+        let (line, used) = self.fit(trimmed);
+        let remaining = trimmed[used..].trim_start_matches(' ');
+
+        if !remaining.is_empty() {
+            // Carry on from where the line left off in the same paragraph
+            let start = remaining.as_ptr() as usize - self.rest.as_ptr() as usize;
+            self.rest = &self.rest[start..];
+        } else {
+            match after {
+                // A newline at the very end starts no line
+                Some(after) if !after.is_empty() => self.rest = after,
+                _ => self.done = true,
+            }
+        }
+        Some(line)
+    }
+}
+
+/// The start of `text` that fits `width` with [`ELLIPSIS`] after it, and
+/// whether the ellipsis is needed
+pub fn ellipsize<'a, M: TextMetrics>(text: &'a str, metrics: &M, width: u32) -> (&'a str, bool) {
+    if metrics.width(text) <= width {
+        return (text, false);
+    }
+    let mut end = 0;
+    for (i, c) in text.char_indices() {
+        let next = i + c.len_utf8();
+        if metrics.width_with(&text[..next], ELLIPSIS) > width {
+            break;
+        }
+        end = next;
+    }
+    (text[..end].trim_end_matches(' '), true)
+}
+
+/// Number of lines `text` wraps to
+pub fn line_count<M: TextMetrics>(text: &str, metrics: &M, width: u32) -> usize {
+    wrap(text, metrics, width).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::mono_font::ascii::{FONT_6X10, FONT_8X13};
+    use std::vec::Vec;
+
+    fn lines<'a>(text: &'a str, font: &'a MonoFont<'a>, chars: u32) -> Vec<&'a str> {
+        let width = chars * font.character_size.width;
+        wrap(text, font, width)
+            .inspect(|line| assert!(font.width_with(line.text, "-") <= width || !line.hyphen))
+            .map(|line| line.text)
+            .collect()
+    }
+
+    #[test]
+    fn wraps_at_spaces_to_the_font_width() {
+        let text = "Take the U2 towards Pankow and change at Alexanderplatz";
+        // 144 pixels of content: 24 characters of 6×10, 18 of 8×13
+        assert_eq!(
+            lines(text, &FONT_6X10, 24),
+            [
+                "Take the U2 towards",
+                "Pankow and change at",
+                "Alexanderplatz"
+            ]
+        );
+        assert_eq!(
+            lines(text, &FONT_8X13, 18),
+            [
+                "Take the U2",
+                "towards Pankow and",
+                "change at",
+                "Alexanderplatz"
+            ]
+        );
+        for line in wrap(text, &FONT_8X13, 144) {
+            assert!(FONT_8X13.width(line.text) <= 144);
+            assert!(!line.text.starts_with(' ') && !line.text.ends_with(' '));
+        }
+    }
+
+    #[test]
+    fn keeps_newlines_and_blank_lines() {
+        assert_eq!(
+            lines("Route ready\n\nLeave at 8:15\n", &FONT_6X10, 24),
+            ["Route ready", "", "Leave at 8:15"]
+        );
+        assert_eq!(lines("", &FONT_6X10, 24), Vec::<&str>::new());
+        assert_eq!(
+            lines("Exit here\r\nthen left", &FONT_6X10, 6),
+            ["Exit", "here", "then", "left"]
+        );
+    }
+
+    #[test]
+    fn breaks_after_hyphens_and_hyphenates_long_words() {
+        // An existing hyphen is the place to break
+        assert_eq!(
+            lines("Via Frankfurt-Niederrad", &FONT_6X10, 18),
+            ["Via Frankfurt-", "Niederrad"]
+        );
+
+        // A word longer than the line gets a hyphen of its own
+        let wrapped: Vec<Line<'_>> = wrap("Donaudampfschifffahrt", &FONT_8X13, 80).collect();
+        assert_eq!(
+            wrapped,
+            [
+                Line {
+                    text: "Donaudamp",
+                    hyphen: true
+                },
+                Line {
+                    text: "fschifffa",
+                    hyphen: true
+                },
+                Line {
+                    text: "hrt",
+                    hyphen: false
+                },
+            ]
+        );
+
+        // Too narrow for a hyphen: one character per line, never stuck
+        assert_eq!(lines("abc", &FONT_6X10, 1), ["a", "b", "c"]);
+        assert_eq!(wrap("ab", &FONT_6X10, 0).count(), 2);
+    }
+
+    #[test]
+    fn ellipsizes_what_does_not_fit() {
+        assert_eq!(ellipsize("Ready", &FONT_6X10, 30), ("Ready", false));
+        // Five characters of room: two and the ellipsis
+        assert_eq!(ellipsize("Alexanderplatz", &FONT_6X10, 30), ("Al", true));
+        assert_eq!(
+            ellipsize("Alexanderplatz", &FONT_8X13, 80),
+            ("Alexand", true)
+        );
+        // No space left dangling before the ellipsis
+        assert_eq!(ellipsize("U2 to Pankow", &FONT_6X10, 36), ("U2", true));
+    }
+}
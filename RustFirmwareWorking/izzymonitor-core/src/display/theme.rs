@@ -0,0 +1,291 @@
+//! Themes
+//! The colors, fonts and spacing every screen and widget is drawn with
+//!
+//! There are three themes to choose from in Settings. An agent personality
+//! can bring its own accent color, which recolors the bars, selection and
+//! highlights of the dark and light themes; the high-contrast theme keeps its
+//! own colors, as it is chosen to be readable.
+
+use core::fmt;
+
+use embedded_graphics::{
+    mono_font::ascii::{FONT_6X10, FONT_8X13, FONT_8X13_BOLD},
+    pixelcolor::{Gray8, Rgb565},
+    prelude::*,
+};
+
+use super::font::Font;
+use super::fonts::SANS_13;
+use super::layout::{MARGIN, PADDING};
+use super::rgb565;
+use crate::leds::{colors, KeyHint, LedFrame, RgbColor as LedColor};
+use crate::KEY_COUNT;
+
+/// Colors, fonts and spacing of the screens
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    /// Behind everything
+    pub background: Rgb565,
+    /// Body text
+    pub text: Rgb565,
+    /// Title bar, soft-key bar and scroll bar track
+    pub button: Rgb565,
+    /// Text on the bars, and the active soft key's outline
+    pub button_text: Rgb565,
+    /// Selected list row and toasts
+    pub button_active: Rgb565,
+    /// Text on a selected row or toast
+    pub active_text: Rgb565,
+    /// Box outlines
+    pub border: Rgb565,
+    /// The item being edited, progress and scroll bar thumbs
+    pub highlight: Rgb565,
+    /// Body text font
+    pub font: Font,
+    /// Font of the soft keys, toasts and dense screens
+    pub small_font: Font,
+    /// Running text such as route descriptions, proportional and with the
+    /// accents and scripts street names come in
+    pub reading_font: Font,
+    /// Space between the bars and the content frame
+    pub margin: u32,
+    /// Space between a frame and what is in it
+    pub padding: u32,
+    /// LED color of a screen's main key
+    pub primary_key: LedColor,
+}
+
+impl Theme {
+    /// Green on black
+    pub const DARK: Theme = Theme {
+        background: Rgb565::BLACK,
+        text: Rgb565::new(31, 63, 31),
+        button: Rgb565::new(10, 20, 10),
+        button_text: Rgb565::WHITE,
+        button_active: Rgb565::new(20, 40, 20),
+        active_text: Rgb565::WHITE,
+        border: Rgb565::new(15, 30, 15),
+        highlight: Rgb565::new(31, 50, 20),
+        font: Font::Mono(&FONT_8X13),
+        small_font: Font::Mono(&FONT_6X10),
+        reading_font: Font::Bitmap(&SANS_13),
+        margin: MARGIN,
+        padding: PADDING,
+        primary_key: colors::DIM_GREEN,
+    };
+
+    /// Dark green on white, for bright rooms
+    pub const LIGHT: Theme = Theme {
+        background: Rgb565::WHITE,
+        text: Rgb565::new(2, 10, 3),
+        button: Rgb565::new(22, 52, 22),
+        button_text: Rgb565::new(2, 10, 3),
+        button_active: Rgb565::new(6, 26, 8),
+        active_text: Rgb565::WHITE,
+        border: Rgb565::new(10, 30, 12),
+        highlight: Rgb565::new(3, 36, 6),
+        font: Font::Mono(&FONT_8X13),
+        small_font: Font::Mono(&FONT_6X10),
+        reading_font: Font::Bitmap(&SANS_13),
+        margin: MARGIN,
+        padding: PADDING,
+        primary_key: colors::DIM_GREEN,
+    };
+
+    /// White and yellow on black, bold text
+    pub const HIGH_CONTRAST: Theme = Theme {
+        background: Rgb565::BLACK,
+        text: Rgb565::WHITE,
+        button: Rgb565::new(0, 0, 20),
+        button_text: Rgb565::WHITE,
+        button_active: Rgb565::YELLOW,
+        active_text: Rgb565::BLACK,
+        border: Rgb565::WHITE,
+        highlight: Rgb565::YELLOW,
+        font: Font::Mono(&FONT_8X13_BOLD),
+        small_font: Font::Mono(&FONT_6X10),
+        reading_font: Font::Bitmap(&SANS_13),
+        margin: MARGIN,
+        padding: PADDING,
+        primary_key: colors::DIM_YELLOW,
+    };
+
+    /// Recolor the bars, selection and highlights in an accent color
+    pub fn with_accent(mut self, accent: LedColor) -> Self {
+        // Dark backgrounds take shades of the accent, light ones tints
+        let light = luma(self.background) > luma(self.text);
+        let shade = |level| rgb565(accent.scale(level));
+        let tint = |level| rgb565(tint(accent, level));
+        if light {
+            self.button = tint(180);
+            self.button_active = shade(110);
+            self.border = shade(150);
+            self.highlight = shade(120);
+        } else {
+            self.button = shade(80);
+            self.button_active = shade(160);
+            self.border = shade(120);
+            self.highlight = tint(100);
+        }
+        self.primary_key = accent.scale(32);
+        self
+    }
+
+    /// LED color of a key hint
+    pub fn hint_color(&self, hint: KeyHint) -> LedColor {
+        match hint {
+            KeyHint::Primary => self.primary_key,
+            hint => hint.color(),
+        }
+    }
+
+    /// LED colors of the hints of every key
+    pub fn hint_frame(&self, hints: &[KeyHint; KEY_COUNT]) -> LedFrame {
+        hints.map(|hint| self.hint_color(hint))
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::DARK
+    }
+}
+
+/// Brightness of a panel color
+fn luma(color: Rgb565) -> u8 {
+    Gray8::from(color).luma()
+}
+
+/// `color` mixed with white, 255 is all white
+fn tint(color: LedColor, level: u8) -> LedColor {
+    let mix = |c: u8| c + ((u16::from(255 - c) * u16::from(level)) / 255) as u8;
+    LedColor::new(mix(color.r), mix(color.g), mix(color.b))
+}
+
+/// The themes to choose from in Settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ThemeId {
+    #[default]
+    Dark,
+    Light,
+    HighContrast,
+}
+
+impl ThemeId {
+    /// Every theme, in the order Settings steps through them
+    pub const ALL: [ThemeId; 3] = [ThemeId::Dark, ThemeId::Light, ThemeId::HighContrast];
+
+    /// The theme's colors, fonts and spacing
+    pub fn theme(self) -> Theme {
+        match self {
+            ThemeId::Dark => Theme::DARK,
+            ThemeId::Light => Theme::LIGHT,
+            ThemeId::HighContrast => Theme::HIGH_CONTRAST,
+        }
+    }
+
+    /// Short name, as shown in Settings
+    pub fn name(self) -> &'static str {
+        match self {
+            ThemeId::Dark => "Dark",
+            ThemeId::Light => "Light",
+            ThemeId::HighContrast => "Contrast",
+        }
+    }
+
+    /// The theme in a personality's accent color, high contrast stays as it is
+    pub fn with_accent(self, accent: Option<LedColor>) -> Theme {
+        match (self, accent) {
+            (ThemeId::HighContrast, _) | (_, None) => self.theme(),
+            (_, Some(accent)) => self.theme().with_accent(accent),
+        }
+    }
+
+    /// The theme after this one, wrapping around
+    pub fn next(self) -> ThemeId {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+
+    /// Serialize for storage
+    pub fn to_byte(self) -> u8 {
+        self as u8
+    }
+
+    /// Deserialize from storage
+    pub fn from_byte(byte: u8) -> Result<Self, &'static str> {
+        Self::ALL
+            .get(usize::from(byte))
+            .copied()
+            .ok_or("Unknown theme")
+    }
+}
+
+impl fmt::Display for ThemeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::text::TextMetrics;
+
+    #[test]
+    fn ids_step_and_round_trip() {
+        let mut id = ThemeId::default();
+        for expected in [ThemeId::Light, ThemeId::HighContrast, ThemeId::Dark] {
+            id = id.next();
+            assert_eq!(id, expected);
+            assert_eq!(ThemeId::from_byte(id.to_byte()), Ok(id));
+        }
+        assert_eq!(ThemeId::from_byte(3), Err("Unknown theme"));
+    }
+
+    #[test]
+    fn themes_keep_the_layout() {
+        // Every screen is laid out for the 8×13 and 6×10 metrics
+        for id in ThemeId::ALL {
+            let theme = id.theme();
+            assert_eq!(theme.font.line_height(), 13, "{id}");
+            assert_eq!(theme.font.width("Keys"), 32, "{id}");
+            assert_eq!(theme.small_font.line_height(), 10, "{id}");
+            assert_ne!(theme.text, theme.background, "{id}");
+            assert_ne!(theme.active_text, theme.button_active, "{id}");
+        }
+    }
+
+    #[test]
+    fn themes_read_street_names() {
+        let route = "Über Königsallee – Łódź, Αθήνα, Москва";
+        for id in ThemeId::ALL {
+            let Font::Bitmap(font) = id.theme().reading_font else {
+                panic!("{id} reads in a mono font");
+            };
+            assert!(route.chars().all(|c| c == ' ' || font.contains(c)), "{id}");
+        }
+    }
+
+    #[test]
+    fn accents_follow_the_background() {
+        let purple = LedColor::new(160, 0, 255);
+        let dark = Theme::DARK.with_accent(purple);
+        assert_eq!(dark.button, rgb565(purple.scale(80)));
+        assert!(luma(dark.highlight) > luma(dark.button));
+        assert_eq!(dark.text, Theme::DARK.text);
+
+        // On white the bars get lighter, not darker, than the selection
+        let light = Theme::LIGHT.with_accent(purple);
+        assert!(luma(light.button) > luma(light.button_active));
+        assert_eq!(light.background, Rgb565::WHITE);
+        let contrast = ThemeId::HighContrast.with_accent(Some(purple));
+        assert_eq!(contrast.highlight, Theme::HIGH_CONTRAST.highlight);
+
+        assert_eq!(dark.hint_color(KeyHint::Primary), purple.scale(32));
+        assert_eq!(dark.hint_color(KeyHint::Back), colors::DIM_BLUE);
+        assert_eq!(
+            Theme::DARK.hint_frame(&[KeyHint::Primary; KEY_COUNT]),
+            [colors::DIM_GREEN; KEY_COUNT]
+        );
+    }
+}
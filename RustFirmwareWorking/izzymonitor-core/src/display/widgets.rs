@@ -0,0 +1,1231 @@
+//! Widgets
+//! Retained-mode building blocks for screens
+//!
+//! A widget owns its area and state and is drawn like any other
+//! embedded-graphics drawable, so a screen changes a widget and redraws only
+//! that one. Text that doesn't fit its area ends in an ellipsis. Colors,
+//! fonts and spacing come from a [`Theme`], the dark one unless told otherwise.
+
+use embassy_time::{Duration, Instant};
+use embedded_graphics::{
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::{
+        Circle, CornerRadii, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle, RoundedRectangle,
+    },
+    text::Alignment,
+};
+use heapless::{String, Vec};
+
+use super::font::Font;
+use super::layout;
+use super::text::{self, TextMetrics};
+use super::theme::Theme;
+use super::ButtonLayout;
+use crate::keymap::Action;
+use crate::KEY_COUNT;
+
+/// Longest label text kept
+pub const LABEL_LEN: usize = 32;
+/// Longest list item kept
+pub const ITEM_LEN: usize = 24;
+/// Most lines in a modal's message
+pub const MODAL_LINES: usize = 4;
+
+/// Width of a list's scroll bar
+const SCROLL_BAR_WIDTH: u32 = 3;
+/// Space above and below a list row's text
+const ROW_GAP: u32 = 2;
+
+/// `text` cut to whole characters within `N` bytes
+fn fitted<const N: usize>(text: &str) -> String<N> {
+    let mut owned = String::new();
+    for c in text.chars() {
+        if owned.push(c).is_err() {
+            break;
+        }
+    }
+    owned
+}
+
+/// Draw `text` and then `suffix` on one line in `area`, nothing if it won't fit
+fn draw_line<D>(
+    target: &mut D,
+    text: &str,
+    suffix: &str,
+    area: Rectangle,
+    font: Font,
+    color: Rgb565,
+    alignment: Alignment,
+) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = Rgb565>,
+{
+    let width = font.width_with(text, suffix);
+    if width == 0 || width > area.size.width {
+        return Ok(());
+    }
+    let x = match alignment {
+        Alignment::Left => 0,
+        Alignment::Center => (area.size.width - width) / 2,
+        Alignment::Right => area.size.width - width,
+    };
+    let y = area.size.height.saturating_sub(font.line_height()) / 2;
+    let mut position = area.top_left + Point::new(x as i32, y as i32);
+    for part in [text, suffix] {
+        if !part.is_empty() {
+            position = font.draw(part, position, color, target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Draw `text` in `area`, ending in an ellipsis if it doesn't fit
+fn draw_text<D>(
+    target: &mut D,
+    text: &str,
+    area: Rectangle,
+    font: Font,
+    color: Rgb565,
+    alignment: Alignment,
+) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = Rgb565>,
+{
+    let (text, cut) = text::ellipsize(text, &font, area.size.width);
+    let suffix = if cut { text::ELLIPSIS } else { "" };
+    draw_line(target, text, suffix, area, font, color, alignment)
+}
+
+/// Scroll bar down the right of `area` for `shown` of `total` rows from `first`
+fn draw_scroll_bar<D>(
+    target: &mut D,
+    theme: &Theme,
+    area: Rectangle,
+    first: usize,
+    shown: usize,
+    total: usize,
+) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = Rgb565>,
+{
+    let (_, track) = layout::split_right(area, SCROLL_BAR_WIDTH);
+    target.fill_solid(&track, theme.button)?;
+    // Thumb as long and as far down as the rows shown
+    let (height, shown, total) = (track.size.height, shown as u32, total as u32);
+    let length = (height * shown / total).max(2);
+    let top = (height - length) * first as u32 / (total - shown);
+    let thumb = Rectangle::new(
+        track.top_left + Point::new(0, top as i32),
+        Size::new(SCROLL_BAR_WIDTH, length),
+    );
+    target.fill_solid(&thumb, theme.highlight)
+}
+
+/// A line of text in an area
+#[derive(Debug, Clone)]
+pub struct Label {
+    area: Rectangle,
+    text: String<LABEL_LEN>,
+    // The theme's unless set
+    font: Option<Font>,
+    color: Option<Rgb565>,
+    background: Option<Rgb565>,
+    alignment: Alignment,
+    theme: Theme,
+}
+
+impl Label {
+    /// Left-aligned body text, drawn over whatever is there
+    pub fn new(area: Rectangle, text: &str) -> Self {
+        Self {
+            area,
+            text: fitted(text),
+            font: None,
+            color: None,
+            background: None,
+            alignment: Alignment::Left,
+            theme: Theme::DARK,
+        }
+    }
+
+    /// Draw in another theme
+    pub fn with_theme(mut self, theme: &Theme) -> Self {
+        self.theme = *theme;
+        self
+    }
+
+    /// Use another font than the theme's
+    pub fn with_font(mut self, font: impl Into<Font>) -> Self {
+        self.font = Some(font.into());
+        self
+    }
+
+    /// Use another text color than the theme's
+    pub fn with_color(mut self, color: Rgb565) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    /// Fill the area first, so new text fully replaces the old
+    pub fn with_background(mut self, color: Rgb565) -> Self {
+        self.background = Some(color);
+        self
+    }
+
+    /// Align the text within the area
+    pub fn with_alignment(mut self, alignment: Alignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    /// The text shown
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Change the text, returning whether it needs a redraw
+    pub fn set_text(&mut self, text: &str) -> bool {
+        let text = fitted(text);
+        let changed = text != self.text;
+        self.text = text;
+        changed
+    }
+
+    /// Change the text color, returning whether it needs a redraw
+    pub fn set_color(&mut self, color: Rgb565) -> bool {
+        let changed = Some(color) != self.color;
+        self.color = Some(color);
+        changed
+    }
+}
+
+impl Drawable for Label {
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        if let Some(background) = self.background {
+            target.fill_solid(&self.area, background)?;
+        }
+        draw_text(
+            target,
+            &self.text,
+            self.area,
+            self.font.unwrap_or(self.theme.font),
+            self.color.unwrap_or(self.theme.text),
+            self.alignment,
+        )
+    }
+}
+
+/// A list of items, one selected, scrolling to keep it in view
+#[derive(Debug, Clone)]
+pub struct ScrollList<const N: usize> {
+    area: Rectangle,
+    items: Vec<String<ITEM_LEN>, N>,
+    selected: usize,
+    // First item shown
+    offset: usize,
+    font: Option<Font>,
+    theme: Theme,
+}
+
+impl<const N: usize> ScrollList<N> {
+    /// An empty list
+    pub fn new(area: Rectangle) -> Self {
+        Self {
+            area,
+            items: Vec::new(),
+            selected: 0,
+            offset: 0,
+            font: None,
+            theme: Theme::DARK,
+        }
+    }
+
+    /// Draw in another theme
+    pub fn with_theme(mut self, theme: &Theme) -> Self {
+        self.theme = *theme;
+        self
+    }
+
+    /// Use another font than the theme's, smaller fits more rows
+    pub fn with_font(mut self, font: impl Into<Font>) -> Self {
+        self.font = Some(font.into());
+        self
+    }
+
+    /// Add an item at the end
+    pub fn push(&mut self, item: &str) -> Result<(), &'static str> {
+        self.items.push(fitted(item)).map_err(|_| "List full")
+    }
+
+    /// Remove every item
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.selected = 0;
+        self.offset = 0;
+    }
+
+    /// Number of items
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Whether there are no items
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Index of the selected item
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// The selected item, `None` when empty
+    pub fn selected_item(&self) -> Option<&str> {
+        self.items.get(self.selected).map(String::as_str)
+    }
+
+    /// Index of the first item shown
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    fn font(&self) -> Font {
+        self.font.unwrap_or(self.theme.font)
+    }
+
+    /// Height of a row
+    fn row_height(&self) -> u32 {
+        self.font().line_height() + 2 * ROW_GAP
+    }
+
+    /// How many rows fit the area
+    pub fn visible_rows(&self) -> usize {
+        ((self.area.size.height / self.row_height()) as usize).max(1)
+    }
+
+    /// Select an item, scrolling it into view, returning whether it changed
+    pub fn select(&mut self, index: usize) -> bool {
+        if index >= self.items.len() || index == self.selected {
+            return false;
+        }
+        self.selected = index;
+        let rows = self.visible_rows();
+        if index < self.offset {
+            self.offset = index;
+        } else if index >= self.offset + rows {
+            self.offset = index + 1 - rows;
+        }
+        true
+    }
+
+    /// Select the next item, returning whether it changed
+    pub fn select_next(&mut self) -> bool {
+        self.select(self.selected + 1)
+    }
+
+    /// Select the previous item, returning whether it changed
+    pub fn select_previous(&mut self) -> bool {
+        self.selected > 0 && self.select(self.selected - 1)
+    }
+}
+
+impl<const N: usize> Drawable for ScrollList<N> {
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        let theme = &self.theme;
+        target.fill_solid(&self.area, theme.background)?;
+
+        let rows = self.visible_rows();
+        let scrolls = self.items.len() > rows;
+        let text_width = if scrolls {
+            self.area.size.width.saturating_sub(SCROLL_BAR_WIDTH + 1)
+        } else {
+            self.area.size.width
+        };
+
+        for (row, item) in self.items.iter().skip(self.offset).take(rows).enumerate() {
+            let area = Rectangle::new(
+                self.area.top_left + Point::new(0, (row as u32 * self.row_height()) as i32),
+                Size::new(text_width, self.row_height()),
+            );
+            let color = if self.offset + row == self.selected {
+                target.fill_solid(&area, theme.button_active)?;
+                theme.active_text
+            } else {
+                theme.text
+            };
+            draw_text(
+                target,
+                item,
+                layout::inset(area, ROW_GAP, 0),
+                self.font(),
+                color,
+                Alignment::Left,
+            )?;
+        }
+
+        if scrolls {
+            draw_scroll_bar(
+                target,
+                theme,
+                self.area,
+                self.offset,
+                rows,
+                self.items.len(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Long text wrapped to an area, scrolled by line or page
+#[derive(Debug, Clone)]
+pub struct TextViewer<'a> {
+    area: Rectangle,
+    text: &'a str,
+    font: Option<Font>,
+    theme: Theme,
+    // First line shown
+    top: usize,
+    // Lines the text wraps to, and the width it wraps to
+    lines: usize,
+    width: u32,
+}
+
+impl<'a> TextViewer<'a> {
+    /// Show `text` from the top
+    pub fn new(area: Rectangle, text: &'a str) -> Self {
+        let mut viewer = Self {
+            area,
+            text,
+            font: None,
+            theme: Theme::DARK,
+            top: 0,
+            lines: 0,
+            width: area.size.width,
+        };
+        viewer.reflow();
+        viewer
+    }
+
+    /// Draw in another theme
+    pub fn with_theme(mut self, theme: &Theme) -> Self {
+        self.theme = *theme;
+        self.reflow();
+        self
+    }
+
+    /// Use another font than the theme's reading font, smaller fits more on a page
+    pub fn with_font(mut self, font: impl Into<Font>) -> Self {
+        self.font = Some(font.into());
+        self.reflow();
+        self
+    }
+
+    fn font(&self) -> Font {
+        self.font.unwrap_or(self.theme.reading_font)
+    }
+
+    /// Show other text, from the top
+    pub fn set_text(&mut self, text: &'a str) {
+        self.text = text;
+        self.top = 0;
+        self.reflow();
+    }
+
+    /// Wrap the text again, narrower if it needs the scroll bar
+    fn reflow(&mut self) {
+        self.width = self.area.size.width;
+        self.lines = text::line_count(self.text, &self.font(), self.width);
+        if self.lines > self.rows() {
+            self.width = self.area.size.width.saturating_sub(SCROLL_BAR_WIDTH + 1);
+            self.lines = text::line_count(self.text, &self.font(), self.width);
+        }
+        self.top = self.top.min(self.last_top());
+    }
+
+    /// Height of a line, a pixel apart
+    fn line_height(&self) -> u32 {
+        self.font().line_height() + 1
+    }
+
+    /// Lines on a page
+    pub fn rows(&self) -> usize {
+        ((self.area.size.height / self.line_height()) as usize).max(1)
+    }
+
+    /// Lines the text wraps to
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    /// Index of the first line shown
+    pub fn top(&self) -> usize {
+        self.top
+    }
+
+    /// Top line when scrolled to the end
+    fn last_top(&self) -> usize {
+        self.lines.saturating_sub(self.rows())
+    }
+
+    /// Page shown and the number of pages, counting from 1
+    pub fn page(&self) -> (usize, usize) {
+        let pages = self.lines.div_ceil(self.rows()).max(1);
+        (self.top.div_ceil(self.rows()).min(pages - 1) + 1, pages)
+    }
+
+    /// Scroll by a number of lines, negative is up, returning whether it moved
+    pub fn scroll(&mut self, lines: isize) -> bool {
+        let top = self.top.saturating_add_signed(lines).min(self.last_top());
+        let moved = top != self.top;
+        self.top = top;
+        moved
+    }
+
+    /// Up/Down move a line, held they move a page, returning whether it moved
+    pub fn press(&mut self, action: Action, held: bool) -> bool {
+        let step = if held { self.rows() as isize } else { 1 };
+        match action {
+            Action::Up => self.scroll(-step),
+            Action::Down => self.scroll(step),
+            _ => false,
+        }
+    }
+}
+
+impl Drawable for TextViewer<'_> {
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        target.fill_solid(&self.area, self.theme.background)?;
+        let rows = self.rows();
+        let font = self.font();
+        let lines = text::wrap(self.text, &font, self.width)
+            .skip(self.top)
+            .take(rows);
+        for (row, line) in lines.enumerate() {
+            let area = Rectangle::new(
+                self.area.top_left + Point::new(0, (row as u32 * self.line_height()) as i32),
+                Size::new(self.width, self.line_height()),
+            );
+            let suffix = if line.hyphen { "-" } else { "" };
+            draw_line(
+                target,
+                line.text,
+                suffix,
+                area,
+                font,
+                self.theme.text,
+                Alignment::Left,
+            )?;
+        }
+        if self.lines > rows {
+            draw_scroll_bar(target, &self.theme, self.area, self.top, rows, self.lines)?;
+        }
+        Ok(())
+    }
+}
+
+/// A bar filling up from the left
+#[derive(Debug, Clone, Copy)]
+pub struct ProgressBar {
+    area: Rectangle,
+    percent: u8,
+    theme: Theme,
+}
+
+impl ProgressBar {
+    /// An empty bar
+    pub fn new(area: Rectangle) -> Self {
+        Self {
+            area,
+            percent: 0,
+            theme: Theme::DARK,
+        }
+    }
+
+    /// Draw in another theme
+    pub fn with_theme(mut self, theme: &Theme) -> Self {
+        self.theme = *theme;
+        self
+    }
+
+    /// How full it is
+    pub fn percent(&self) -> u8 {
+        self.percent
+    }
+
+    /// Fill to a percentage, returning whether it needs a redraw
+    pub fn set(&mut self, percent: u8) -> bool {
+        let percent = percent.min(100);
+        let changed = percent != self.percent;
+        self.percent = percent;
+        changed
+    }
+}
+
+impl Drawable for ProgressBar {
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        self.area
+            .into_styled(
+                PrimitiveStyleBuilder::new()
+                    .fill_color(self.theme.background)
+                    .stroke_color(self.theme.border)
+                    .stroke_width(1)
+                    .build(),
+            )
+            .draw(target)?;
+        let inner = layout::inset(self.area, 2, 2);
+        let filled = Size::new(
+            inner.size.width * u32::from(self.percent) / 100,
+            inner.size.height,
+        );
+        target.fill_solid(
+            &Rectangle::new(inner.top_left, filled),
+            self.theme.highlight,
+        )
+    }
+}
+
+/// Dots on a circle, in sixteenths of the radius, clockwise from the top
+const SPINNER_DOTS: [(i32, i32); 8] = [
+    (0, -16),
+    (11, -11),
+    (16, 0),
+    (11, 11),
+    (0, 16),
+    (-11, 11),
+    (-16, 0),
+    (-11, -11),
+];
+
+/// Busy indicator, a bright dot chasing round a circle
+#[derive(Debug, Clone, Copy)]
+pub struct Spinner {
+    area: Rectangle,
+    step: usize,
+    theme: Theme,
+}
+
+impl Spinner {
+    /// A spinner filling the middle of an area
+    pub fn new(area: Rectangle) -> Self {
+        Self {
+            area,
+            step: 0,
+            theme: Theme::DARK,
+        }
+    }
+
+    /// Draw in another theme
+    pub fn with_theme(mut self, theme: &Theme) -> Self {
+        self.theme = *theme;
+        self
+    }
+
+    /// Move on one dot
+    pub fn advance(&mut self) {
+        self.step = (self.step + 1) % SPINNER_DOTS.len();
+    }
+
+    /// Index of the bright dot
+    pub fn step(&self) -> usize {
+        self.step
+    }
+}
+
+impl Drawable for Spinner {
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        let theme = &self.theme;
+        target.fill_solid(&self.area, theme.background)?;
+        let side = self.area.size.width.min(self.area.size.height);
+        let dot = (side / 6).max(2);
+        let radius = ((side - dot) / 2) as i32;
+        let center = self.area.center();
+
+        for (index, (x, y)) in SPINNER_DOTS.into_iter().enumerate() {
+            // The two dots behind the head trail off
+            let behind = (self.step + SPINNER_DOTS.len() - index) % SPINNER_DOTS.len();
+            let color = match behind {
+                0 => theme.highlight,
+                1 | 2 => theme.text,
+                _ => theme.border,
+            };
+            let at = center + Point::new(x * radius / 16, y * radius / 16);
+            Circle::with_center(at, dot)
+                .into_styled(PrimitiveStyle::with_fill(color))
+                .draw(target)?;
+        }
+        Ok(())
+    }
+}
+
+/// Rounded border in the theme's box color
+fn outline(area: Rectangle, theme: &Theme) -> impl Drawable<Color = Rgb565, Output = ()> {
+    RoundedRectangle::new(area, CornerRadii::new(Size::new(3, 3))).into_styled(
+        PrimitiveStyleBuilder::new()
+            .stroke_color(theme.border)
+            .stroke_width(1)
+            .build(),
+    )
+}
+
+/// A bordered box
+#[derive(Debug, Clone, Copy)]
+pub struct Panel {
+    area: Rectangle,
+    theme: Theme,
+}
+
+impl Panel {
+    /// A box around an area
+    pub fn new(area: Rectangle) -> Self {
+        Self {
+            area,
+            theme: Theme::DARK,
+        }
+    }
+
+    /// Draw in another theme
+    pub fn with_theme(mut self, theme: &Theme) -> Self {
+        self.theme = *theme;
+        self
+    }
+}
+
+impl Drawable for Panel {
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        outline(self.area, &self.theme).draw(target)
+    }
+}
+
+/// A titled box over the middle of the screen
+#[derive(Debug, Clone)]
+pub struct Modal {
+    screen: Rectangle,
+    area: Rectangle,
+    title: String<ITEM_LEN>,
+    lines: Vec<String<ITEM_LEN>, MODAL_LINES>,
+    theme: Theme,
+}
+
+impl Modal {
+    /// A dialog centered on `screen`, the message split into lines at `\n`
+    pub fn new(screen: Rectangle, title: &str, message: &str) -> Self {
+        let mut modal = Self {
+            screen,
+            area: screen,
+            title: fitted(title),
+            lines: message.split('\n').take(MODAL_LINES).map(fitted).collect(),
+            theme: Theme::DARK,
+        };
+        modal.place();
+        modal
+    }
+
+    /// Draw in another theme
+    pub fn with_theme(mut self, theme: &Theme) -> Self {
+        self.theme = *theme;
+        self.place();
+        self
+    }
+
+    /// Size the box to the lines in the theme's font
+    fn place(&mut self) {
+        let (line, padding) = (self.theme.font.line_height(), self.theme.padding);
+        let height = line + 2 * padding + (self.lines.len() as u32 * line) + 2 * padding;
+        let width = self.screen.size.width - 4 * self.theme.margin;
+        self.area = layout::centered(self.screen, Size::new(width, height));
+    }
+
+    /// Where it is drawn, to redraw what it covered once dismissed
+    pub fn area(&self) -> Rectangle {
+        self.area
+    }
+}
+
+impl Drawable for Modal {
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        let theme = &self.theme;
+        target.fill_solid(&self.area, theme.background)?;
+        let line = theme.font.line_height();
+        let (title, body) = layout::split_top(self.area, line + 2 * theme.padding);
+        target.fill_solid(&title, theme.button)?;
+        outline(self.area, theme).draw(target)?;
+        draw_text(
+            target,
+            &self.title,
+            layout::inset(title, theme.padding, 0),
+            theme.font,
+            theme.button_text,
+            Alignment::Center,
+        )?;
+
+        let body = layout::inset(body, theme.padding, theme.padding);
+        for (index, text) in self.lines.iter().enumerate() {
+            let area = Rectangle::new(
+                body.top_left + Point::new(0, (index as u32 * line) as i32),
+                Size::new(body.size.width, line),
+            );
+            draw_text(
+                target,
+                text,
+                area,
+                theme.font,
+                theme.text,
+                Alignment::Center,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A short message at the bottom of an area that goes away by itself
+#[derive(Debug, Clone)]
+pub struct Toast {
+    area: Rectangle,
+    text: String<ITEM_LEN>,
+    until: Option<Instant>,
+    theme: Theme,
+}
+
+impl Toast {
+    /// Nothing shown, messages go at the bottom of `area`
+    pub fn new(area: Rectangle) -> Self {
+        Self {
+            area,
+            text: String::new(),
+            until: None,
+            theme: Theme::DARK,
+        }
+    }
+
+    /// Draw in another theme
+    pub fn with_theme(mut self, theme: &Theme) -> Self {
+        self.theme = *theme;
+        self
+    }
+
+    /// Show a message for a while
+    pub fn show(&mut self, text: &str, now: Instant, duration: Duration) {
+        self.text = fitted(text);
+        self.until = Some(now + duration);
+    }
+
+    /// Whether a message is up
+    pub fn is_visible(&self) -> bool {
+        self.until.is_some()
+    }
+
+    /// When the message goes, for the caller's timer
+    pub fn expires(&self) -> Option<Instant> {
+        self.until
+    }
+
+    /// Take down an expired message, returning whether it just went
+    pub fn update(&mut self, now: Instant) -> bool {
+        match self.until {
+            Some(until) if now >= until => {
+                self.until = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Where the message is drawn
+    pub fn area(&self) -> Rectangle {
+        let (font, padding) = (self.theme.small_font, self.theme.padding);
+        let size = Size::new(
+            font.width(&self.text) + 2 * padding + 2,
+            font.line_height() + 2 * padding,
+        )
+        .component_min(self.area.size);
+        let (_, bottom) = layout::split_bottom(self.area, size.height);
+        layout::centered(bottom, size)
+    }
+}
+
+impl Drawable for Toast {
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        if !self.is_visible() {
+            return Ok(());
+        }
+        let theme = &self.theme;
+        let area = self.area();
+        RoundedRectangle::new(area, CornerRadii::new(Size::new(3, 3)))
+            .into_styled(
+                PrimitiveStyleBuilder::new()
+                    .fill_color(theme.button_active)
+                    .stroke_color(theme.highlight)
+                    .stroke_width(1)
+                    .build(),
+            )
+            .draw(target)?;
+        draw_text(
+            target,
+            &self.text,
+            layout::inset(area, theme.padding, 0),
+            theme.small_font,
+            theme.active_text,
+            Alignment::Center,
+        )
+    }
+}
+
+/// The labels over the keys, one slot each, the active one outlined
+#[derive(Debug, Clone, Copy)]
+pub struct SoftKeyBar {
+    area: Rectangle,
+    layout: ButtonLayout,
+    theme: Theme,
+}
+
+impl SoftKeyBar {
+    /// A bar across `area`
+    pub fn new(area: Rectangle, layout: ButtonLayout) -> Self {
+        Self {
+            area,
+            layout,
+            theme: Theme::DARK,
+        }
+    }
+
+    /// Draw in another theme
+    pub fn with_theme(mut self, theme: &Theme) -> Self {
+        self.theme = *theme;
+        self
+    }
+
+    /// Show other labels, returning whether they need a redraw
+    pub fn set_layout(&mut self, layout: &ButtonLayout) -> bool {
+        let changed = *layout != self.layout;
+        self.layout = *layout;
+        changed
+    }
+
+    /// Area of each key's slot
+    pub fn slots(&self) -> [Rectangle; KEY_COUNT] {
+        layout::columns(self.area)
+    }
+}
+
+impl Drawable for SoftKeyBar {
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        let theme = &self.theme;
+        target.fill_solid(&self.area, theme.button)?;
+        let font = theme.small_font;
+        for (i, (slot, label)) in self.slots().into_iter().zip(self.layout.labels).enumerate() {
+            if label.is_empty() {
+                continue;
+            }
+            if i == self.layout.active_index {
+                // Indicator around the label, a line of space above and below
+                let height = font.line_height() + 4;
+                let indicator =
+                    layout::centered(slot, Size::new(slot.size.width.saturating_sub(2), height));
+                indicator
+                    .into_styled(PrimitiveStyle::with_stroke(theme.button_text, 1))
+                    .draw(target)?;
+            }
+            draw_text(
+                target,
+                label,
+                slot,
+                font,
+                theme.button_text,
+                Alignment::Center,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::fonts::SANS_13;
+    use embedded_graphics::mock_display::MockDisplay;
+    use embedded_graphics::mono_font::ascii::{FONT_6X10, FONT_8X13};
+
+    const DARK: Theme = Theme::DARK;
+
+    fn canvas() -> MockDisplay<Rgb565> {
+        let mut mock = MockDisplay::new();
+        mock.set_allow_overdraw(true);
+        mock.set_allow_out_of_bounds_drawing(true);
+        mock
+    }
+
+    fn area(x: i32, y: i32, width: u32, height: u32) -> Rectangle {
+        Rectangle::new(Point::new(x, y), Size::new(width, height))
+    }
+
+    /// Columns of an area with any pixel of a color
+    fn columns_with(mock: &MockDisplay<Rgb565>, area: Rectangle, color: Rgb565) -> Vec<i32, 64> {
+        let mut columns = Vec::new();
+        for x in area.columns() {
+            if area
+                .rows()
+                .any(|y| mock.get_pixel(Point::new(x, y)) == Some(color))
+            {
+                let _ = columns.push(x);
+            }
+        }
+        columns
+    }
+
+    #[test]
+    fn label_clips_and_aligns() {
+        let mut label = Label::new(area(0, 0, 30, 12), "Too long to fit").with_font(&FONT_6X10);
+        let mut mock = canvas();
+        label.draw(&mut mock).unwrap();
+        // Two characters and the ellipsis fit, nothing past the area
+        let drawn = columns_with(&mock, area(0, 0, 64, 12), DARK.text);
+        assert!(drawn.iter().all(|&x| x < 30), "{drawn:?}");
+
+        assert!(label.set_text("Hi"));
+        assert!(!label.set_text("Hi"));
+        let label = label.with_alignment(Alignment::Right);
+        let mut mock = canvas();
+        label.draw(&mut mock).unwrap();
+        let drawn = columns_with(&mock, area(0, 0, 64, 12), DARK.text);
+        assert!(drawn.iter().all(|&x| x >= 18), "{drawn:?}");
+    }
+
+    #[test]
+    fn list_scrolls_to_the_selection() {
+        let mut list: ScrollList<8> = ScrollList::new(area(0, 0, 60, 51));
+        for item in ["One", "Two", "Three", "Four", "Five"] {
+            list.push(item).unwrap();
+        }
+        // 17 pixel rows
+        assert_eq!(list.visible_rows(), 3);
+        assert!(!list.select_previous());
+        assert!(list.select_next() && list.select_next());
+        assert_eq!(list.offset(), 0);
+        assert!(list.select_next());
+        assert_eq!((list.selected(), list.offset()), (3, 1));
+        assert_eq!(list.selected_item(), Some("Four"));
+        list.select(4);
+        assert!(!list.select_next());
+        assert!(list.select(0));
+        assert_eq!(list.offset(), 0);
+
+        let mut mock = canvas();
+        list.select(1);
+        list.draw(&mut mock).unwrap();
+        // Second row is the selection, the scroll bar's thumb is at the top
+        assert_eq!(mock.get_pixel(Point::new(1, 20)), Some(DARK.button_active));
+        assert_eq!(mock.get_pixel(Point::new(1, 3)), Some(DARK.background));
+        assert_eq!(mock.get_pixel(Point::new(58, 0)), Some(DARK.highlight));
+        assert_eq!(mock.get_pixel(Point::new(58, 50)), Some(DARK.button));
+
+        let mut full: ScrollList<1> = ScrollList::new(area(0, 0, 60, 51));
+        full.push("One").unwrap();
+        assert_eq!(full.push("Two"), Err("List full"));
+    }
+
+    #[test]
+    fn progress_fills_its_share() {
+        let mut bar = ProgressBar::new(area(0, 0, 54, 8));
+        assert!(bar.set(50));
+        assert!(!bar.set(50));
+        assert!(bar.set(250));
+        assert_eq!(bar.percent(), 100);
+        bar.set(50);
+        let mut mock = canvas();
+        bar.draw(&mut mock).unwrap();
+        // Inside is 50 wide from x 2
+        assert_eq!(
+            columns_with(&mock, area(0, 0, 54, 8), DARK.highlight).len(),
+            25
+        );
+        assert_eq!(mock.get_pixel(Point::new(0, 4)), Some(DARK.border));
+    }
+
+    #[test]
+    fn spinner_head_goes_round() {
+        let mut spinner = Spinner::new(area(0, 0, 32, 32));
+        let mut mock = canvas();
+        spinner.draw(&mut mock).unwrap();
+        // Head at the top
+        let top = area(0, 0, 32, 8);
+        assert!(!columns_with(&mock, top, DARK.highlight).is_empty());
+
+        for _ in 0..2 {
+            spinner.advance();
+        }
+        let mut mock = canvas();
+        spinner.draw(&mut mock).unwrap();
+        assert!(columns_with(&mock, top, DARK.highlight).is_empty());
+        assert!(!columns_with(&mock, area(24, 12, 8, 8), DARK.highlight).is_empty());
+        for _ in 0..6 {
+            spinner.advance();
+        }
+        assert_eq!(spinner.step(), 0);
+    }
+
+    #[test]
+    fn modal_sits_in_the_middle() {
+        let screen = area(0, 0, 64, 64);
+        let modal = Modal::new(screen, "Hi", "A\nB");
+        let box_ = modal.area();
+        // Width from the screen, height from the two lines
+        assert_eq!(box_.top_left.x, 10);
+        assert_eq!(box_.size.width, 44);
+        assert!((box_.center().y - screen.center().y).abs() <= 1);
+        let mut mock = canvas();
+        modal.draw(&mut mock).unwrap();
+        assert_eq!(
+            mock.get_pixel(box_.top_left + Point::new(5, 2)),
+            Some(DARK.button)
+        );
+        assert_eq!(mock.get_pixel(Point::new(2, 2)), None);
+    }
+
+    /// A route description, longer than a page at either font size
+    const DIRECTIONS: &str = "Walk to Hauptbahnhof, then take the S-Bahn towards \
+        Frankfurt-Niederrad. Change at Hauptwache for the U4 to Bockenheimer \
+        Warte.\nArrive 8:42, four minutes early.";
+
+    #[test]
+    fn viewer_pages_through_long_text() {
+        let content = area(8, 28, 144, 62);
+        let small = TextViewer::new(content, DIRECTIONS).with_font(&FONT_6X10);
+        let large = TextViewer::new(content, DIRECTIONS).with_font(&FONT_8X13);
+        // 11 and 14 pixel lines
+        assert_eq!((small.rows(), large.rows()), (5, 4));
+        assert!(large.lines() > small.lines());
+        assert!(small.lines() > small.rows());
+        // Narrowed for the scroll bar
+        assert_eq!(small.width, 140);
+        // Without a font of its own it reads in the theme's proportional one
+        let reading = TextViewer::new(content, DIRECTIONS);
+        let sans = TextViewer::new(content, DIRECTIONS).with_font(&SANS_13);
+        assert_eq!(
+            (reading.rows(), reading.lines()),
+            (sans.rows(), sans.lines())
+        );
+
+        let mut viewer = large;
+        assert_eq!(viewer.page(), (1, viewer.lines().div_ceil(4)));
+        assert!(!viewer.press(Action::Up, false));
+        assert!(viewer.press(Action::Down, false));
+        assert_eq!(viewer.top(), 1);
+        assert!(viewer.press(Action::Down, true));
+        assert_eq!(viewer.top(), 5);
+        // Never past the last page
+        while viewer.press(Action::Down, true) {}
+        assert_eq!(viewer.top(), viewer.lines() - viewer.rows());
+        assert_eq!(viewer.page().0, viewer.page().1);
+        assert!(!viewer.press(Action::Back, false));
+        viewer.set_text("Arrived");
+        assert_eq!(
+            (viewer.top(), viewer.lines(), viewer.page()),
+            (0, 1, (1, 1))
+        );
+    }
+
+    #[test]
+    fn viewer_draws_lines_and_position() {
+        let mut viewer = TextViewer::new(area(0, 0, 64, 40), DIRECTIONS).with_font(&FONT_6X10);
+        let mut screen = canvas();
+        viewer.draw(&mut screen).unwrap();
+        // Text on the first line, a thumb at the top of the scroll bar
+        assert!(!columns_with(&screen, area(0, 0, 60, 10), DARK.text).is_empty());
+        assert_eq!(screen.get_pixel(Point::new(62, 0)), Some(DARK.highlight));
+        assert_eq!(screen.get_pixel(Point::new(62, 39)), Some(DARK.button));
+        // Nothing drawn under the bar's gap
+        assert_eq!(
+            columns_with(&screen, area(60, 0, 1, 40), DARK.text).len(),
+            0
+        );
+
+        while viewer.press(Action::Down, true) {}
+        let mut screen = canvas();
+        viewer.draw(&mut screen).unwrap();
+        assert_eq!(screen.get_pixel(Point::new(62, 0)), Some(DARK.button));
+        assert_eq!(screen.get_pixel(Point::new(62, 39)), Some(DARK.highlight));
+
+        // Short text leaves the bar out
+        let viewer = TextViewer::new(area(0, 0, 64, 40), "Arrived");
+        let mut screen = canvas();
+        viewer.draw(&mut screen).unwrap();
+        assert_eq!(screen.get_pixel(Point::new(62, 39)), Some(DARK.background));
+    }
+
+    #[test]
+    fn toast_expires() {
+        let mut toast = Toast::new(area(0, 0, 64, 64));
+        let now = Instant::from_millis(0);
+        assert!(!toast.update(now));
+        toast.show("Saved", now, Duration::from_secs(2));
+        assert!(toast.is_visible());
+        assert_eq!(toast.area().bottom_right().map(|p| p.y), Some(63));
+
+        let mut mock = canvas();
+        toast.draw(&mut mock).unwrap();
+        assert!(mock.get_pixel(toast.area().center()).is_some());
+
+        assert!(!toast.update(Instant::from_millis(1999)));
+        assert!(toast.update(Instant::from_millis(2000)));
+        assert!(!toast.is_visible());
+        let mut mock = canvas();
+        toast.draw(&mut mock).unwrap();
+        assert_eq!(mock.affected_area().size, Size::zero());
+    }
+}
@@ -0,0 +1,349 @@
+//! Key mapping
+//! Translates physical key indices into logical actions, per screen
+//!
+//! The soft-key labels follow the map, so a remapped key shows what it does.
+//! The key map editor itself always uses the physical layout so a bad map can
+//! never lock the user out of fixing it.
+
+use crate::buttons::{DOWN_KEY, UP_KEY};
+use crate::menu::MenuScreen;
+use crate::KEY_COUNT;
+
+/// What a key does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Action {
+    None,
+    Back,
+    Menu,
+    Trip,
+    Settings,
+    Mic,
+    New,
+    View,
+    Map,
+    Wifi,
+    Led,
+    User,
+    Keys,
+    Up,
+    Down,
+    Theme,
+}
+
+impl Action {
+    /// Every action, in the order the editor cycles through them
+    pub const ALL: [Action; 16] = [
+        Action::None,
+        Action::Back,
+        Action::Menu,
+        Action::Trip,
+        Action::Settings,
+        Action::Mic,
+        Action::New,
+        Action::View,
+        Action::Map,
+        Action::Wifi,
+        Action::Led,
+        Action::User,
+        Action::Keys,
+        Action::Up,
+        Action::Down,
+        Action::Theme,
+    ];
+
+    /// Soft-key label, empty for no action
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::None => "",
+            Action::Back => "Back",
+            Action::Menu => "Menu",
+            Action::Trip => "Trip",
+            Action::Settings => "Set",
+            Action::Mic => "Mic",
+            Action::New => "New",
+            Action::View => "View",
+            Action::Map => "Map",
+            Action::Wifi => "WiFi",
+            Action::Led => "LED",
+            Action::User => "User",
+            Action::Keys => "Keys",
+            Action::Up => "Up",
+            Action::Down => "Down",
+            Action::Theme => "Look",
+        }
+    }
+
+    /// Decode a stored action
+    pub fn from_u8(value: u8) -> Option<Action> {
+        Action::ALL.get(usize::from(value)).copied()
+    }
+
+    /// The action after this one in [`Action::ALL`], wrapping around
+    pub fn next(self) -> Action {
+        Action::ALL[(self as usize + 1) % Action::ALL.len()]
+    }
+
+    /// Whether holding the key scrolls
+    pub fn is_scroll(self) -> bool {
+        matches!(self, Action::Up | Action::Down)
+    }
+}
+
+/// Screens whose keys can be remapped, in storage order
+pub const MAPPED_SCREENS: [MenuScreen; 3] =
+    [MenuScreen::Main, MenuScreen::Trip, MenuScreen::Settings];
+
+/// Bytes a key map takes up in storage
+pub const KEYMAP_SIZE: usize = MAPPED_SCREENS.len() * KEY_COUNT;
+
+pub const MAIN_KEYS: [Action; KEY_COUNT] = [
+    Action::Menu,
+    Action::Trip,
+    Action::Settings,
+    Action::Mic,
+    Action::Up,
+    Action::Down,
+];
+pub const TRIP_KEYS: [Action; KEY_COUNT] = [
+    Action::Back,
+    Action::New,
+    Action::View,
+    Action::Map,
+    Action::Up,
+    Action::Down,
+];
+pub const SETTINGS_KEYS: [Action; KEY_COUNT] = [
+    Action::Back,
+    Action::Theme,
+    Action::Led,
+    Action::Keys,
+    Action::Up,
+    Action::Down,
+];
+
+/// Physical key to action table for every remappable screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keymap {
+    screens: [[Action; KEY_COUNT]; MAPPED_SCREENS.len()],
+}
+
+impl Keymap {
+    /// The layout printed on the keycaps
+    pub const fn new() -> Self {
+        Self {
+            screens: [MAIN_KEYS, TRIP_KEYS, SETTINGS_KEYS],
+        }
+    }
+
+    /// The default layout mirrored left to right, for left-handed use
+    pub fn mirrored() -> Self {
+        let mut keymap = Self::new();
+        keymap.mirror();
+        keymap
+    }
+
+    /// Action of a physical key on a screen
+    pub fn action(&self, screen: MenuScreen, key: usize) -> Action {
+        match (Self::index(screen), key < KEY_COUNT) {
+            (Some(screen), true) => self.screens[screen][key],
+            _ => Action::None,
+        }
+    }
+
+    /// Physical key bound to an action on a screen
+    pub fn key_for(&self, screen: MenuScreen, action: Action) -> Option<usize> {
+        let screen = Self::index(screen)?;
+        self.screens[screen].iter().position(|&a| a == action)
+    }
+
+    /// Bind a physical key to an action on a screen
+    pub fn set(&mut self, screen: MenuScreen, key: usize, action: Action) {
+        if let (Some(screen), true) = (Self::index(screen), key < KEY_COUNT) {
+            self.screens[screen][key] = action;
+        }
+    }
+
+    /// Soft-key labels of a screen, fixed labels for screens without a map
+    pub fn labels(&self, screen: MenuScreen) -> [&'static str; KEY_COUNT] {
+        match Self::index(screen) {
+            Some(index) => self.screens[index].map(Action::label),
+            None => screen.labels(),
+        }
+    }
+
+    /// Reverse the key order on every screen
+    pub fn mirror(&mut self) {
+        for keys in self.screens.iter_mut() {
+            keys.reverse();
+        }
+    }
+
+    /// Make sure every screen can still reach the key map editor and the
+    /// theme picker
+    ///
+    /// Maps stored before Settings had a Look key fail this, and loading falls
+    /// back to the default map.
+    pub fn check(&self) -> Result<(), &'static str> {
+        let has = |screen, action| self.key_for(screen, action).is_some();
+        if !has(MenuScreen::Main, Action::Settings) {
+            return Err("Main screen needs a Settings key");
+        }
+        if !has(MenuScreen::Trip, Action::Back) || !has(MenuScreen::Settings, Action::Back) {
+            return Err("Sub screens need a Back key");
+        }
+        if !has(MenuScreen::Settings, Action::Keys) {
+            return Err("Settings needs a Keys key");
+        }
+        if !has(MenuScreen::Settings, Action::Theme) {
+            return Err("Settings needs a Look key");
+        }
+        Ok(())
+    }
+
+    /// Serialize for storage
+    pub fn to_bytes(&self) -> [u8; KEYMAP_SIZE] {
+        let mut bytes = [0; KEYMAP_SIZE];
+        for (byte, &action) in bytes.iter_mut().zip(self.screens.iter().flatten()) {
+            *byte = action as u8;
+        }
+        bytes
+    }
+
+    /// Deserialize from storage, rejecting unknown actions and unusable maps
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        if bytes.len() != KEYMAP_SIZE {
+            return Err("Wrong key map size");
+        }
+        let mut keymap = Self::new();
+        for (slot, &byte) in keymap.screens.iter_mut().flatten().zip(bytes) {
+            *slot = Action::from_u8(byte).ok_or("Unknown key action")?;
+        }
+        keymap.check()?;
+        Ok(keymap)
+    }
+
+    fn index(screen: MenuScreen) -> Option<usize> {
+        MAPPED_SCREENS.iter().position(|&s| s == screen)
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Cursor of the key map editor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeymapEditor {
+    /// Screen whose map is being edited
+    pub screen: MenuScreen,
+    /// Physical key being edited
+    pub key: usize,
+}
+
+impl KeymapEditor {
+    /// Start on the first key of the main screen
+    pub const fn new() -> Self {
+        Self {
+            screen: MenuScreen::Main,
+            key: 0,
+        }
+    }
+
+    /// Switch to the next remappable screen
+    pub fn next_screen(&mut self) {
+        let index = MAPPED_SCREENS
+            .iter()
+            .position(|&s| s == self.screen)
+            .map_or(0, |i| (i + 1) % MAPPED_SCREENS.len());
+        self.screen = MAPPED_SCREENS[index];
+    }
+
+    /// Handle a physical key, editing `keymap`; returns whether anything changed
+    pub fn press(&mut self, key: usize, keymap: &mut Keymap) -> bool {
+        match key {
+            1 => self.next_screen(),
+            2 => {
+                let action = keymap.action(self.screen, self.key).next();
+                keymap.set(self.screen, self.key, action);
+            }
+            3 => keymap.mirror(),
+            UP_KEY => self.key = (self.key + KEY_COUNT - 1) % KEY_COUNT,
+            DOWN_KEY => self.key = (self.key + 1) % KEY_COUNT,
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl Default for KeymapEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::menu::{MAIN_BUTTONS, SETTINGS_BUTTONS, TRIP_BUTTONS};
+
+    #[test]
+    fn default_map_matches_the_keycaps() {
+        let keymap = Keymap::new();
+        assert_eq!(keymap.labels(MenuScreen::Main), MAIN_BUTTONS);
+        assert_eq!(keymap.labels(MenuScreen::Trip), TRIP_BUTTONS);
+        assert_eq!(keymap.labels(MenuScreen::Settings), SETTINGS_BUTTONS);
+        assert!(keymap.check().is_ok());
+    }
+
+    #[test]
+    fn mirrored_map_reverses_keys() {
+        let keymap = Keymap::mirrored();
+        assert_eq!(keymap.action(MenuScreen::Trip, 5), Action::Back);
+        assert_eq!(keymap.action(MenuScreen::Trip, 0), Action::Down);
+        assert_eq!(keymap.key_for(MenuScreen::Main, Action::Up), Some(1));
+        assert_eq!(keymap.action(MenuScreen::Startup, 0), Action::None);
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let mut keymap = Keymap::mirrored();
+        keymap.set(MenuScreen::Main, 2, Action::Map);
+        assert_eq!(Keymap::from_bytes(&keymap.to_bytes()), Ok(keymap));
+    }
+
+    #[test]
+    fn unusable_maps_are_rejected() {
+        let mut keymap = Keymap::new();
+        keymap.set(MenuScreen::Settings, 0, Action::Mic);
+        assert!(keymap.check().is_err());
+        assert!(Keymap::from_bytes(&keymap.to_bytes()).is_err());
+
+        // Settings as it was before themes, WiFi where Look is now
+        let mut keymap = Keymap::new();
+        keymap.set(MenuScreen::Settings, 1, Action::Wifi);
+        assert_eq!(keymap.check(), Err("Settings needs a Look key"));
+
+        let mut bytes = Keymap::new().to_bytes();
+        bytes[0] = 200;
+        assert_eq!(Keymap::from_bytes(&bytes), Err("Unknown key action"));
+    }
+
+    #[test]
+    fn editor_cycles_actions_and_screens() {
+        let mut keymap = Keymap::new();
+        let mut editor = KeymapEditor::new();
+        assert!(editor.press(DOWN_KEY, &mut keymap));
+        assert_eq!(editor.key, 1);
+        assert!(editor.press(2, &mut keymap));
+        assert_eq!(keymap.action(MenuScreen::Main, 1), Action::Settings);
+        assert!(editor.press(1, &mut keymap));
+        assert_eq!(editor.screen, MenuScreen::Trip);
+        assert!(editor.press(UP_KEY, &mut keymap));
+        assert!(editor.press(UP_KEY, &mut keymap));
+        assert_eq!(editor.key, KEY_COUNT - 1);
+        assert!(!editor.press(0, &mut keymap));
+    }
+}
@@ -0,0 +1,195 @@
+//! LED frame module
+//! Colors and frame generation for the six key LEDs
+
+use crate::buttons::ButtonState;
+use crate::KEY_COUNT;
+
+pub mod calibration;
+pub mod effects;
+pub mod encoding;
+pub mod format;
+pub mod notify;
+pub mod pattern;
+pub mod power;
+
+pub use calibration::{Calibration, Calibrator, LedCalibration};
+pub use effects::{Effect, EffectStack, Layer, LayerId, Priority};
+pub use format::{PixelFormat, StripConfig};
+pub use notify::{Notification, Notifier, QuietHours};
+pub use pattern::{Pattern, PatternEncoder, StoredPattern};
+pub use power::{LedModel, PowerBudget, PowerReport};
+
+/// Color structure for RGB values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RgbColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl RgbColor {
+    /// Create a new RGB color
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Get the color as a 32-bit value in GRB order
+    pub fn as_u32(&self) -> u32 {
+        ((self.g as u32) << 16) | ((self.r as u32) << 8) | (self.b as u32)
+    }
+
+    /// Scale every channel by `level / 255`
+    pub fn scale(self, level: u8) -> Self {
+        let s = |c: u8| ((c as u16 * level as u16) / 255) as u8;
+        Self::new(s(self.r), s(self.g), s(self.b))
+    }
+
+    /// Convert from HSV with all components in 0..=255
+    pub fn from_hsv(hue: u8, sat: u8, val: u8) -> Self {
+        if sat == 0 {
+            return Self::new(val, val, val);
+        }
+
+        // Six hue sectors of roughly 43 steps each
+        let sector = hue / 43;
+        let offset = (hue - sector * 43) as u16 * 6;
+
+        let v = val as u16;
+        let s = sat as u16;
+        let p = ((v * (255 - s)) >> 8) as u8;
+        let q = ((v * (255 - ((s * offset) >> 8))) >> 8) as u8;
+        let t = ((v * (255 - ((s * (255 - offset)) >> 8))) >> 8) as u8;
+
+        match sector {
+            0 => Self::new(val, t, p),
+            1 => Self::new(q, val, p),
+            2 => Self::new(p, val, t),
+            3 => Self::new(p, q, val),
+            4 => Self::new(t, p, val),
+            _ => Self::new(val, p, q),
+        }
+    }
+}
+
+/// Common color constants
+pub mod colors {
+    use super::RgbColor;
+
+    pub const OFF: RgbColor = RgbColor::new(0, 0, 0);
+    pub const RED: RgbColor = RgbColor::new(255, 0, 0);
+    pub const GREEN: RgbColor = RgbColor::new(0, 255, 0);
+    pub const BLUE: RgbColor = RgbColor::new(0, 0, 255);
+    pub const WHITE: RgbColor = RgbColor::new(255, 255, 255);
+    pub const YELLOW: RgbColor = RgbColor::new(255, 255, 0);
+    pub const CYAN: RgbColor = RgbColor::new(0, 255, 255);
+    pub const MAGENTA: RgbColor = RgbColor::new(255, 0, 255);
+
+    // Dimmer versions for button backgrounds
+    pub const DIM_RED: RgbColor = RgbColor::new(32, 0, 0);
+    pub const DIM_GREEN: RgbColor = RgbColor::new(0, 32, 0);
+    pub const DIM_BLUE: RgbColor = RgbColor::new(0, 0, 32);
+    pub const DIM_WHITE: RgbColor = RgbColor::new(32, 32, 32);
+    pub const DIM_YELLOW: RgbColor = RgbColor::new(32, 32, 0);
+    pub const DIM_CYAN: RgbColor = RgbColor::new(0, 32, 32);
+    pub const DIM_MAGENTA: RgbColor = RgbColor::new(32, 0, 32);
+}
+
+/// One color per key LED
+pub type LedFrame = [RgbColor; KEY_COUNT];
+
+/// Something that can push a frame of colors out to physical LEDs
+// Only awaited from tasks on the single-core executor, no Send bound needed
+#[allow(async_fn_in_trait)]
+pub trait LedOutput {
+    type Error;
+
+    /// Send the colors to the LEDs, extra colors are ignored
+    async fn show(&mut self, colors: &[RgbColor]) -> Result<(), Self::Error>;
+
+    /// Set all LEDs to a single color
+    async fn set_all(&mut self, color: RgbColor) -> Result<(), Self::Error> {
+        self.show(&[color; KEY_COUNT]).await
+    }
+}
+
+/// What a key's LED says about the key under it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyHint {
+    /// No label, the LED is dark
+    Off,
+    /// Labelled but not usable right now
+    Disabled,
+    /// An ordinary action
+    Enabled,
+    /// The main thing to do on this screen
+    Primary,
+    /// Something that throws work away
+    Destructive,
+    /// Back, the same color on every screen
+    Back,
+}
+
+impl KeyHint {
+    /// LED color of the hint
+    pub fn color(self) -> RgbColor {
+        match self {
+            KeyHint::Off => colors::OFF,
+            KeyHint::Disabled => RgbColor::new(4, 4, 4),
+            KeyHint::Enabled => colors::DIM_WHITE,
+            KeyHint::Primary => colors::DIM_GREEN,
+            KeyHint::Destructive => colors::DIM_RED,
+            KeyHint::Back => colors::DIM_BLUE,
+        }
+    }
+}
+
+/// Frame of the hint colors
+pub fn hint_frame(hints: &[KeyHint; KEY_COUNT]) -> LedFrame {
+    hints.map(KeyHint::color)
+}
+
+/// Frame that lights pressed keys white over the hint colors
+pub fn key_frame(hints: &LedFrame, states: &[ButtonState; KEY_COUNT]) -> LedFrame {
+    let mut frame = *hints;
+    for (color, state) in frame.iter_mut().zip(states) {
+        if *state == ButtonState::Pressed {
+            *color = colors::WHITE;
+        }
+    }
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pressed_keys_go_white() {
+        let mut states = [ButtonState::Idle; KEY_COUNT];
+        states[2] = ButtonState::Pressed;
+        states[4] = ButtonState::Released;
+        let mut hints = [KeyHint::Enabled; KEY_COUNT];
+        hints[0] = KeyHint::Back;
+        hints[5] = KeyHint::Off;
+
+        let frame = key_frame(&hint_frame(&hints), &states);
+        assert_eq!(frame[2], colors::WHITE);
+        assert_eq!(frame[4], colors::DIM_WHITE);
+        assert_eq!(frame[0], colors::DIM_BLUE);
+        assert_eq!(frame[5], colors::OFF);
+    }
+
+    #[test]
+    fn hsv_primaries() {
+        assert_eq!(RgbColor::from_hsv(0, 255, 255), RgbColor::new(255, 0, 0));
+        assert_eq!(RgbColor::from_hsv(0, 0, 80), RgbColor::new(80, 80, 80));
+        let green = RgbColor::from_hsv(86, 255, 255);
+        assert!(green.g == 255 && green.r < 8 && green.b < 8);
+    }
+
+    #[test]
+    fn scale_and_grb_packing() {
+        assert_eq!(colors::WHITE.scale(32), RgbColor::new(32, 32, 32));
+        assert_eq!(RgbColor::new(1, 2, 3).as_u32(), 0x02_01_03);
+    }
+}
@@ -0,0 +1,419 @@
+//! LED calibration
+//! Per-position color correction applied just before a frame goes out
+//!
+//! Each key LED gets a gamma curve, a white-point correction matrix and a
+//! floor below which a lit channel is raised so it stays visible. An SK6805
+//! under a tinted, translucent keycap needs different numbers than a bare
+//! WS2812B, and even identical LEDs differ across a keyboard. The defaults are
+//! an identity, so an uncalibrated board shows the raw frame values.
+
+use micromath::F32Ext;
+
+use super::RgbColor;
+use crate::buttons::{DOWN_KEY, UP_KEY};
+use crate::KEY_COUNT;
+
+/// Matrix entry that passes a channel through unchanged
+pub const UNITY: i16 = 256;
+/// Bytes one LED's calibration takes up in storage
+pub const LED_CALIBRATION_SIZE: usize = 20;
+/// Bytes the calibration of every key LED takes up in storage
+pub const CALIBRATION_SIZE: usize = KEY_COUNT * LED_CALIBRATION_SIZE;
+
+// Accepted ranges, wide enough for any real LED and keycap
+const MATRIX_RANGE: core::ops::RangeInclusive<i16> = -UNITY..=2 * UNITY;
+const GAMMA_RANGE: core::ops::RangeInclusive<u8> = 10..=30;
+const FLOOR_RANGE: core::ops::RangeInclusive<u8> = 0..=64;
+
+/// Color correction of one LED position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LedCalibration {
+    /// Output channels as a mix of the input channels, in 1/256ths
+    pub matrix: [[i16; 3]; 3],
+    /// Gamma exponent in tenths, 10 is linear
+    pub gamma_x10: u8,
+    /// Lowest value a lit channel is sent with
+    pub floor: u8,
+}
+
+impl LedCalibration {
+    /// No correction at all
+    pub const IDENTITY: Self = Self {
+        matrix: [[UNITY, 0, 0], [0, UNITY, 0], [0, 0, UNITY]],
+        gamma_x10: 10,
+        floor: 0,
+    };
+
+    /// Make sure every value is in its accepted range
+    pub fn check(&self) -> Result<(), &'static str> {
+        if !self
+            .matrix
+            .iter()
+            .flatten()
+            .all(|m| MATRIX_RANGE.contains(m))
+        {
+            return Err("Calibration matrix out of range");
+        }
+        if !GAMMA_RANGE.contains(&self.gamma_x10) {
+            return Err("Calibration gamma out of range");
+        }
+        if !FLOOR_RANGE.contains(&self.floor) {
+            return Err("Calibration floor out of range");
+        }
+        Ok(())
+    }
+
+    /// Serialize for storage
+    pub fn to_bytes(&self) -> [u8; LED_CALIBRATION_SIZE] {
+        let mut bytes = [0; LED_CALIBRATION_SIZE];
+        for (chunk, m) in bytes.chunks_exact_mut(2).zip(self.matrix.iter().flatten()) {
+            chunk.copy_from_slice(&m.to_le_bytes());
+        }
+        bytes[18] = self.gamma_x10;
+        bytes[19] = self.floor;
+        bytes
+    }
+
+    /// Deserialize from storage, rejecting out of range values
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        if bytes.len() != LED_CALIBRATION_SIZE {
+            return Err("Wrong LED calibration size");
+        }
+        let mut calibration = Self::IDENTITY;
+        for (m, chunk) in calibration
+            .matrix
+            .iter_mut()
+            .flatten()
+            .zip(bytes.chunks_exact(2))
+        {
+            *m = i16::from_le_bytes([chunk[0], chunk[1]]);
+        }
+        calibration.gamma_x10 = bytes[18];
+        calibration.floor = bytes[19];
+        calibration.check()?;
+        Ok(calibration)
+    }
+}
+
+impl Default for LedCalibration {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// Calibration of every key LED, left to right
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Calibration {
+    pub leds: [LedCalibration; KEY_COUNT],
+}
+
+impl Calibration {
+    /// No correction on any LED
+    pub const fn new() -> Self {
+        Self {
+            leds: [LedCalibration::IDENTITY; KEY_COUNT],
+        }
+    }
+
+    /// Serialize for storage
+    pub fn to_bytes(&self) -> [u8; CALIBRATION_SIZE] {
+        let mut bytes = [0; CALIBRATION_SIZE];
+        for (chunk, led) in bytes.chunks_exact_mut(LED_CALIBRATION_SIZE).zip(&self.leds) {
+            chunk.copy_from_slice(&led.to_bytes());
+        }
+        bytes
+    }
+
+    /// Deserialize from storage, rejecting out of range values
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        if bytes.len() != CALIBRATION_SIZE {
+            return Err("Wrong calibration size");
+        }
+        let mut calibration = Self::new();
+        for (led, chunk) in calibration
+            .leds
+            .iter_mut()
+            .zip(bytes.chunks_exact(LED_CALIBRATION_SIZE))
+        {
+            *led = LedCalibration::from_bytes(chunk)?;
+        }
+        Ok(calibration)
+    }
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Calibration with the gamma curves worked out, ready to apply every frame
+#[derive(Debug, Clone)]
+pub struct Calibrator {
+    leds: [LedCalibration; KEY_COUNT],
+    gamma: [[u8; 256]; KEY_COUNT],
+}
+
+impl Calibrator {
+    /// Build the gamma tables of a calibration
+    pub fn new(calibration: &Calibration) -> Self {
+        let mut calibrator = Self {
+            leds: calibration.leds,
+            gamma: [[0; 256]; KEY_COUNT],
+        };
+        for (table, led) in calibrator.gamma.iter_mut().zip(&calibration.leds) {
+            let gamma = f32::from(led.gamma_x10) / 10.0;
+            for (value, out) in table.iter_mut().enumerate() {
+                // The approximate powf is a step off in places, linear stays exact
+                *out = if led.gamma_x10 == 10 {
+                    value as u8
+                } else {
+                    let level = F32Ext::powf(value as f32 / 255.0, gamma);
+                    F32Ext::round(level * 255.0) as u8
+                };
+            }
+        }
+        calibrator
+    }
+
+    /// Corrected color of the LED at `index`, positions past the keys pass through
+    pub fn correct(&self, index: usize, color: RgbColor) -> RgbColor {
+        let (Some(led), Some(gamma)) = (self.leds.get(index), self.gamma.get(index)) else {
+            return color;
+        };
+        let input = [color.r, color.g, color.b].map(|c| i32::from(gamma[usize::from(c)]));
+        let lit = [color.r, color.g, color.b].map(|c| c > 0);
+
+        let out: [u8; 3] = core::array::from_fn(|channel| {
+            let row = led.matrix[channel];
+            let mixed = (0..3).map(|i| i32::from(row[i]) * input[i]).sum::<i32>() / 256;
+            let value = mixed.clamp(0, 255) as u8;
+            // A channel that is meant to be on never drops below the floor
+            if lit[channel] && row[channel] > 0 {
+                value.max(led.floor)
+            } else {
+                value
+            }
+        });
+        RgbColor::new(out[0], out[1], out[2])
+    }
+
+    /// Correct a whole frame in place
+    pub fn apply(&self, frame: &mut [RgbColor]) {
+        for (index, color) in frame.iter_mut().enumerate() {
+            *color = self.correct(index, *color);
+        }
+    }
+}
+
+impl Default for Calibrator {
+    fn default() -> Self {
+        Self::new(&Calibration::new())
+    }
+}
+
+/// Colors the calibration screen steps through
+pub const TEST_COLORS: [(&str, RgbColor); 6] = [
+    ("White", RgbColor::new(255, 255, 255)),
+    ("Red", RgbColor::new(255, 0, 0)),
+    ("Green", RgbColor::new(0, 255, 0)),
+    ("Blue", RgbColor::new(0, 0, 255)),
+    ("Gray", RgbColor::new(64, 64, 64)),
+    ("Dim", RgbColor::new(4, 4, 4)),
+];
+
+/// Value the calibration editor is adjusting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalibrationItem {
+    RedGain,
+    GreenGain,
+    BlueGain,
+    Gamma,
+    Floor,
+}
+
+impl CalibrationItem {
+    /// Every item, in the order the editor cycles through them
+    pub const ALL: [CalibrationItem; 5] = [
+        CalibrationItem::RedGain,
+        CalibrationItem::GreenGain,
+        CalibrationItem::BlueGain,
+        CalibrationItem::Gamma,
+        CalibrationItem::Floor,
+    ];
+
+    /// Short name for the calibration screen
+    pub fn label(self) -> &'static str {
+        match self {
+            CalibrationItem::RedGain => "Red",
+            CalibrationItem::GreenGain => "Grn",
+            CalibrationItem::BlueGain => "Blu",
+            CalibrationItem::Gamma => "Gam",
+            CalibrationItem::Floor => "Min",
+        }
+    }
+
+    /// The item after this one, wrapping around
+    pub fn next(self) -> CalibrationItem {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+
+    /// Step the item's value of one LED up or down, staying in range
+    pub fn adjust(self, led: &mut LedCalibration, up: bool) {
+        // Gains move in 1/32 steps
+        let gain = |m: &mut i16| {
+            let step = if up { UNITY / 32 } else { -UNITY / 32 };
+            *m = (*m + step).clamp(0, *MATRIX_RANGE.end());
+        };
+        let byte = |v: &mut u8, range: core::ops::RangeInclusive<u8>| {
+            let stepped = if up {
+                v.saturating_add(1)
+            } else {
+                v.saturating_sub(1)
+            };
+            *v = stepped.clamp(*range.start(), *range.end());
+        };
+        match self {
+            CalibrationItem::RedGain => gain(&mut led.matrix[0][0]),
+            CalibrationItem::GreenGain => gain(&mut led.matrix[1][1]),
+            CalibrationItem::BlueGain => gain(&mut led.matrix[2][2]),
+            CalibrationItem::Gamma => byte(&mut led.gamma_x10, GAMMA_RANGE),
+            CalibrationItem::Floor => byte(&mut led.floor, FLOOR_RANGE),
+        }
+    }
+}
+
+/// Cursor of the calibration screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CalibrationEditor {
+    /// Index into [`TEST_COLORS`]
+    pub color: usize,
+    /// LED position being adjusted
+    pub led: usize,
+    pub item: CalibrationItem,
+}
+
+impl CalibrationEditor {
+    /// Start with white on the first LED
+    pub const fn new() -> Self {
+        Self {
+            color: 0,
+            led: 0,
+            item: CalibrationItem::RedGain,
+        }
+    }
+
+    /// Name and color of the test color on show
+    pub fn test_color(&self) -> (&'static str, RgbColor) {
+        TEST_COLORS[self.color % TEST_COLORS.len()]
+    }
+
+    /// Handle a physical key, editing `calibration`; returns whether anything changed
+    pub fn press(&mut self, key: usize, calibration: &mut Calibration) -> bool {
+        match key {
+            1 => self.color = (self.color + 1) % TEST_COLORS.len(),
+            2 => self.led = (self.led + 1) % KEY_COUNT,
+            3 => self.item = self.item.next(),
+            UP_KEY | DOWN_KEY => self
+                .item
+                .adjust(&mut calibration.leds[self.led], key == UP_KEY),
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl Default for CalibrationEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::leds::colors;
+
+    #[test]
+    fn identity_passes_colors_through() {
+        let calibrator = Calibrator::default();
+        for value in [0, 1, 4, 32, 128, 255] {
+            let color = RgbColor::new(value, 255 - value, value / 2);
+            assert_eq!(calibrator.correct(0, color), color);
+        }
+        assert_eq!(calibrator.correct(KEY_COUNT, colors::RED), colors::RED);
+    }
+
+    #[test]
+    fn gamma_matrix_and_floor() {
+        let mut calibration = Calibration::new();
+        calibration.leds[1].gamma_x10 = 20;
+        // Warm the white point: a bit less blue, some red bleeding into green
+        calibration.leds[1].matrix[2][2] = 192;
+        calibration.leds[1].matrix[1][0] = 64;
+        calibration.leds[1].floor = 3;
+        let calibrator = Calibrator::new(&calibration);
+
+        assert_eq!(
+            calibrator.correct(1, colors::WHITE),
+            RgbColor::new(255, 255, 191)
+        );
+        // 128 squared is 64, the red adds a quarter of that to green
+        assert_eq!(
+            calibrator.correct(1, RgbColor::new(128, 0, 128)),
+            RgbColor::new(64, 16, 48)
+        );
+        // Dim channels vanish under gamma 2.0, the floor keeps them lit
+        assert_eq!(
+            calibrator.correct(1, RgbColor::new(4, 0, 0)),
+            RgbColor::new(3, 0, 0)
+        );
+        assert_eq!(calibrator.correct(1, colors::OFF), colors::OFF);
+        assert_eq!(
+            calibrator.correct(0, RgbColor::new(4, 0, 0)),
+            RgbColor::new(4, 0, 0)
+        );
+    }
+
+    #[test]
+    fn bytes_round_trip_and_validate() {
+        let mut calibration = Calibration::new();
+        calibration.leds[5].matrix[0][2] = -40;
+        calibration.leds[5].gamma_x10 = 24;
+        assert_eq!(
+            Calibration::from_bytes(&calibration.to_bytes()),
+            Ok(calibration)
+        );
+
+        let mut bytes = calibration.to_bytes();
+        bytes[18] = 99;
+        assert_eq!(
+            Calibration::from_bytes(&bytes),
+            Err("Calibration gamma out of range")
+        );
+        assert!(Calibration::from_bytes(&bytes[1..]).is_err());
+    }
+
+    #[test]
+    fn editor_steps_and_clamps() {
+        let mut calibration = Calibration::new();
+        let mut editor = CalibrationEditor::new();
+        assert!(editor.press(2, &mut calibration));
+        assert!(editor.press(DOWN_KEY, &mut calibration));
+        assert_eq!(calibration.leds[1].matrix[0][0], UNITY - 8);
+
+        assert!(editor.press(3, &mut calibration));
+        assert!(editor.press(3, &mut calibration));
+        assert!(editor.press(3, &mut calibration));
+        assert_eq!(editor.item, CalibrationItem::Gamma);
+        for _ in 0..40 {
+            editor.press(UP_KEY, &mut calibration);
+        }
+        assert_eq!(calibration.leds[1].gamma_x10, 30);
+        assert!(calibration.leds[1].check().is_ok());
+
+        assert!(editor.press(1, &mut calibration));
+        assert_eq!(editor.test_color().0, "Red");
+        assert!(!editor.press(0, &mut calibration));
+    }
+}
@@ -0,0 +1,640 @@
+//! LED effects engine
+//! Animated effects stacked in priority layers, rendered to frames
+//!
+//! Each layer runs one [`Effect`] on a subset of the keys. For every key the
+//! highest-priority layer that has a color for it wins, later layers winning
+//! ties, and keys no layer covers stay dark. Rendering only depends on the
+//! layers and the time passed in, so whole animations can be checked on the
+//! host. The eased effects are [`Timeline`]s that start with their layer, the
+//! same as the screen transitions.
+
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+
+use super::pattern::Pattern;
+use super::{colors, LedFrame, RgbColor};
+use crate::buttons::KeySet;
+use crate::timeline::{Easing, Lerp, Playback, Timeline};
+use crate::KEY_COUNT;
+
+/// Most layers alive at the same time
+pub const MAX_LAYERS: usize = 16;
+
+/// What a layer does to its keys over time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Effect {
+    /// Constant color
+    Solid(RgbColor),
+    /// Slowly swells up and back down once per period
+    Breathe { color: RgbColor, period: Duration },
+    /// Flashes at the start of each period and decays
+    Pulse { color: RgbColor, period: Duration },
+    /// One key at a time is lit, moving on every step, the rest show through
+    Chase { color: RgbColor, step: Duration },
+    /// Hue cycles once per period, spread across the keys
+    Rainbow { period: Duration },
+    /// Blinks `count` times, then finishes
+    Blink {
+        color: RgbColor,
+        count: u8,
+        on: Duration,
+        off: Duration,
+    },
+    /// Blends from one color to another, then finishes
+    Fade {
+        from: RgbColor,
+        to: RgbColor,
+        duration: Duration,
+        easing: Easing,
+    },
+    /// The uploaded pattern the stack is playing, see [`EffectStack::play`]
+    Pattern,
+}
+
+impl Effect {
+    /// Color of the `index`th of `count` targeted keys after `elapsed`,
+    /// `None` lets lower layers show through
+    pub fn color(&self, index: usize, count: usize, elapsed: Duration) -> Option<RgbColor> {
+        let ms = elapsed.as_millis();
+        match *self {
+            Effect::Solid(color) => Some(color),
+            Effect::Breathe { color, period } => {
+                // Up for the first half of the period, back down for the second
+                let rise = tween(Playback::PingPong, 0.0, 1.0, period / 2, Easing::EaseInOut);
+                Some(color.scale(level(value_after(&rise, elapsed)?)))
+            }
+            Effect::Pulse { color, period } => {
+                let decay = tween(Playback::Loop, 1.0, 0.0, period, Easing::Cubic);
+                Some(color.scale(level(value_after(&decay, elapsed)?)))
+            }
+            Effect::Chase { color, step } => {
+                let lit = (ms / step.as_millis().max(1)) as usize % count.max(1);
+                (lit == index).then_some(color)
+            }
+            Effect::Rainbow { period } => {
+                let spread = (index * 256 / count.max(1)) as i32;
+                let hue = tween(Playback::Loop, 0, 256, period, Easing::Linear);
+                Some(RgbColor::from_hsv(
+                    (value_after(&hue, elapsed)? + spread) as u8,
+                    255,
+                    255,
+                ))
+            }
+            Effect::Blink {
+                color,
+                count: blinks,
+                on,
+                off,
+            } => {
+                let cycle = (on + off).as_millis().max(1);
+                if ms >= cycle * u64::from(blinks) {
+                    None
+                } else if ms % cycle < on.as_millis() {
+                    Some(color)
+                } else {
+                    Some(colors::OFF)
+                }
+            }
+            Effect::Fade {
+                from,
+                to,
+                duration,
+                easing,
+            } => {
+                let fade = tween(Playback::Once, from, to, duration, easing);
+                value_after(&fade, elapsed).filter(|_| elapsed < duration)
+            }
+            // Needs the pattern, which only the stack has
+            Effect::Pattern => None,
+        }
+    }
+
+    /// How long the effect runs, `None` for forever
+    pub fn duration(&self) -> Option<Duration> {
+        match *self {
+            Effect::Blink { count, on, off, .. } => Some((on + off) * u32::from(count)),
+            Effect::Fade { duration, .. } => Some(duration),
+            _ => None,
+        }
+    }
+
+    /// Whether the colors change over time
+    pub fn is_animated(&self) -> bool {
+        !matches!(self, Effect::Solid(_))
+    }
+}
+
+/// Brightness level for a `0.0..=1.0` fraction
+fn level(fraction: f32) -> u8 {
+    (fraction * 255.0 + 0.5) as u8
+}
+
+/// Start of every effect's timeline, which runs on the layer's elapsed time
+const START: Instant = Instant::from_ticks(0);
+
+/// Eased move from one value to another that starts with the layer
+fn tween<T: Lerp>(
+    playback: Playback,
+    from: T,
+    to: T,
+    duration: Duration,
+    easing: Easing,
+) -> Timeline<T> {
+    let mut timeline = Timeline::new(START, playback);
+    // Two keyframes always fit
+    let _ = timeline.push(Duration::from_ticks(0), from, Easing::Linear);
+    let _ = timeline.push(duration, to, easing);
+    timeline
+}
+
+/// Value of an effect's timeline `elapsed` after its layer started
+fn value_after<T: Lerp>(timeline: &Timeline<T>, elapsed: Duration) -> Option<T> {
+    timeline.value_at(START + elapsed)
+}
+
+/// Stacking order of layers, later variants draw on top
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Background lighting
+    Ambient,
+    /// Per-key hints from the current screen
+    Hint,
+    /// Feedback to a key press
+    Feedback,
+    /// Something the user has to notice
+    Notification,
+}
+
+/// Handle for removing a layer again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayerId(u16);
+
+/// An effect running on some keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layer {
+    pub id: LayerId,
+    pub priority: Priority,
+    pub keys: KeySet,
+    pub effect: Effect,
+    pub started: Instant,
+    /// Removed at this time even if the effect would go on
+    pub expires: Option<Instant>,
+}
+
+impl Layer {
+    /// Whether the layer has nothing left to show at `now`
+    pub fn is_finished(&self, now: Instant) -> bool {
+        let effect_end = self.effect.duration().map(|d| self.started + d);
+        [effect_end, self.expires]
+            .into_iter()
+            .flatten()
+            .any(|end| now >= end)
+    }
+}
+
+/// Stack of effect layers
+#[derive(Debug, Clone)]
+pub struct EffectStack {
+    layers: Vec<Layer, MAX_LAYERS>,
+    next_id: u16,
+    // Played by the `Effect::Pattern` layer
+    pattern: Option<Pattern>,
+}
+
+impl EffectStack {
+    /// No layers, every key dark
+    pub const fn new() -> Self {
+        Self {
+            layers: Vec::new(),
+            next_id: 0,
+            pattern: None,
+        }
+    }
+
+    /// All live layers, in the order they were added
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    /// Start an effect on some keys
+    pub fn push(
+        &mut self,
+        priority: Priority,
+        keys: KeySet,
+        effect: Effect,
+        now: Instant,
+    ) -> Result<LayerId, &'static str> {
+        self.add(priority, keys, effect, now, None)
+    }
+
+    /// Start an effect that is removed after `lifetime`
+    pub fn push_for(
+        &mut self,
+        priority: Priority,
+        keys: KeySet,
+        effect: Effect,
+        now: Instant,
+        lifetime: Duration,
+    ) -> Result<LayerId, &'static str> {
+        self.add(priority, keys, effect, now, Some(now + lifetime))
+    }
+
+    /// Play a pattern on its keys until its duration cap, replacing the one playing
+    pub fn play(
+        &mut self,
+        priority: Priority,
+        pattern: Pattern,
+        now: Instant,
+    ) -> Result<LayerId, &'static str> {
+        self.stop_pattern();
+        let duration = pattern.duration();
+        self.pattern = Some(pattern);
+        self.push_for(priority, KeySet::ALL, Effect::Pattern, now, duration)
+    }
+
+    /// Stop the pattern playing, returning whether there was one
+    pub fn stop_pattern(&mut self) -> bool {
+        self.pattern = None;
+        let before = self.layers.len();
+        self.layers.retain(|layer| layer.effect != Effect::Pattern);
+        self.layers.len() != before
+    }
+
+    /// Remove a layer, returning whether it was still there
+    pub fn remove(&mut self, id: LayerId) -> bool {
+        let before = self.layers.len();
+        self.layers.retain(|layer| layer.id != id);
+        self.layers.len() != before
+    }
+
+    /// Remove every layer of a priority
+    pub fn clear(&mut self, priority: Priority) {
+        self.layers.retain(|layer| layer.priority != priority);
+    }
+
+    /// Drop the layers that finished by `now`
+    pub fn retire(&mut self, now: Instant) {
+        self.layers.retain(|layer| !layer.is_finished(now));
+    }
+
+    /// Whether any live layer changes over time
+    pub fn is_animated(&self) -> bool {
+        self.layers.iter().any(|layer| layer.effect.is_animated())
+    }
+
+    /// Colors of all keys at `now`
+    pub fn render(&self, now: Instant) -> LedFrame {
+        core::array::from_fn(|key| {
+            let layers = self.layers.iter().filter(|layer| layer.keys.contains(key));
+            top(layers, now, |layer, elapsed| {
+                let index = (0..key).filter(|&k| layer.keys.contains(k)).count();
+                self.color(layer, key, index, layer.keys.len() as usize, elapsed)
+            })
+        })
+    }
+
+    /// Colors of an extra LED chain of any length at `now`
+    ///
+    /// Only layers on every key show, stretched over the chain, so a strip
+    /// follows board-wide effects and notifications but not per-key hints.
+    pub fn render_chain(&self, now: Instant, chain: &mut [RgbColor]) {
+        let count = chain.len();
+        for (index, color) in chain.iter_mut().enumerate() {
+            let layers = self.layers.iter().filter(|layer| layer.keys == KeySet::ALL);
+            // Patterns target keys, each LED follows the key it lines up with
+            let key = index * KEY_COUNT / count;
+            *color = top(layers, now, |layer, elapsed| {
+                self.color(layer, key, index, count, elapsed)
+            });
+        }
+    }
+
+    /// Color of the `index`th of `count` LEDs of a layer, at `key`
+    fn color(
+        &self,
+        layer: &Layer,
+        key: usize,
+        index: usize,
+        count: usize,
+        elapsed: Duration,
+    ) -> Option<RgbColor> {
+        match layer.effect {
+            Effect::Pattern => self.pattern.as_ref()?.color(key, elapsed),
+            effect => effect.color(index, count, elapsed),
+        }
+    }
+
+    fn add(
+        &mut self,
+        priority: Priority,
+        keys: KeySet,
+        effect: Effect,
+        now: Instant,
+        expires: Option<Instant>,
+    ) -> Result<LayerId, &'static str> {
+        let id = LayerId(self.next_id);
+        self.layers
+            .push(Layer {
+                id,
+                priority,
+                keys,
+                effect,
+                started: now,
+                expires,
+            })
+            .map_err(|_| "Too many LED layers")?;
+        self.next_id = self.next_id.wrapping_add(1);
+        Ok(id)
+    }
+}
+
+/// Color of the top layer with something to show, dark if none has
+///
+/// `color` gives a layer's color for the LED after some time.
+fn top<'a>(
+    layers: impl DoubleEndedIterator<Item = &'a Layer>,
+    now: Instant,
+    color: impl Fn(&Layer, Duration) -> Option<RgbColor>,
+) -> RgbColor {
+    layers
+        .rev()
+        .filter(|layer| !layer.is_finished(now))
+        .filter_map(|layer| {
+            let elapsed = now.checked_duration_since(layer.started)?;
+            Some((layer.priority, color(layer, elapsed)?))
+        })
+        // Newest first, only a strictly higher priority takes over
+        .fold(
+            None,
+            |best: Option<(Priority, RgbColor)>, (priority, color)| match best {
+                Some((top, _)) if top >= priority => best,
+                _ => Some((priority, color)),
+            },
+        )
+        .map_or(colors::OFF, |(_, color)| color)
+}
+
+impl Default for EffectStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(t: u64) -> Instant {
+        Instant::from_millis(t)
+    }
+
+    fn period(t: u64) -> Duration {
+        Duration::from_millis(t)
+    }
+
+    #[test]
+    fn higher_priority_wins_on_its_keys_only() {
+        let mut stack = EffectStack::new();
+        stack
+            .push(
+                Priority::Ambient,
+                KeySet::ALL,
+                Effect::Solid(colors::DIM_BLUE),
+                ms(0),
+            )
+            .unwrap();
+        stack
+            .push(
+                Priority::Notification,
+                KeySet::of(&[1]),
+                Effect::Solid(colors::RED),
+                ms(0),
+            )
+            .unwrap();
+        // Added later but lower, stays under the notification
+        stack
+            .push(
+                Priority::Hint,
+                KeySet::of(&[1, 2]),
+                Effect::Solid(colors::GREEN),
+                ms(0),
+            )
+            .unwrap();
+
+        let frame = stack.render(ms(10));
+        assert_eq!(frame[0], colors::DIM_BLUE);
+        assert_eq!(frame[1], colors::RED);
+        assert_eq!(frame[2], colors::GREEN);
+    }
+
+    #[test]
+    fn uncovered_keys_are_dark() {
+        let mut stack = EffectStack::new();
+        stack
+            .push(
+                Priority::Hint,
+                KeySet::of(&[3]),
+                Effect::Solid(colors::WHITE),
+                ms(0),
+            )
+            .unwrap();
+        let frame = stack.render(ms(0));
+        assert_eq!(frame[3], colors::WHITE);
+        assert_eq!(frame[0], colors::OFF);
+    }
+
+    #[test]
+    fn blink_n_finishes_and_reveals_lower_layers() {
+        let mut stack = EffectStack::new();
+        stack
+            .push(
+                Priority::Ambient,
+                KeySet::ALL,
+                Effect::Solid(colors::DIM_GREEN),
+                ms(0),
+            )
+            .unwrap();
+        let blink = Effect::Blink {
+            color: colors::RED,
+            count: 2,
+            on: period(100),
+            off: period(100),
+        };
+        stack
+            .push(Priority::Notification, KeySet::ALL, blink, ms(1000))
+            .unwrap();
+
+        assert_eq!(stack.render(ms(1050))[0], colors::RED);
+        assert_eq!(stack.render(ms(1150))[0], colors::OFF);
+        assert_eq!(stack.render(ms(1250))[0], colors::RED);
+        assert_eq!(stack.render(ms(1400))[0], colors::DIM_GREEN);
+
+        stack.retire(ms(1400));
+        assert_eq!(stack.layers().len(), 1);
+    }
+
+    #[test]
+    fn chase_walks_the_targeted_keys() {
+        let mut stack = EffectStack::new();
+        let chase = Effect::Chase {
+            color: colors::CYAN,
+            step: period(50),
+        };
+        stack
+            .push(Priority::Hint, KeySet::of(&[1, 3, 5]), chase, ms(0))
+            .unwrap();
+
+        let lit = |t| {
+            let frame = stack.render(ms(t));
+            (0..KEY_COUNT)
+                .filter(|&k| frame[k] == colors::CYAN)
+                .collect::<Vec<usize, KEY_COUNT>>()
+        };
+        assert_eq!(lit(0).as_slice(), &[1]);
+        assert_eq!(lit(60).as_slice(), &[3]);
+        assert_eq!(lit(110).as_slice(), &[5]);
+        assert_eq!(lit(160).as_slice(), &[1]);
+    }
+
+    #[test]
+    fn breathe_and_pulse_levels() {
+        let breathe = Effect::Breathe {
+            color: colors::WHITE,
+            period: period(1000),
+        };
+        assert_eq!(breathe.color(0, 1, period(0)), Some(colors::OFF));
+        assert_eq!(breathe.color(0, 1, period(500)), Some(colors::WHITE));
+        // Eased, so a quarter of the way is well below half brightness
+        let eighth = breathe.color(0, 1, period(125)).unwrap();
+        assert!(eighth.r > 20 && eighth.r < 40);
+        assert_eq!(breathe.color(0, 1, period(250)).unwrap().r, 128);
+
+        let pulse = Effect::Pulse {
+            color: colors::WHITE,
+            period: period(1000),
+        };
+        assert_eq!(pulse.color(0, 1, period(0)), Some(colors::WHITE));
+        assert!(pulse.color(0, 1, period(900)).unwrap().r < 5);
+
+        // Both start over every period, however long they have run
+        assert_eq!(breathe.color(0, 1, period(7000)), Some(colors::OFF));
+        assert_eq!(breathe.color(0, 1, period(7500)), Some(colors::WHITE));
+        assert_eq!(pulse.color(0, 1, period(7000)), Some(colors::WHITE));
+    }
+
+    #[test]
+    fn fade_blends_then_finishes() {
+        let fade = Effect::Fade {
+            from: colors::OFF,
+            to: colors::WHITE,
+            duration: period(200),
+            easing: Easing::Linear,
+        };
+        let mid = fade.color(0, 1, period(100)).unwrap();
+        assert!(mid.r > 120 && mid.r < 135);
+        assert_eq!(fade.color(0, 1, period(200)), None);
+    }
+
+    #[test]
+    fn rainbow_spreads_hues_across_keys() {
+        let rainbow = Effect::Rainbow {
+            period: period(1000),
+        };
+        assert_eq!(rainbow.color(0, 6, period(0)), Some(colors::RED));
+        assert_ne!(rainbow.color(3, 6, period(0)), Some(colors::RED));
+    }
+
+    #[test]
+    fn chains_follow_board_wide_layers() {
+        let mut stack = EffectStack::new();
+        let rainbow = Effect::Rainbow {
+            period: period(1000),
+        };
+        stack
+            .push(Priority::Ambient, KeySet::ALL, rainbow, ms(0))
+            .unwrap();
+        stack
+            .push(
+                Priority::Hint,
+                KeySet::of(&[0]),
+                Effect::Solid(colors::DIM_BLUE),
+                ms(0),
+            )
+            .unwrap();
+
+        let mut chain = [colors::WHITE; 30];
+        stack.render_chain(ms(0), &mut chain);
+        assert_eq!(chain[0], colors::RED);
+        assert_eq!(chain[15], RgbColor::from_hsv(128, 255, 255));
+        assert_eq!(stack.render(ms(0))[0], colors::DIM_BLUE);
+
+        stack.clear(Priority::Ambient);
+        stack.render_chain(ms(0), &mut chain);
+        assert_eq!(chain, [colors::OFF; 30]);
+    }
+
+    #[test]
+    fn patterns_play_until_their_cap() {
+        use crate::leds::pattern::PatternEncoder;
+        use crate::timeline::Playback;
+
+        let mut encoder = PatternEncoder::new(period(1000));
+        encoder.track(KeySet::of(&[1]), Playback::Once, 0).unwrap();
+        encoder
+            .keyframe(period(0), colors::DIM_GREEN, Easing::Linear)
+            .unwrap();
+        let pattern = Pattern::decode(&encoder.finish()).unwrap();
+
+        let mut stack = EffectStack::new();
+        stack
+            .push(
+                Priority::Ambient,
+                KeySet::ALL,
+                Effect::Solid(colors::RED),
+                ms(0),
+            )
+            .unwrap();
+        stack.play(Priority::Feedback, pattern, ms(100)).unwrap();
+        let frame = stack.render(ms(500));
+        assert_eq!(frame[1], colors::DIM_GREEN);
+        // Keys the pattern leaves alone show what is below
+        assert_eq!(frame[0], colors::RED);
+
+        stack.retire(ms(1100));
+        assert_eq!(stack.render(ms(1100))[1], colors::RED);
+        assert!(!stack.stop_pattern());
+    }
+
+    #[test]
+    fn expiring_and_removed_layers() {
+        let mut stack = EffectStack::new();
+        let rainbow = Effect::Rainbow {
+            period: period(1000),
+        };
+        stack
+            .push_for(
+                Priority::Notification,
+                KeySet::ALL,
+                rainbow,
+                ms(0),
+                period(2000),
+            )
+            .unwrap();
+        let solid = stack
+            .push(
+                Priority::Ambient,
+                KeySet::ALL,
+                Effect::Solid(colors::DIM_RED),
+                ms(0),
+            )
+            .unwrap();
+        assert!(stack.is_animated());
+        assert_eq!(stack.render(ms(2000))[0], colors::DIM_RED);
+
+        stack.retire(ms(2000));
+        assert!(!stack.is_animated());
+        assert!(stack.remove(solid));
+        assert!(!stack.remove(solid));
+        assert_eq!(stack.render(ms(2000))[0], colors::OFF);
+    }
+}
@@ -0,0 +1,185 @@
+//! LED bit encoding
+//! Turns frames into RMT pulse codes, independent of the RMT driver
+//!
+//! A pulse code is the 32 bit word the ESP32 RMT memory holds: a 15 bit
+//! duration and a level bit for each of two half-periods. A one-wire LED bit
+//! is one code, high then low, with the high time telling a 0 from a 1.
+
+use super::format::PixelFormat;
+use super::RgbColor;
+
+/// WS2812B 0 bit high time in ns
+pub const T0H: u32 = 400;
+/// WS2812B 0 bit low time in ns
+pub const T0L: u32 = 850;
+/// WS2812B 1 bit high time in ns
+pub const T1H: u32 = 800;
+/// WS2812B 1 bit low time in ns
+pub const T1L: u32 = 450;
+
+/// SK6812 0 bit high time in ns
+pub const SK6812_T0H: u32 = 300;
+/// SK6812 0 bit low time in ns
+pub const SK6812_T0L: u32 = 900;
+/// SK6812 1 bit high time in ns
+pub const SK6812_T1H: u32 = 600;
+/// SK6812 1 bit low time in ns
+pub const SK6812_T1L: u32 = 600;
+
+/// Most pulse codes one LED takes, one per bit of RGBW
+pub const MAX_CODES_PER_LED: usize = 32;
+/// Zero-length code that stops the RMT, the line then idles low
+pub const END_MARKER: u32 = 0;
+
+/// Convert nanoseconds to ticks of an RMT clock
+pub const fn ticks(ns: u32, clock_mhz: u32) -> u16 {
+    (ns * clock_mhz / 1000) as u16
+}
+
+/// Pulse code that is high for `high` ticks, then low for `low` ticks
+pub const fn pulse_code(high: u16, low: u16) -> u32 {
+    ((low as u32 & 0x7fff) << 16) | (1 << 15) | (high as u32 & 0x7fff)
+}
+
+/// Pulse codes of a 0 and a 1 bit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitCodes {
+    pub zero: u32,
+    pub one: u32,
+}
+
+impl BitCodes {
+    /// WS2812B bits at an RMT clock of `clock_mhz`
+    pub const fn ws2812b(clock_mhz: u32) -> Self {
+        Self {
+            zero: pulse_code(ticks(T0H, clock_mhz), ticks(T0L, clock_mhz)),
+            one: pulse_code(ticks(T1H, clock_mhz), ticks(T1L, clock_mhz)),
+        }
+    }
+
+    /// SK6812 bits at an RMT clock of `clock_mhz`
+    pub const fn sk6812(clock_mhz: u32) -> Self {
+        Self {
+            zero: pulse_code(ticks(SK6812_T0H, clock_mhz), ticks(SK6812_T0L, clock_mhz)),
+            one: pulse_code(ticks(SK6812_T1H, clock_mhz), ticks(SK6812_T1L, clock_mhz)),
+        }
+    }
+
+    /// Bits of a one-wire format, `None` for clocked chains
+    pub fn for_format(format: PixelFormat, clock_mhz: u32) -> Option<Self> {
+        match format {
+            PixelFormat::Grb | PixelFormat::Rgb => Some(Self::ws2812b(clock_mhz)),
+            PixelFormat::Grbw | PixelFormat::Rgbw => Some(Self::sk6812(clock_mhz)),
+            PixelFormat::Apa102 => None,
+        }
+    }
+}
+
+/// Buffer size for `leds` LEDs of any format, with room for the end marker
+pub const fn buffer_size(leds: usize) -> usize {
+    leds * MAX_CODES_PER_LED + 1
+}
+
+/// Encode colors in a format's byte order, MSB first, and end with the end marker
+///
+/// Returns how many codes were written. LEDs that don't fit in `out` are
+/// left off, the chain stays dark past them.
+pub fn encode(colors: &[RgbColor], format: PixelFormat, bits: BitCodes, out: &mut [u32]) -> usize {
+    let Some(room) = out.len().checked_sub(1) else {
+        return 0;
+    };
+    let bytes = format.bytes_per_pixel();
+    let leds = colors.len().min(room / (bytes * 8));
+
+    let mut len = 0;
+    for color in &colors[..leds] {
+        for &byte in &format.pixel(*color)[..bytes] {
+            for bit in (0..8).rev() {
+                out[len] = if (byte >> bit) & 1 == 1 {
+                    bits.one
+                } else {
+                    bits.zero
+                };
+                len += 1;
+            }
+        }
+    }
+    out[len] = END_MARKER;
+    len + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLOCK_MHZ: u32 = 80;
+
+    fn high(code: u32) -> u32 {
+        code & 0x7fff
+    }
+
+    fn low(code: u32) -> u32 {
+        (code >> 16) & 0x7fff
+    }
+
+    #[test]
+    fn bit_codes_follow_the_datasheet() {
+        let bits = BitCodes::ws2812b(CLOCK_MHZ);
+        // 12.5 ns per tick at 80 MHz
+        assert_eq!(high(bits.zero), T0H * 80 / 1000);
+        assert_eq!(low(bits.zero), T0L * 80 / 1000);
+        assert_eq!(high(bits.one), T1H * 80 / 1000);
+        assert_eq!(low(bits.one), T1L * 80 / 1000);
+        assert_eq!(high(bits.zero), 32);
+        assert_eq!(high(bits.one), 64);
+        // High first, then low
+        assert_eq!(bits.one & (1 << 15), 1 << 15);
+        assert_eq!(bits.one & (1 << 31), 0);
+    }
+
+    #[test]
+    fn sk6812_bits_and_white_byte() {
+        let bits = BitCodes::for_format(PixelFormat::Grbw, CLOCK_MHZ).unwrap();
+        assert_eq!(high(bits.zero), SK6812_T0H * 80 / 1000);
+        assert_eq!(high(bits.one), SK6812_T1H * 80 / 1000);
+        assert_eq!(BitCodes::for_format(PixelFormat::Apa102, CLOCK_MHZ), None);
+
+        let mut out = [0; buffer_size(1)];
+        let white = RgbColor::new(0x80, 0x80, 0x80);
+        assert_eq!(encode(&[white], PixelFormat::Grbw, bits, &mut out), 33);
+        // Only the MSB of the white byte is set
+        let ones: Vec<usize> = (0..32).filter(|&i| out[i] == bits.one).collect();
+        assert_eq!(ones, [24]);
+    }
+
+    #[test]
+    fn colors_go_out_grb_msb_first() {
+        let bits = BitCodes::ws2812b(CLOCK_MHZ);
+        let mut out = [0xdead; buffer_size(1)];
+        let color = RgbColor::new(0x01, 0x80, 0x00);
+        let len = encode(&[color], PixelFormat::Grb, bits, &mut out);
+        assert_eq!(len, 25);
+
+        let expected = |i: usize| match i {
+            0 | 15 => bits.one,
+            _ => bits.zero,
+        };
+        for (i, &code) in out[..24].iter().enumerate() {
+            assert_eq!(code, expected(i), "bit {i}");
+        }
+        assert_eq!(out[24], END_MARKER);
+    }
+
+    #[test]
+    fn long_chains_are_cut_to_the_buffer() {
+        let bits = BitCodes::ws2812b(CLOCK_MHZ);
+        let colors = [RgbColor::new(1, 2, 3); 5];
+        let mut out = [0; 48];
+        // One LED and the end marker fit, the rest is left off
+        assert_eq!(encode(&colors, PixelFormat::Grb, bits, &mut out), 25);
+        assert_eq!(out[24], END_MARKER);
+        assert_eq!(encode(&colors, PixelFormat::Grbw, bits, &mut out), 33);
+        assert_eq!(encode(&colors, PixelFormat::Grb, bits, &mut []), 0);
+        assert_eq!(encode(&[], PixelFormat::Grb, bits, &mut out), 1);
+    }
+}
@@ -0,0 +1,232 @@
+//! Pixel formats
+//! Byte order and wire protocol of an LED chain
+//!
+//! Effects, calibration and the power budget all work on [`RgbColor`]. A
+//! chain's format only comes in at the very end, when a frame is turned into
+//! the bytes its LEDs expect, so any effect runs on any strip.
+
+use super::RgbColor;
+
+/// Wire format of the LEDs on a chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PixelFormat {
+    /// WS2812B and SK6805, one-wire, green first
+    Grb,
+    /// One-wire RGB parts such as WS2811 strips
+    Rgb,
+    /// SK6812 RGBW, one-wire, green first
+    Grbw,
+    /// One-wire RGBW in red-first order
+    Rgbw,
+    /// APA102 and SK9822 on an SPI data and clock pair
+    Apa102,
+}
+
+impl PixelFormat {
+    /// Every format, in storage order
+    pub const ALL: [PixelFormat; 5] = [
+        PixelFormat::Grb,
+        PixelFormat::Rgb,
+        PixelFormat::Grbw,
+        PixelFormat::Rgbw,
+        PixelFormat::Apa102,
+    ];
+
+    /// Name used on the console
+    pub fn name(self) -> &'static str {
+        match self {
+            PixelFormat::Grb => "grb",
+            PixelFormat::Rgb => "rgb",
+            PixelFormat::Grbw => "grbw",
+            PixelFormat::Rgbw => "rgbw",
+            PixelFormat::Apa102 => "apa102",
+        }
+    }
+
+    /// Look a format up by its console name
+    pub fn from_name(name: &str) -> Option<PixelFormat> {
+        Self::ALL.into_iter().find(|format| format.name() == name)
+    }
+
+    /// Decode a stored format
+    pub fn from_u8(value: u8) -> Option<PixelFormat> {
+        Self::ALL.get(usize::from(value)).copied()
+    }
+
+    /// Whether the chain has a clock line and is driven over SPI
+    pub fn is_clocked(self) -> bool {
+        self == PixelFormat::Apa102
+    }
+
+    /// Bytes of one LED on the wire
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Grb | PixelFormat::Rgb => 3,
+            PixelFormat::Grbw | PixelFormat::Rgbw | PixelFormat::Apa102 => 4,
+        }
+    }
+
+    /// Wire bytes of one LED, the first [`bytes_per_pixel`](Self::bytes_per_pixel) are used
+    pub fn pixel(self, color: RgbColor) -> [u8; 4] {
+        match self {
+            PixelFormat::Grb => [color.g, color.r, color.b, 0],
+            PixelFormat::Rgb => [color.r, color.g, color.b, 0],
+            PixelFormat::Grbw => {
+                let (rgb, w) = extract_white(color);
+                [rgb.g, rgb.r, rgb.b, w]
+            }
+            PixelFormat::Rgbw => {
+                let (rgb, w) = extract_white(color);
+                [rgb.r, rgb.g, rgb.b, w]
+            }
+            // Full global brightness, the color values carry the level
+            PixelFormat::Apa102 => [0xe0 | APA102_MAX_BRIGHTNESS, color.b, color.g, color.r],
+        }
+    }
+}
+
+/// Split off the part all three channels share for the white LED
+///
+/// The white die is brighter and more neutral than the three color dies
+/// mixed, and a single die draws less current.
+pub fn extract_white(color: RgbColor) -> (RgbColor, u8) {
+    let w = color.r.min(color.g).min(color.b);
+    (RgbColor::new(color.r - w, color.g - w, color.b - w), w)
+}
+
+/// Top of the APA102 5 bit global brightness
+const APA102_MAX_BRIGHTNESS: u8 = 0x1f;
+
+/// Bytes an APA102/SK9822 frame of `leds` LEDs takes
+///
+/// A zero start frame, the LEDs, a zero reset frame that SK9822 needs to
+/// latch, and half a clock per LED to push the data to the end of the chain.
+pub const fn apa102_frame_size(leds: usize) -> usize {
+    4 + 4 * leds + 4 + leds.div_ceil(16)
+}
+
+/// Encode a whole APA102/SK9822 frame, returning how many bytes were written
+///
+/// LEDs that don't fit in `out` are left off.
+pub fn encode_apa102(colors: &[RgbColor], out: &mut [u8]) -> usize {
+    if out.len() < apa102_frame_size(0) {
+        return 0;
+    }
+    let leds = (0..=colors.len())
+        .rev()
+        .find(|&leds| apa102_frame_size(leds) <= out.len())
+        .unwrap_or(0);
+
+    out[..4].fill(0);
+    for (chunk, color) in out[4..].chunks_exact_mut(4).zip(&colors[..leds]) {
+        chunk.copy_from_slice(&PixelFormat::Apa102.pixel(*color));
+    }
+    let end = apa102_frame_size(leds);
+    out[4 + 4 * leds..end].fill(0);
+    end
+}
+
+/// Most LEDs on an external strip
+pub const MAX_STRIP_LEDS: u8 = 60;
+
+/// Format and length of the external strip, no LEDs for no strip
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StripConfig {
+    pub format: PixelFormat,
+    pub len: u8,
+}
+
+impl StripConfig {
+    /// No strip connected
+    pub const fn new() -> Self {
+        Self {
+            format: PixelFormat::Grb,
+            len: 0,
+        }
+    }
+
+    /// Serialize for storage
+    pub fn to_bytes(&self) -> [u8; 2] {
+        [self.format as u8, self.len]
+    }
+
+    /// Deserialize from storage, rejecting unknown formats and long strips
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        let &[format, len] = bytes else {
+            return Err("Wrong strip config size");
+        };
+        let format = PixelFormat::from_u8(format).ok_or("Unknown pixel format")?;
+        if len > MAX_STRIP_LEDS {
+            return Err("Strip too long");
+        }
+        Ok(Self { format, len })
+    }
+}
+
+impl Default for StripConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_orders() {
+        let color = RgbColor::new(1, 2, 3);
+        assert_eq!(PixelFormat::Grb.pixel(color)[..3], [2, 1, 3]);
+        assert_eq!(PixelFormat::Rgb.pixel(color)[..3], [1, 2, 3]);
+        assert_eq!(PixelFormat::Apa102.pixel(color), [0xff, 3, 2, 1]);
+    }
+
+    #[test]
+    fn rgbw_takes_the_common_part_as_white() {
+        let warm = RgbColor::new(200, 150, 100);
+        assert_eq!(PixelFormat::Grbw.pixel(warm), [50, 100, 0, 100]);
+        assert_eq!(PixelFormat::Rgbw.pixel(warm), [100, 50, 0, 100]);
+        assert_eq!(
+            extract_white(RgbColor::new(255, 255, 255)),
+            (RgbColor::new(0, 0, 0), 255)
+        );
+        assert_eq!(
+            PixelFormat::Grbw.pixel(RgbColor::new(0, 9, 0)),
+            [9, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn apa102_frame_layout() {
+        let colors = [RgbColor::new(10, 20, 30); 3];
+        let mut out = [0xaa; 32];
+        let len = encode_apa102(&colors, &mut out);
+        assert_eq!(len, apa102_frame_size(3));
+        assert_eq!(len, 4 + 12 + 4 + 1);
+        assert_eq!(out[..4], [0; 4]);
+        assert_eq!(out[4..8], [0xff, 30, 20, 10]);
+        assert_eq!(out[16..len], [0; 5]);
+        assert_eq!(out[len], 0xaa);
+
+        // Too short for all of them, the tail of the chain is left off
+        let mut out = [0; 17];
+        assert_eq!(encode_apa102(&colors, &mut out), apa102_frame_size(2));
+        assert_eq!(encode_apa102(&colors, &mut [0; 4]), 0);
+    }
+
+    #[test]
+    fn strip_config_round_trip() {
+        let strip = StripConfig {
+            format: PixelFormat::Rgbw,
+            len: 30,
+        };
+        assert_eq!(StripConfig::from_bytes(&strip.to_bytes()), Ok(strip));
+        assert_eq!(
+            StripConfig::from_bytes(&[9, 1]),
+            Err("Unknown pixel format")
+        );
+        assert_eq!(StripConfig::from_bytes(&[0, 200]), Err("Strip too long"));
+        assert_eq!(PixelFormat::from_name("apa102"), Some(PixelFormat::Apa102));
+    }
+}
@@ -0,0 +1,408 @@
+//! Notification lights
+//! What the key LEDs say when something needs the user, screen or no screen
+//!
+//! Every notification has its own key, color and rhythm, so it can be told
+//! apart at a glance across the room:
+//!
+//! | Notification        | Keys        | Pattern                          |
+//! |---------------------|-------------|----------------------------------|
+//! | Update pending      | Settings    | cyan, breathing every 4 s        |
+//! | Route ready         | Trip        | green, breathing every 3 s       |
+//! | Backend unreachable | Menu        | magenta, breathing every 4 s     |
+//! | WiFi down           | Settings    | red, a pulse every 2 s           |
+//! | Leave now           | every key   | yellow, a pulse every second     |
+//! | Mic recording       | Mic         | steady red                       |
+//!
+//! Keys are the ones bound to the action on the main screen, so they follow
+//! the key map. Where two notifications share a key the one lower in the
+//! table shows. Pressing a key that shows a notification acknowledges it
+//! until it is raised again; the press still does what the key does.
+//!
+//! During quiet hours only the mic light shows, it is a privacy indicator.
+//! The others wait and show when quiet hours end, unless cleared first.
+
+use core::fmt;
+
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+
+use super::effects::{Effect, EffectStack, LayerId, Priority};
+use super::{colors, RgbColor};
+use crate::buttons::KeySet;
+use crate::clock::TimeOfDay;
+use crate::keymap::{Action, Keymap};
+use crate::menu::MenuScreen;
+
+/// Something the LEDs tell the user, in drawing order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notification {
+    UpdatePending,
+    RouteReady,
+    BackendUnreachable,
+    WifiDown,
+    LeaveNow,
+    MicRecording,
+}
+
+impl Notification {
+    /// Every notification, lowest first
+    pub const ALL: [Notification; 6] = [
+        Notification::UpdatePending,
+        Notification::RouteReady,
+        Notification::BackendUnreachable,
+        Notification::WifiDown,
+        Notification::LeaveNow,
+        Notification::MicRecording,
+    ];
+
+    /// Name used on the console
+    pub fn name(self) -> &'static str {
+        match self {
+            Notification::UpdatePending => "update",
+            Notification::RouteReady => "route",
+            Notification::BackendUnreachable => "backend",
+            Notification::WifiDown => "wifi",
+            Notification::LeaveNow => "leave",
+            Notification::MicRecording => "mic",
+        }
+    }
+
+    /// Look a notification up by its console name
+    pub fn from_name(name: &str) -> Option<Notification> {
+        Self::ALL.into_iter().find(|n| n.name() == name)
+    }
+
+    /// Main screen action whose key shows it, `None` for every key
+    pub fn action(self) -> Option<Action> {
+        match self {
+            Notification::UpdatePending | Notification::WifiDown => Some(Action::Settings),
+            Notification::RouteReady => Some(Action::Trip),
+            Notification::BackendUnreachable => Some(Action::Menu),
+            Notification::LeaveNow => None,
+            Notification::MicRecording => Some(Action::Mic),
+        }
+    }
+
+    /// Keys it shows on with a key map
+    pub fn keys(self, keymap: &Keymap) -> KeySet {
+        let Some(action) = self.action() else {
+            return KeySet::ALL;
+        };
+        // An unbound action still shows, on its key of the printed layout
+        let key = keymap
+            .key_for(MenuScreen::Main, action)
+            .or_else(|| Keymap::new().key_for(MenuScreen::Main, action));
+        key.map_or(KeySet::EMPTY, |key| KeySet::of(&[key]))
+    }
+
+    /// What its keys show
+    pub fn effect(self) -> Effect {
+        let breathe = |color: RgbColor, ms: u64| Effect::Breathe {
+            color,
+            period: Duration::from_millis(ms),
+        };
+        let pulse = |color: RgbColor, ms: u64| Effect::Pulse {
+            color,
+            period: Duration::from_millis(ms),
+        };
+        match self {
+            Notification::UpdatePending => breathe(colors::CYAN, 4000),
+            Notification::RouteReady => breathe(colors::GREEN, 3000),
+            Notification::BackendUnreachable => breathe(colors::MAGENTA, 4000),
+            Notification::WifiDown => pulse(colors::RED, 2000),
+            Notification::LeaveNow => pulse(colors::YELLOW, 1000),
+            Notification::MicRecording => Effect::Solid(colors::RED),
+        }
+    }
+
+    /// Whether a key press can dismiss it, the mic light stays while recording
+    pub fn is_acknowledgeable(self) -> bool {
+        self != Notification::MicRecording
+    }
+
+    /// Whether it shows during quiet hours
+    pub fn shows_in_quiet_hours(self) -> bool {
+        self == Notification::MicRecording
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Daily window in which notifications stay dark, off when start and end match
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuietHours {
+    pub start: TimeOfDay,
+    pub end: TimeOfDay,
+}
+
+impl QuietHours {
+    /// No quiet hours
+    pub const OFF: QuietHours = QuietHours {
+        start: TimeOfDay::from_minutes(0),
+        end: TimeOfDay::from_minutes(0),
+    };
+
+    /// Whether there is a window at all
+    pub fn is_off(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Whether `time` falls in the window, which may run past midnight
+    pub fn contains(&self, time: TimeOfDay) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// Serialize for storage
+    pub fn to_bytes(&self) -> [u8; 4] {
+        let [a, b] = self.start.minutes().to_le_bytes();
+        let [c, d] = self.end.minutes().to_le_bytes();
+        [a, b, c, d]
+    }
+
+    /// Deserialize from storage
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        let &[a, b, c, d] = bytes else {
+            return Err("Wrong quiet hours size");
+        };
+        let time = |minutes: u16| {
+            (minutes < crate::clock::MINUTES_PER_DAY)
+                .then(|| TimeOfDay::from_minutes(minutes))
+                .ok_or("Quiet hours out of range")
+        };
+        Ok(Self {
+            start: time(u16::from_le_bytes([a, b]))?,
+            end: time(u16::from_le_bytes([c, d]))?,
+        })
+    }
+
+    /// Parse `HH:MM-HH:MM`, or `off`
+    pub fn parse(text: &str) -> Option<Self> {
+        if text == "off" {
+            return Some(Self::OFF);
+        }
+        let (start, end) = text.split_once('-')?;
+        Some(Self {
+            start: TimeOfDay::parse(start)?,
+            end: TimeOfDay::parse(end)?,
+        })
+    }
+}
+
+impl fmt::Display for QuietHours {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_off() {
+            f.write_str("off")
+        } else {
+            write!(f, "{}-{}", self.start, self.end)
+        }
+    }
+}
+
+impl Default for QuietHours {
+    fn default() -> Self {
+        Self::OFF
+    }
+}
+
+/// Raised notifications and the effect layers showing them
+#[derive(Debug, Clone)]
+pub struct Notifier {
+    raised: u8,
+    acknowledged: u8,
+    // What the layers were built for, to leave running animations alone
+    shown: u8,
+    keymap: Keymap,
+    layers: [Option<LayerId>; Notification::ALL.len()],
+}
+
+impl Notifier {
+    /// Nothing raised
+    pub const fn new(keymap: Keymap) -> Self {
+        Self {
+            raised: 0,
+            acknowledged: 0,
+            shown: 0,
+            keymap,
+            layers: [None; Notification::ALL.len()],
+        }
+    }
+
+    /// Start telling the user, a no-op if it is already raised
+    pub fn raise(&mut self, notification: Notification) {
+        self.raised |= notification.bit();
+    }
+
+    /// Stop telling the user, the next raise shows again
+    pub fn clear(&mut self, notification: Notification) {
+        self.raised &= !notification.bit();
+        self.acknowledged &= !notification.bit();
+    }
+
+    /// Whether a notification is raised, shown or not
+    pub fn is_raised(&self, notification: Notification) -> bool {
+        self.raised & notification.bit() != 0
+    }
+
+    /// Follow a changed key map
+    pub fn set_keymap(&mut self, keymap: Keymap) {
+        if keymap != self.keymap {
+            self.keymap = keymap;
+            // Rebuild the layers on their new keys
+            self.shown = 0;
+        }
+    }
+
+    /// Notifications on the LEDs, given whether it is quiet hours
+    pub fn shown(&self, quiet: bool) -> impl Iterator<Item = Notification> + '_ {
+        let pending = self.raised & !self.acknowledged;
+        Notification::ALL
+            .into_iter()
+            .filter(move |n| pending & n.bit() != 0 && (!quiet || n.shows_in_quiet_hours()))
+    }
+
+    /// Acknowledge what a pressed key shows, returning whether it showed anything
+    pub fn acknowledge(&mut self, key: usize, quiet: bool) -> bool {
+        let hit = self
+            .shown(quiet)
+            .filter(|n| n.is_acknowledgeable() && n.keys(&self.keymap).contains(key))
+            .fold(0, |bits, n| bits | n.bit());
+        self.acknowledged |= hit;
+        hit != 0
+    }
+
+    /// Bring the notification layers of a stack up to date
+    ///
+    /// Layers are only rebuilt when what shows changes, so running
+    /// animations keep their phase.
+    pub fn apply(
+        &mut self,
+        stack: &mut EffectStack,
+        quiet: bool,
+        now: Instant,
+    ) -> Result<(), &'static str> {
+        let shown = self.shown(quiet).fold(0, |bits, n| bits | n.bit());
+        if shown == self.shown {
+            return Ok(());
+        }
+        for id in self.layers.iter_mut().filter_map(Option::take) {
+            stack.remove(id);
+        }
+        self.shown = shown;
+        // Pushed lowest first, so the later ones draw on top
+        for notification in self
+            .shown(quiet)
+            .collect::<Vec<_, { Notification::ALL.len() }>>()
+        {
+            let keys = notification.keys(&self.keymap);
+            let effect = notification.effect();
+            let id = stack.push(Priority::Notification, keys, effect, now)?;
+            self.layers[notification as usize] = Some(id);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quiet_night() -> QuietHours {
+        QuietHours::parse("22:30-07:00").unwrap()
+    }
+
+    #[test]
+    fn notifications_are_distinct() {
+        let keymap = Keymap::new();
+        for (i, a) in Notification::ALL.iter().enumerate() {
+            assert_eq!(Notification::from_name(a.name()), Some(*a));
+            for b in &Notification::ALL[i + 1..] {
+                let same_keys = a.keys(&keymap) == b.keys(&keymap);
+                assert!(!same_keys || a.effect() != b.effect(), "{a:?} and {b:?}");
+            }
+        }
+        assert_eq!(Notification::RouteReady.keys(&keymap), KeySet::of(&[1]));
+        assert_eq!(
+            Notification::RouteReady.keys(&Keymap::mirrored()),
+            KeySet::of(&[4])
+        );
+        assert_eq!(Notification::LeaveNow.keys(&keymap), KeySet::ALL);
+    }
+
+    #[test]
+    fn a_press_on_the_key_acknowledges() {
+        let mut notifier = Notifier::new(Keymap::new());
+        let mut stack = EffectStack::new();
+        notifier.raise(Notification::RouteReady);
+        notifier.raise(Notification::MicRecording);
+        notifier
+            .apply(&mut stack, false, Instant::from_millis(0))
+            .unwrap();
+        assert_eq!(stack.layers().len(), 2);
+        // Mic on key 4 draws over nothing, route on key 2 breathes green
+        assert_eq!(stack.render(Instant::from_millis(1500))[3], colors::RED);
+        assert_eq!(stack.render(Instant::from_millis(1500))[1], colors::GREEN);
+
+        assert!(!notifier.acknowledge(0, false));
+        assert!(notifier.acknowledge(1, false));
+        // The mic light can't be dismissed
+        assert!(!notifier.acknowledge(3, false));
+        notifier
+            .apply(&mut stack, false, Instant::from_millis(0))
+            .unwrap();
+        assert_eq!(stack.layers().len(), 1);
+
+        // Raising again keeps it acknowledged, clearing first shows it again
+        notifier.raise(Notification::RouteReady);
+        assert_eq!(notifier.shown(false).count(), 1);
+        notifier.clear(Notification::RouteReady);
+        notifier.raise(Notification::RouteReady);
+        assert_eq!(notifier.shown(false).count(), 2);
+    }
+
+    #[test]
+    fn quiet_hours_hold_all_but_the_mic() {
+        let quiet = quiet_night();
+        assert!(quiet.contains(TimeOfDay::new(23, 0).unwrap()));
+        assert!(quiet.contains(TimeOfDay::new(6, 59).unwrap()));
+        assert!(!quiet.contains(TimeOfDay::new(7, 0).unwrap()));
+        assert!(!QuietHours::OFF.contains(TimeOfDay::new(12, 0).unwrap()));
+
+        let mut notifier = Notifier::new(Keymap::new());
+        let mut stack = EffectStack::new();
+        notifier.raise(Notification::LeaveNow);
+        notifier.raise(Notification::MicRecording);
+        notifier
+            .apply(&mut stack, true, Instant::from_millis(0))
+            .unwrap();
+        let frame = stack.render(Instant::from_millis(0));
+        assert_eq!(frame[3], colors::RED);
+        assert_eq!(frame[0], colors::OFF);
+        // A press in the dark acknowledges nothing
+        assert!(!notifier.acknowledge(0, true));
+
+        // Morning comes and the held one shows
+        notifier
+            .apply(&mut stack, false, Instant::from_millis(0))
+            .unwrap();
+        assert_eq!(stack.render(Instant::from_millis(0))[0], colors::YELLOW);
+    }
+
+    #[test]
+    fn quiet_hours_round_trip() {
+        let quiet = quiet_night();
+        assert_eq!(QuietHours::from_bytes(&quiet.to_bytes()), Ok(quiet));
+        assert_eq!(
+            QuietHours::from_bytes(&[0xff, 0xff, 0, 0]),
+            Err("Quiet hours out of range")
+        );
+        assert!(QuietHours::parse("off").unwrap().is_off());
+        assert_eq!(std::format!("{quiet}"), "22:30-07:00");
+        assert_eq!(QuietHours::parse("22:30"), None);
+    }
+}
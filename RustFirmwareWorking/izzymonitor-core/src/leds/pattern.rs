@@ -0,0 +1,600 @@
+//! LED patterns
+//! Compact, versioned bytecode for key animations sent by the backend
+//!
+//! A pattern is a 6 byte header, `"IZP"`, the format version and the duration
+//! cap in ms (u16 LE), followed by ops:
+//!
+//! - `01 keys playback repeats` starts a track on a key bitmask. Playback is
+//!   0 once, 1 loop, 2 ping-pong, and a loop runs `repeats` passes, 0 for
+//!   until the cap.
+//! - `02 at_lo at_hi r g b easing` adds a keyframe at `at` ms to the track.
+//!
+//! Later tracks draw over earlier ones, and a track holds its final color
+//! once its passes are done. Decoding caps the brightness of every keyframe
+//! and rejects patterns that would flash more than [`MAX_FLASHES_PER_SECOND`]
+//! times a second, so nothing uploaded can strobe the keys.
+
+use embassy_time::{Duration, Instant};
+use heapless::{String, Vec};
+
+use super::RgbColor;
+use crate::buttons::KeySet;
+use crate::config::fletcher16;
+use crate::timeline::{Easing, Playback, Timeline};
+use crate::KEY_COUNT;
+
+/// Marks the start of a pattern
+pub const PATTERN_MAGIC: [u8; 3] = *b"IZP";
+/// Bytecode version this firmware plays
+pub const PATTERN_VERSION: u8 = 1;
+/// Largest pattern accepted
+pub const MAX_PATTERN_SIZE: usize = 256;
+/// Most tracks in one pattern
+pub const MAX_TRACKS: usize = KEY_COUNT;
+/// Longest duration cap a pattern may ask for
+pub const MAX_PATTERN_DURATION: Duration = Duration::from_secs(60);
+/// Brightest channel value a pattern may show, brighter keyframes are scaled down
+pub const MAX_PATTERN_LEVEL: u8 = 160;
+/// Most bright-dark-bright flashes a key may show in any one second
+pub const MAX_FLASHES_PER_SECOND: usize = 3;
+/// Luminance change that counts as half a flash
+pub const FLASH_SWING: u8 = 48;
+/// Shortest time between keyframes that aren't at the same time
+pub const MIN_KEYFRAME_GAP: Duration = Duration::from_millis(20);
+
+const HEADER_SIZE: usize = 6;
+const OP_TRACK: u8 = 1;
+const OP_KEYFRAME: u8 = 2;
+// Half the keyframe gap, so every held level is seen by the flash check
+const SAMPLE_PERIOD: Duration = Duration::from_millis(10);
+
+/// Animation of a set of keys within a pattern
+#[derive(Debug, Clone)]
+pub struct Track {
+    pub keys: KeySet,
+    timeline: Timeline<RgbColor>,
+    playback: Playback,
+    repeats: u8,
+    first: RgbColor,
+    last: RgbColor,
+    keyframes: u8,
+}
+
+impl Track {
+    /// Color after `elapsed`, holding the final color after the last pass
+    fn color(&self, elapsed: Duration) -> Option<RgbColor> {
+        let span = self.timeline.duration();
+        let pass = match self.playback {
+            Playback::PingPong => span * 2,
+            _ => span,
+        };
+        let looping = self.playback != Playback::Once;
+        if looping && self.repeats > 0 && elapsed >= pass * u32::from(self.repeats) {
+            // Ping-pong passes end back at the first keyframe
+            return Some(match self.playback {
+                Playback::PingPong => self.first,
+                _ => self.last,
+            });
+        }
+        self.timeline.value_at(self.timeline.start() + elapsed)
+    }
+}
+
+/// A validated pattern, ready to play
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    tracks: Vec<Track, MAX_TRACKS>,
+    duration: Duration,
+}
+
+impl Pattern {
+    /// Parse and check bytecode
+    pub fn decode(code: &[u8]) -> Result<Self, &'static str> {
+        if code.len() > MAX_PATTERN_SIZE {
+            return Err("Pattern too large");
+        }
+        let Some((header, mut ops)) = code.split_first_chunk::<HEADER_SIZE>() else {
+            return Err("Not an LED pattern");
+        };
+        if header[..3] != PATTERN_MAGIC {
+            return Err("Not an LED pattern");
+        }
+        if header[3] != PATTERN_VERSION {
+            return Err("Unsupported pattern version");
+        }
+        let duration = Duration::from_millis(u16::from_le_bytes([header[4], header[5]]).into());
+        if duration == Duration::from_ticks(0) || duration > MAX_PATTERN_DURATION {
+            return Err("Pattern duration out of range");
+        }
+
+        let mut tracks: Vec<Track, MAX_TRACKS> = Vec::new();
+        loop {
+            match *ops {
+                [] => break,
+                [OP_TRACK, keys, playback, repeats, ref tail @ ..] => {
+                    let keys = KeySet::from_bits(keys)
+                        .filter(|keys| !keys.is_empty())
+                        .ok_or("Pattern track keys out of range")?;
+                    let playback = playback_from_u8(playback).ok_or("Unknown playback")?;
+                    let track = Track {
+                        keys,
+                        timeline: Timeline::new(Instant::from_ticks(0), playback),
+                        playback,
+                        repeats,
+                        first: RgbColor::default(),
+                        last: RgbColor::default(),
+                        keyframes: 0,
+                    };
+                    tracks.push(track).map_err(|_| "Too many pattern tracks")?;
+                    ops = tail;
+                }
+                [OP_KEYFRAME, lo, hi, r, g, b, easing, ref tail @ ..] => {
+                    let track = tracks.last_mut().ok_or("Keyframe before any track")?;
+                    let at = Duration::from_millis(u16::from_le_bytes([lo, hi]).into());
+                    let easing = easing_from_u8(easing).ok_or("Unknown easing")?;
+                    let span = track.timeline.duration();
+                    if track.keyframes > 0 && at > span && at - span < MIN_KEYFRAME_GAP {
+                        return Err("Pattern keyframes too close");
+                    }
+                    let color = limit_brightness(RgbColor::new(r, g, b));
+                    track.timeline.push(at, color, easing)?;
+                    if track.keyframes == 0 {
+                        track.first = color;
+                    }
+                    track.last = color;
+                    track.keyframes += 1;
+                    ops = tail;
+                }
+                [OP_TRACK | OP_KEYFRAME, ..] => return Err("Truncated pattern op"),
+                _ => return Err("Unknown pattern op"),
+            }
+        }
+
+        if tracks.is_empty() {
+            return Err("Empty pattern");
+        }
+        if tracks.iter().any(|track| track.keyframes == 0) {
+            return Err("Pattern track without keyframes");
+        }
+        let pattern = Self { tracks, duration };
+        pattern.check_flashes()?;
+        Ok(pattern)
+    }
+
+    /// How long the pattern plays
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Color of a key after `elapsed`, `None` where no track covers it
+    pub fn color(&self, key: usize, elapsed: Duration) -> Option<RgbColor> {
+        self.tracks
+            .iter()
+            .rev()
+            .filter(|track| track.keys.contains(key))
+            .find_map(|track| track.color(elapsed))
+    }
+
+    /// Reject patterns that flash faster than the limit on any key
+    fn check_flashes(&self) -> Result<(), &'static str> {
+        for key in 0..KEY_COUNT {
+            let mut swings = Swings::new();
+            let mut at = Duration::from_ticks(0);
+            while at <= self.duration {
+                let level = self.color(key, at).map_or(0, luma);
+                if !swings.sample(level, at) {
+                    return Err("Pattern flashes too fast");
+                }
+                at += SAMPLE_PERIOD;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Swings of a key's luminance, two of them make a flash
+struct Swings {
+    low: u8,
+    high: u8,
+    rising: Option<bool>,
+    // Times of the latest swings, oldest overwritten first
+    times: [Duration; 2 * MAX_FLASHES_PER_SECOND],
+    count: usize,
+}
+
+impl Swings {
+    fn new() -> Self {
+        Self {
+            low: u8::MAX,
+            high: 0,
+            rising: None,
+            times: [Duration::from_ticks(0); 2 * MAX_FLASHES_PER_SECOND],
+            count: 0,
+        }
+    }
+
+    /// Track a level sampled at `at`, false once the flash limit is broken
+    fn sample(&mut self, level: u8, at: Duration) -> bool {
+        self.low = self.low.min(level);
+        self.high = self.high.max(level);
+        let swing = if self.rising != Some(true) && level >= self.low.saturating_add(FLASH_SWING) {
+            Some(true)
+        } else if self.rising != Some(false) && level.saturating_add(FLASH_SWING) <= self.high {
+            Some(false)
+        } else {
+            None
+        };
+        let Some(rising) = swing else {
+            return true;
+        };
+
+        // Measure the next swing from here
+        self.rising = Some(rising);
+        self.low = level;
+        self.high = level;
+
+        let slot = self.count % self.times.len();
+        let full = self.count >= self.times.len();
+        let ok = !full || at - self.times[slot] >= Duration::from_secs(1);
+        self.times[slot] = at;
+        self.count += 1;
+        ok
+    }
+}
+
+/// Perceived brightness, Rec. 601 weights
+fn luma(color: RgbColor) -> u8 {
+    ((77 * u16::from(color.r) + 150 * u16::from(color.g) + 29 * u16::from(color.b)) >> 8) as u8
+}
+
+/// Scale a color down to [`MAX_PATTERN_LEVEL`], keeping its hue
+fn limit_brightness(color: RgbColor) -> RgbColor {
+    let max = color.r.max(color.g).max(color.b);
+    if max <= MAX_PATTERN_LEVEL {
+        return color;
+    }
+    color.scale((u16::from(MAX_PATTERN_LEVEL) * 255 / u16::from(max)) as u8)
+}
+
+fn playback_to_u8(playback: Playback) -> u8 {
+    match playback {
+        Playback::Once => 0,
+        Playback::Loop => 1,
+        Playback::PingPong => 2,
+    }
+}
+
+fn playback_from_u8(value: u8) -> Option<Playback> {
+    [Playback::Once, Playback::Loop, Playback::PingPong]
+        .get(usize::from(value))
+        .copied()
+}
+
+fn easing_to_u8(easing: Easing) -> u8 {
+    match easing {
+        Easing::Linear => 0,
+        Easing::EaseInOut => 1,
+        Easing::Cubic => 2,
+        Easing::Bounce => 3,
+    }
+}
+
+fn easing_from_u8(value: u8) -> Option<Easing> {
+    [
+        Easing::Linear,
+        Easing::EaseInOut,
+        Easing::Cubic,
+        Easing::Bounce,
+    ]
+    .get(usize::from(value))
+    .copied()
+}
+
+/// Bytecode of a pattern
+pub type PatternCode = Vec<u8, MAX_PATTERN_SIZE>;
+
+/// Builds pattern bytecode on the host
+///
+/// The encoder only lays out bytes, run the result through
+/// [`Pattern::decode`] to see whether a device would accept it.
+#[derive(Debug, Clone)]
+pub struct PatternEncoder {
+    code: PatternCode,
+}
+
+impl PatternEncoder {
+    /// Start a pattern that plays for `duration`
+    pub fn new(duration: Duration) -> Self {
+        let ms = duration.as_millis().min(u64::from(u16::MAX)) as u16;
+        let mut code = PatternCode::new();
+        // The header always fits
+        let _ = code.extend_from_slice(&PATTERN_MAGIC);
+        let _ = code.push(PATTERN_VERSION);
+        let _ = code.extend_from_slice(&ms.to_le_bytes());
+        Self { code }
+    }
+
+    /// Start a track on some keys, `repeats` passes of a loop or 0 for until the cap
+    pub fn track(
+        &mut self,
+        keys: KeySet,
+        playback: Playback,
+        repeats: u8,
+    ) -> Result<(), &'static str> {
+        self.op(&[OP_TRACK, keys.bits(), playback_to_u8(playback), repeats])
+    }
+
+    /// Add a keyframe to the current track
+    pub fn keyframe(
+        &mut self,
+        at: Duration,
+        color: RgbColor,
+        easing: Easing,
+    ) -> Result<(), &'static str> {
+        let at = u16::try_from(at.as_millis()).map_err(|_| "Keyframe too late")?;
+        let [lo, hi] = at.to_le_bytes();
+        let easing = easing_to_u8(easing);
+        self.op(&[OP_KEYFRAME, lo, hi, color.r, color.g, color.b, easing])
+    }
+
+    /// The finished bytecode
+    pub fn finish(self) -> PatternCode {
+        self.code
+    }
+
+    fn op(&mut self, bytes: &[u8]) -> Result<(), &'static str> {
+        self.code
+            .extend_from_slice(bytes)
+            .map_err(|_| "Pattern too large")
+    }
+}
+
+/// Longest pattern name
+pub const MAX_NAME_LEN: usize = 15;
+/// Flash space of one stored pattern
+pub const PATTERN_SLOT_SIZE: usize = 512;
+
+/// Name a pattern is stored and played under
+pub type PatternName = String<MAX_NAME_LEN>;
+
+fn pattern_name(name: &str) -> Option<PatternName> {
+    let mut string = PatternName::new();
+    string.push_str(name).ok()?;
+    Some(string)
+}
+
+/// A checked pattern under its name, as kept in flash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredPattern {
+    pub name: PatternName,
+    pub code: PatternCode,
+}
+
+impl StoredPattern {
+    /// Check the name and the bytecode before storing
+    pub fn new(name: &str, code: &[u8]) -> Result<Self, &'static str> {
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        if name.is_empty() || !name.chars().all(valid) {
+            return Err("Bad pattern name");
+        }
+        Pattern::decode(code)?;
+        Ok(Self {
+            name: pattern_name(name).ok_or("Pattern name too long")?,
+            code: code.try_into().map_err(|_| "Pattern too large")?,
+        })
+    }
+
+    /// Decode the bytecode for playing
+    pub fn pattern(&self) -> Result<Pattern, &'static str> {
+        Pattern::decode(&self.code)
+    }
+
+    /// Serialize into a flash slot: name and code with their lengths, and a
+    /// Fletcher-16 checksum
+    pub fn to_bytes(&self) -> [u8; PATTERN_SLOT_SIZE] {
+        // Unused space stays erased
+        let mut slot = [0xff; PATTERN_SLOT_SIZE];
+        let name = self.name.as_bytes();
+        slot[0] = name.len() as u8;
+        slot[1..1 + name.len()].copy_from_slice(name);
+        let at = 1 + name.len();
+        slot[at..at + 2].copy_from_slice(&(self.code.len() as u16).to_le_bytes());
+        let end = at + 2 + self.code.len();
+        slot[at + 2..end].copy_from_slice(&self.code);
+        let checksum = fletcher16(&slot[..end]);
+        slot[end..end + 2].copy_from_slice(&checksum.to_le_bytes());
+        slot
+    }
+
+    /// Deserialize a flash slot, the bytecode is checked when it is played
+    pub fn from_bytes(slot: &[u8]) -> Result<Self, &'static str> {
+        let [name_len, rest @ ..] = slot else {
+            return Err("Empty pattern slot");
+        };
+        let name_len = usize::from(*name_len);
+        if name_len == 0 || name_len > MAX_NAME_LEN {
+            return Err("Empty pattern slot");
+        }
+        let (name, rest) = rest
+            .split_at_checked(name_len)
+            .ok_or("Truncated pattern slot")?;
+        let [lo, hi, rest @ ..] = rest else {
+            return Err("Truncated pattern slot");
+        };
+        let code_len = usize::from(u16::from_le_bytes([*lo, *hi]));
+        let end = 1 + name_len + 2 + code_len;
+        if code_len > MAX_PATTERN_SIZE || rest.len() < code_len + 2 {
+            return Err("Truncated pattern slot");
+        }
+        let stored = u16::from_le_bytes([slot[end], slot[end + 1]]);
+        if fletcher16(&slot[..end]) != stored {
+            return Err("Pattern slot checksum mismatch");
+        }
+
+        let name = core::str::from_utf8(name).map_err(|_| "Bad pattern name")?;
+        Ok(Self {
+            name: pattern_name(name).ok_or("Bad pattern name")?,
+            code: rest[..code_len]
+                .try_into()
+                .map_err(|_| "Pattern too large")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::leds::colors;
+
+    fn ms(t: u64) -> Duration {
+        Duration::from_millis(t)
+    }
+
+    /// Blink every key white, on and off for `half` ms each
+    fn blink(half: u64) -> PatternCode {
+        let mut encoder = PatternEncoder::new(ms(3000));
+        encoder.track(KeySet::ALL, Playback::Loop, 0).unwrap();
+        encoder
+            .keyframe(ms(0), colors::WHITE, Easing::Linear)
+            .unwrap();
+        encoder
+            .keyframe(ms(half), colors::WHITE, Easing::Linear)
+            .unwrap();
+        encoder
+            .keyframe(ms(half), colors::OFF, Easing::Linear)
+            .unwrap();
+        encoder
+            .keyframe(ms(2 * half), colors::OFF, Easing::Linear)
+            .unwrap();
+        encoder.finish()
+    }
+
+    #[test]
+    fn encoded_tracks_play_back() {
+        let blue = RgbColor::new(0, 0, 120);
+        let mut encoder = PatternEncoder::new(ms(2000));
+        encoder.track(KeySet::ALL, Playback::Loop, 0).unwrap();
+        encoder
+            .keyframe(ms(0), colors::OFF, Easing::Linear)
+            .unwrap();
+        encoder.keyframe(ms(400), blue, Easing::Linear).unwrap();
+        encoder
+            .keyframe(ms(800), colors::OFF, Easing::Linear)
+            .unwrap();
+        // Key 2 pings twice on top, then holds where it started
+        encoder
+            .track(KeySet::of(&[2]), Playback::PingPong, 2)
+            .unwrap();
+        encoder
+            .keyframe(ms(0), colors::OFF, Easing::Linear)
+            .unwrap();
+        encoder
+            .keyframe(ms(100), colors::GREEN, Easing::Linear)
+            .unwrap();
+        let code = encoder.finish();
+        assert_eq!(code[..4], *b"IZP\x01");
+
+        let pattern = Pattern::decode(&code).unwrap();
+        assert_eq!(pattern.duration(), ms(2000));
+        assert_eq!(pattern.color(0, ms(200)), Some(RgbColor::new(0, 0, 60)));
+        assert_eq!(pattern.color(0, ms(1200)), Some(blue));
+        assert_eq!(pattern.color(2, ms(200)), Some(colors::OFF));
+        assert_eq!(
+            pattern.color(2, ms(300)),
+            Some(limit_brightness(colors::GREEN))
+        );
+        assert_eq!(pattern.color(2, ms(900)), Some(colors::OFF));
+        assert_eq!(pattern.color(KEY_COUNT, ms(0)), None);
+    }
+
+    #[test]
+    fn keyframes_are_dimmed_to_the_limit() {
+        let mut encoder = PatternEncoder::new(ms(500));
+        encoder.track(KeySet::of(&[0]), Playback::Once, 0).unwrap();
+        encoder
+            .keyframe(ms(0), RgbColor::new(255, 128, 0), Easing::Linear)
+            .unwrap();
+        let pattern = Pattern::decode(&encoder.finish()).unwrap();
+        assert_eq!(pattern.color(0, ms(0)), Some(RgbColor::new(160, 80, 0)));
+        assert_eq!(pattern.color(1, ms(0)), None);
+    }
+
+    #[test]
+    fn fast_flashing_is_rejected() {
+        // 2 Hz is fine, 5 Hz is a strobe
+        assert!(Pattern::decode(&blink(250)).is_ok());
+        assert_eq!(
+            Pattern::decode(&blink(100)).map(|_| ()),
+            Err("Pattern flashes too fast")
+        );
+
+        // Keyframes closer than the sampling could see are refused outright
+        let mut encoder = PatternEncoder::new(ms(1000));
+        encoder.track(KeySet::ALL, Playback::Loop, 0).unwrap();
+        encoder
+            .keyframe(ms(0), colors::WHITE, Easing::Linear)
+            .unwrap();
+        encoder
+            .keyframe(ms(5), colors::OFF, Easing::Linear)
+            .unwrap();
+        assert_eq!(
+            Pattern::decode(&encoder.finish()).map(|_| ()),
+            Err("Pattern keyframes too close")
+        );
+    }
+
+    #[test]
+    fn malformed_code_is_rejected() {
+        let check = |code: &[u8]| Pattern::decode(code).map(|_| ()).unwrap_err();
+        assert_eq!(check(b"IZ"), "Not an LED pattern");
+        assert_eq!(check(b"IZP\x02\xe8\x03"), "Unsupported pattern version");
+        assert_eq!(check(b"IZP\x01\x00\x00"), "Pattern duration out of range");
+        assert_eq!(check(b"IZP\x01\xe8\x03"), "Empty pattern");
+        assert_eq!(
+            check(b"IZP\x01\xe8\x03\x01\x40\x00\x00"),
+            "Pattern track keys out of range"
+        );
+        assert_eq!(
+            check(b"IZP\x01\xe8\x03\x01\x01\x07\x00"),
+            "Unknown playback"
+        );
+        assert_eq!(
+            check(b"IZP\x01\xe8\x03\x01\x01\x00\x00"),
+            "Pattern track without keyframes"
+        );
+        assert_eq!(
+            check(b"IZP\x01\xe8\x03\x02\x00\x00\x01\x01\x01\x00"),
+            "Keyframe before any track"
+        );
+        assert_eq!(check(b"IZP\x01\xe8\x03\x01\x01"), "Truncated pattern op");
+        assert_eq!(check(b"IZP\x01\xe8\x03\x09"), "Unknown pattern op");
+        assert_eq!(check(&[0; MAX_PATTERN_SIZE + 1]), "Pattern too large");
+    }
+
+    #[test]
+    fn stored_patterns_round_trip() {
+        let stored = StoredPattern::new("thinking", &blink(400)).unwrap();
+        let slot = stored.to_bytes();
+        assert_eq!(StoredPattern::from_bytes(&slot), Ok(stored.clone()));
+        assert!(stored.pattern().is_ok());
+
+        let mut corrupt = slot;
+        corrupt[4] ^= 1;
+        assert_eq!(
+            StoredPattern::from_bytes(&corrupt),
+            Err("Pattern slot checksum mismatch")
+        );
+        assert_eq!(
+            StoredPattern::from_bytes(&[0xff; PATTERN_SLOT_SIZE]),
+            Err("Empty pattern slot")
+        );
+        assert_eq!(
+            StoredPattern::new("no spaces", &blink(400)),
+            Err("Bad pattern name")
+        );
+        assert_eq!(
+            StoredPattern::new("strobe", &blink(100)),
+            Err("Pattern flashes too fast")
+        );
+    }
+}
@@ -0,0 +1,217 @@
+//! LED power budget
+//! Estimated supply current of a frame, and dimming to keep it under a limit
+//!
+//! Each color channel of an addressable LED draws close to its full-scale
+//! current times its PWM duty, on top of a small idle current for the driver
+//! IC. The estimate is the sum over the chain, so a longer strip or a brighter
+//! effect shows up in the number before it browns out the USB supply.
+
+use core::fmt;
+
+use super::RgbColor;
+
+/// Current profile of one LED type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LedModel {
+    pub name: &'static str,
+    /// Driver IC draw with every channel off, in µA
+    pub idle_ua: u32,
+    /// Red, green and blue draw at full duty, in µA
+    pub channel_ua: [u32; 3],
+}
+
+/// 5 mm and 5050 WS2812B, measured on a bench supply
+pub const WS2812B: LedModel = LedModel {
+    name: "WS2812B",
+    idle_ua: 1000,
+    channel_ua: [12_500, 12_500, 12_500],
+};
+
+/// SK6805 mini LEDs under the keys, 5 mA per channel from the datasheet
+pub const SK6805: LedModel = LedModel {
+    name: "SK6805",
+    idle_ua: 500,
+    channel_ua: [5_000, 5_000, 5_000],
+};
+
+/// 5050 APA102 and SK9822 strips, 20 mA per channel from the datasheet
+pub const APA102: LedModel = LedModel {
+    name: "APA102",
+    idle_ua: 1000,
+    channel_ua: [20_000, 20_000, 20_000],
+};
+
+/// Budget used until one is configured, what is left of a 500 mA USB port
+/// after the ESP32-S3 with WiFi and the display
+pub const DEFAULT_LIMIT_MA: u16 = 150;
+/// Smallest budget accepted, enough to show the key hints
+pub const MIN_LIMIT_MA: u16 = 20;
+/// Largest budget accepted, the rest of the board needs the other half
+pub const MAX_LIMIT_MA: u16 = 250;
+
+impl LedModel {
+    /// Estimated draw of a frame, in µA
+    pub fn estimate_ua(&self, frame: &[RgbColor]) -> u32 {
+        let idle = self.idle_ua * frame.len() as u32;
+        idle + self.color_ua(frame)
+    }
+
+    /// Estimated draw of a frame, in mA rounded up
+    pub fn estimate_ma(&self, frame: &[RgbColor]) -> u32 {
+        self.estimate_ua(frame).div_ceil(1000)
+    }
+
+    /// Draw of the lit channels only
+    fn color_ua(&self, frame: &[RgbColor]) -> u32 {
+        let [r, g, b] = self.channel_ua;
+        frame
+            .iter()
+            .map(|c| (u32::from(c.r) * r + u32::from(c.g) * g + u32::from(c.b) * b) / 255)
+            .sum()
+    }
+}
+
+/// Estimated draw of the last frame shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PowerReport {
+    /// What the frame would have drawn as rendered
+    pub requested_ma: u32,
+    /// What it draws after dimming
+    pub drawn_ma: u32,
+    /// The budget it was held to
+    pub limit_ma: u16,
+}
+
+impl PowerReport {
+    /// Whether the frame had to be dimmed
+    pub fn is_limited(&self) -> bool {
+        self.drawn_ma < self.requested_ma
+    }
+}
+
+impl fmt::Display for PowerReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} mA of {} mA budget (requested {} mA{})",
+            self.drawn_ma,
+            self.limit_ma,
+            self.requested_ma,
+            if self.is_limited() { ", dimmed" } else { "" }
+        )
+    }
+}
+
+/// Supply current limit for an LED chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PowerBudget {
+    pub model: LedModel,
+    limit_ma: u16,
+}
+
+impl PowerBudget {
+    /// Budget of `limit_ma` for LEDs of type `model`, clamped to the accepted range
+    pub fn new(model: LedModel, limit_ma: u16) -> Self {
+        Self {
+            model,
+            limit_ma: limit_ma.clamp(MIN_LIMIT_MA, MAX_LIMIT_MA),
+        }
+    }
+
+    /// Current limit in mA
+    pub fn limit_ma(&self) -> u16 {
+        self.limit_ma
+    }
+
+    /// Change the limit, clamped to the accepted range
+    pub fn set_limit_ma(&mut self, limit_ma: u16) {
+        self.limit_ma = limit_ma.clamp(MIN_LIMIT_MA, MAX_LIMIT_MA);
+    }
+
+    /// Dim the whole frame evenly if it would go over the limit
+    ///
+    /// Hues are kept, only the overall level drops. The idle draw can't be
+    /// dimmed, so a chain whose idle draw alone is over the limit goes dark.
+    pub fn apply(&self, frame: &mut [RgbColor]) -> PowerReport {
+        let requested = self.model.estimate_ua(frame);
+        let limit = u32::from(self.limit_ma) * 1000;
+
+        if requested > limit {
+            let idle = requested - self.model.color_ua(frame);
+            let available = limit.saturating_sub(idle);
+            // Rounding down both here and in `scale` keeps the result under
+            let level = (u64::from(available) * 255 / u64::from(requested - idle)) as u8;
+            for color in frame.iter_mut() {
+                *color = color.scale(level);
+            }
+        }
+
+        PowerReport {
+            requested_ma: requested.div_ceil(1000),
+            drawn_ma: self.model.estimate_ma(frame),
+            limit_ma: self.limit_ma,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::leds::colors;
+    use crate::KEY_COUNT;
+
+    #[test]
+    fn estimate_adds_channels_and_idle() {
+        let frame = [colors::WHITE; KEY_COUNT];
+        assert_eq!(WS2812B.estimate_ma(&frame), 231);
+        assert_eq!(SK6805.estimate_ua(&[colors::OFF; KEY_COUNT]), 3000);
+        assert_eq!(SK6805.estimate_ua(&[RgbColor::new(255, 0, 0)]), 5500);
+    }
+
+    #[test]
+    fn frames_under_budget_are_untouched() {
+        let budget = PowerBudget::new(SK6805, DEFAULT_LIMIT_MA);
+        let mut frame = [colors::WHITE; KEY_COUNT];
+        let report = budget.apply(&mut frame);
+        assert_eq!(frame, [colors::WHITE; KEY_COUNT]);
+        assert_eq!(report.drawn_ma, 93);
+        assert!(!report.is_limited());
+    }
+
+    #[test]
+    fn frames_over_budget_are_dimmed_evenly() {
+        let budget = PowerBudget::new(WS2812B, 100);
+        let mut frame = [colors::WHITE; KEY_COUNT];
+        frame[0] = colors::RED;
+        let report = budget.apply(&mut frame);
+
+        assert!(report.is_limited());
+        assert!(report.drawn_ma <= 100);
+        assert!(report.drawn_ma > 90);
+        // Same level on every channel, hues survive
+        assert_eq!(frame[0].g, 0);
+        assert_eq!(frame[0].r, frame[1].r);
+        assert_eq!(frame[1].r, frame[1].b);
+    }
+
+    #[test]
+    fn limit_is_clamped() {
+        let mut budget = PowerBudget::new(SK6805, 0);
+        assert_eq!(budget.limit_ma(), MIN_LIMIT_MA);
+        budget.set_limit_ma(5000);
+        assert_eq!(budget.limit_ma(), MAX_LIMIT_MA);
+    }
+
+    #[test]
+    fn report_formats_for_the_console() {
+        let report = PowerReport {
+            requested_ma: 231,
+            drawn_ma: 99,
+            limit_ma: 100,
+        };
+        assert_eq!(
+            std::format!("{report}"),
+            "99 mA of 100 mA budget (requested 231 mA, dimmed)"
+        );
+    }
+}
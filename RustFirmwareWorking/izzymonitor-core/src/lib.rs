@@ -0,0 +1,19 @@
+//! Platform-independent core of the IzzyMonitor panel
+//! Holds the menu state machine, button processing, LED frames and screen
+//! composition so they can be unit-tested on the host with `cargo test`.
+//! The firmware binaries only provide the board adapters for these traits.
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod ambient;
+pub mod buttons;
+pub mod clock;
+pub mod config;
+pub mod display;
+pub mod keymap;
+pub mod leds;
+pub mod menu;
+pub mod timeline;
+
+/// Number of Kailh keys (and key LEDs) on the panel
+pub const KEY_COUNT: usize = 6;
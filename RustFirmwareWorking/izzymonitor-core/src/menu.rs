@@ -0,0 +1,908 @@
+//! Menu system
+//! Screen state machine driven by key presses
+
+use embassy_time::Duration;
+use heapless::String;
+
+use crate::ambient::BrightnessMode;
+use crate::buttons::{ChordBinding, ChordId, KeySet, KeyStats, RepeatCurve, DOWN_KEY, UP_KEY};
+use crate::display::{screen_layout, ButtonLayout, TextViewer, Theme, ThemeId};
+use crate::keymap::{Action, Keymap, KeymapEditor};
+use crate::leds::calibration::CalibrationEditor;
+use crate::leds::{Calibration, KeyHint, LedFrame, RgbColor};
+use crate::KEY_COUNT;
+
+/// Screens of the menu system
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuScreen {
+    Startup,
+    Main,
+    Trip,
+    Settings,
+    Keymap,
+    Calibration,
+    Diagnostics,
+}
+
+// Define button labels for different screens
+pub const STARTUP_BUTTONS: [&str; KEY_COUNT] = [""; KEY_COUNT];
+pub const MAIN_BUTTONS: [&str; KEY_COUNT] = ["Menu", "Trip", "Set", "Mic", "Up", "Down"];
+pub const TRIP_BUTTONS: [&str; KEY_COUNT] = ["Back", "New", "View", "Map", "Up", "Down"];
+pub const SETTINGS_BUTTONS: [&str; KEY_COUNT] = ["Back", "Look", "LED", "Keys", "Up", "Down"];
+pub const KEYMAP_BUTTONS: [&str; KEY_COUNT] = ["Back", "Scrn", "Act", "Flip", "Up", "Down"];
+pub const CALIBRATION_BUTTONS: [&str; KEY_COUNT] = ["Back", "Test", "LED", "Item", "Up", "Down"];
+pub const DIAGNOSTICS_BUTTONS: [&str; KEY_COUNT] = ["Back", "", "", "", "", ""];
+
+/// Service chord that opens the key diagnostics from any screen
+pub const DIAGNOSTICS_CHORD: ChordBinding = ChordBinding {
+    id: ChordId::App(0),
+    keys: KeySet::of(&[2, 3]),
+    hold: Duration::from_secs(1),
+};
+
+/// Longest route description the Trip screen keeps, in bytes
+pub const MAX_TRIP_TEXT: usize = 512;
+
+/// Steady scrolling for short menus
+pub const MENU_REPEAT: RepeatCurve = RepeatCurve::Fixed(Duration::from_millis(150));
+/// Pages through long text, the Trip screen's route, slow enough to read
+/// each page on the way
+pub const PAGE_REPEAT: RepeatCurve = RepeatCurve::Linear {
+    start: Duration::from_millis(700),
+    min: Duration::from_millis(400),
+    step: Duration::from_millis(100),
+};
+/// Speeds up gently so a value can still be stopped on
+pub const VALUE_REPEAT: RepeatCurve = RepeatCurve::Linear {
+    start: Duration::from_millis(250),
+    min: Duration::from_millis(50),
+    step: Duration::from_millis(25),
+};
+
+impl MenuScreen {
+    /// Default soft-key labels shown on this screen
+    pub fn labels(self) -> [&'static str; KEY_COUNT] {
+        match self {
+            MenuScreen::Startup => STARTUP_BUTTONS,
+            MenuScreen::Main => MAIN_BUTTONS,
+            MenuScreen::Trip => TRIP_BUTTONS,
+            MenuScreen::Settings => SETTINGS_BUTTONS,
+            MenuScreen::Keymap => KEYMAP_BUTTONS,
+            MenuScreen::Calibration => CALIBRATION_BUTTONS,
+            MenuScreen::Diagnostics => DIAGNOSTICS_BUTTONS,
+        }
+    }
+
+    /// Title bar text for this screen
+    pub fn title(self) -> &'static str {
+        match self {
+            MenuScreen::Startup | MenuScreen::Main => "VeraMonitor",
+            MenuScreen::Trip => "Trip Planner",
+            MenuScreen::Settings => "Settings",
+            MenuScreen::Keymap => "Key Map",
+            MenuScreen::Calibration => "LED Calibration",
+            MenuScreen::Diagnostics => "Key Diagnostics",
+        }
+    }
+
+    /// LED hint of a labelled key that is not Back
+    pub fn hint(self, key: usize, action: Action) -> KeyHint {
+        match (self, action) {
+            (MenuScreen::Main, Action::Trip) | (MenuScreen::Trip, Action::New) => KeyHint::Primary,
+            // The editor's keys are fixed: Act edits, Flip rewrites every screen
+            (MenuScreen::Keymap, _) if key == 2 => KeyHint::Primary,
+            (MenuScreen::Keymap, _) if key == 3 => KeyHint::Destructive,
+            _ => KeyHint::Enabled,
+        }
+    }
+
+    /// Auto-repeat curve for the Up/Down keys on this screen
+    pub fn repeat_curve(self) -> RepeatCurve {
+        match self {
+            MenuScreen::Startup
+            | MenuScreen::Main
+            | MenuScreen::Keymap
+            | MenuScreen::Diagnostics => MENU_REPEAT,
+            MenuScreen::Trip => PAGE_REPEAT,
+            MenuScreen::Settings | MenuScreen::Calibration => VALUE_REPEAT,
+        }
+    }
+}
+
+/// What part of the screen has to be redrawn after an input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Redraw {
+    None,
+    Buttons,
+    /// The same screen with new contents, redrawn in place
+    Content,
+    /// A different screen, with a transition
+    Screen,
+}
+
+impl Redraw {
+    /// Whether the whole screen is drawn again, in place or not
+    pub fn is_full(self) -> bool {
+        matches!(self, Redraw::Content | Redraw::Screen)
+    }
+}
+
+/// Menu state: current screen plus the soft-key layout
+#[derive(Debug, Clone)]
+pub struct Menu {
+    screen: MenuScreen,
+    layout: ButtonLayout,
+    keymap: Keymap,
+    // Last usable key map, restored if the editor leaves a broken one
+    saved_keymap: Keymap,
+    keymap_changed: bool,
+    editor: KeymapEditor,
+    calibration: Calibration,
+    saved_calibration: Calibration,
+    calibration_changed: bool,
+    calibration_editor: CalibrationEditor,
+    brightness: BrightnessMode,
+    saved_brightness: BrightnessMode,
+    brightness_changed: bool,
+    theme: ThemeId,
+    saved_theme: ThemeId,
+    theme_changed: bool,
+    // The agent personality's color, not stored
+    accent: Option<RgbColor>,
+    // Route description on the Trip screen and the first line shown
+    trip_text: String<MAX_TRIP_TEXT>,
+    trip_top: usize,
+    // Counters on the diagnostics screen, as last handed in
+    key_stats: [KeyStats; KEY_COUNT],
+    // Where Back leads from the diagnostics screen
+    return_to: MenuScreen,
+}
+
+impl Menu {
+    /// Start on the startup screen with the default key map
+    pub const fn new() -> Self {
+        Self::with_keymap(Keymap::new())
+    }
+
+    /// Start on the startup screen with a stored key map
+    pub const fn with_keymap(keymap: Keymap) -> Self {
+        Self {
+            screen: MenuScreen::Startup,
+            layout: ButtonLayout {
+                labels: STARTUP_BUTTONS,
+                active_index: 0,
+            },
+            keymap,
+            saved_keymap: keymap,
+            keymap_changed: false,
+            editor: KeymapEditor::new(),
+            calibration: Calibration::new(),
+            saved_calibration: Calibration::new(),
+            calibration_changed: false,
+            calibration_editor: CalibrationEditor::new(),
+            brightness: BrightnessMode::Auto,
+            saved_brightness: BrightnessMode::Auto,
+            brightness_changed: false,
+            theme: ThemeId::Dark,
+            saved_theme: ThemeId::Dark,
+            theme_changed: false,
+            accent: None,
+            trip_text: String::new(),
+            trip_top: 0,
+            key_stats: [KeyStats::new(); KEY_COUNT],
+            return_to: MenuScreen::Main,
+        }
+    }
+
+    /// Use a stored LED calibration
+    pub const fn with_calibration(mut self, calibration: Calibration) -> Self {
+        self.calibration = calibration;
+        self.saved_calibration = calibration;
+        self
+    }
+
+    /// Use a stored brightness setting
+    pub const fn with_brightness(mut self, brightness: BrightnessMode) -> Self {
+        self.brightness = brightness;
+        self.saved_brightness = brightness;
+        self
+    }
+
+    /// Use a stored theme
+    pub const fn with_theme(mut self, theme: ThemeId) -> Self {
+        self.theme = theme;
+        self.saved_theme = theme;
+        self
+    }
+
+    /// Get the current screen
+    pub fn screen(&self) -> MenuScreen {
+        self.screen
+    }
+
+    /// Get the current soft-key layout
+    pub fn layout(&self) -> &ButtonLayout {
+        &self.layout
+    }
+
+    /// Get the key map, including unsaved edits
+    pub fn keymap(&self) -> &Keymap {
+        &self.keymap
+    }
+
+    /// Get the key map editor cursor
+    pub fn editor(&self) -> &KeymapEditor {
+        &self.editor
+    }
+
+    /// Get the LED calibration, including unsaved edits
+    pub fn calibration(&self) -> &Calibration {
+        &self.calibration
+    }
+
+    /// Get the calibration screen cursor
+    pub fn calibration_editor(&self) -> &CalibrationEditor {
+        &self.calibration_editor
+    }
+
+    /// Get the brightness setting, including unsaved edits
+    pub fn brightness(&self) -> BrightnessMode {
+        self.brightness
+    }
+
+    /// Get the theme setting, including unsaved edits
+    pub fn theme_id(&self) -> ThemeId {
+        self.theme
+    }
+
+    /// The theme to draw in, in the personality's accent if there is one
+    pub fn theme(&self) -> Theme {
+        self.theme.with_accent(self.accent)
+    }
+
+    /// Take on an agent personality's accent color, `None` for the theme's own
+    pub fn set_accent(&mut self, accent: Option<RgbColor>) -> Redraw {
+        if accent == self.accent {
+            return Redraw::None;
+        }
+        self.accent = accent;
+        if self.screen == MenuScreen::Startup {
+            Redraw::None
+        } else {
+            Redraw::Content
+        }
+    }
+
+    /// Route description on the Trip screen, empty if there is none
+    pub fn trip_text(&self) -> &str {
+        &self.trip_text
+    }
+
+    /// Show a route description on the Trip screen from the top, cut to
+    /// [`MAX_TRIP_TEXT`] bytes
+    pub fn set_trip_text(&mut self, text: &str) -> Redraw {
+        let mut end = text.len().min(MAX_TRIP_TEXT);
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.trip_text.clear();
+        // Always fits, cut to the capacity above
+        let _ = self.trip_text.push_str(&text[..end]);
+        self.trip_top = 0;
+        if self.screen == MenuScreen::Trip {
+            Redraw::Content
+        } else {
+            Redraw::None
+        }
+    }
+
+    /// The Trip screen's route description, scrolled to where it was left
+    pub fn trip_viewer(&self) -> TextViewer<'_> {
+        let theme = self.theme();
+        let mut viewer =
+            TextViewer::new(screen_layout(&theme).content, &self.trip_text).with_theme(&theme);
+        viewer.scroll(self.trip_top as isize);
+        viewer
+    }
+
+    /// Key statistics shown on the diagnostics screen
+    pub fn key_stats(&self) -> &[KeyStats; KEY_COUNT] {
+        &self.key_stats
+    }
+
+    /// Show the latest key statistics on the diagnostics screen
+    pub fn set_key_stats(&mut self, stats: [KeyStats; KEY_COUNT]) -> Redraw {
+        if stats == self.key_stats {
+            return Redraw::None;
+        }
+        self.key_stats = stats;
+        if self.screen == MenuScreen::Diagnostics {
+            Redraw::Content
+        } else {
+            Redraw::None
+        }
+    }
+
+    /// Color every key LED shows instead of its hint, on the calibration screen
+    pub fn test_color(&self) -> Option<RgbColor> {
+        (self.screen == MenuScreen::Calibration).then(|| self.calibration_editor.test_color().1)
+    }
+
+    /// What a physical key does on the current screen
+    pub fn action(&self, key: usize) -> Action {
+        match self.screen {
+            // The editors always use the physical layout
+            MenuScreen::Keymap | MenuScreen::Calibration => match key {
+                0 => Action::Back,
+                UP_KEY => Action::Up,
+                DOWN_KEY => Action::Down,
+                _ => Action::None,
+            },
+            // Works with every key map, the keys may be what is broken
+            MenuScreen::Diagnostics => match key {
+                0 => Action::Back,
+                _ => Action::None,
+            },
+            screen => self.keymap.action(screen, key),
+        }
+    }
+
+    /// LED hint of every key on the current screen
+    pub fn hints(&self) -> [KeyHint; KEY_COUNT] {
+        core::array::from_fn(|key| {
+            let action = self.action(key);
+            if self.layout.labels[key].is_empty() {
+                KeyHint::Off
+            } else if action == Action::Back {
+                KeyHint::Back
+            } else {
+                self.screen.hint(key, action)
+            }
+        })
+    }
+
+    /// LED colors of the hints, in the theme
+    pub fn hint_colors(&self) -> LedFrame {
+        self.theme().hint_frame(&self.hints())
+    }
+
+    /// Key map to persist, if the editor saved one since the last call
+    pub fn take_keymap_change(&mut self) -> Option<Keymap> {
+        core::mem::take(&mut self.keymap_changed).then_some(self.saved_keymap)
+    }
+
+    /// LED calibration to persist, if the calibration screen saved one since the last call
+    pub fn take_calibration_change(&mut self) -> Option<Calibration> {
+        core::mem::take(&mut self.calibration_changed).then_some(self.saved_calibration)
+    }
+
+    /// Brightness setting to persist, if one was saved on leaving Settings since the last call
+    pub fn take_brightness_change(&mut self) -> Option<BrightnessMode> {
+        core::mem::take(&mut self.brightness_changed).then_some(self.saved_brightness)
+    }
+
+    /// Theme to persist, if one was chosen on leaving Settings since the last call
+    pub fn take_theme_change(&mut self) -> Option<ThemeId> {
+        core::mem::take(&mut self.theme_changed).then_some(self.saved_theme)
+    }
+
+    /// Leave the startup screen for the main screen
+    pub fn finish_startup(&mut self) -> Redraw {
+        if self.screen != MenuScreen::Startup {
+            return Redraw::None;
+        }
+        self.go_to(MenuScreen::Main)
+    }
+
+    /// Handle a key press, returning what needs redrawing
+    pub fn press(&mut self, key: usize) -> Redraw {
+        if key >= KEY_COUNT {
+            return Redraw::None;
+        }
+
+        match (self.screen, self.action(key)) {
+            // Keys do nothing until the main screen is up
+            (MenuScreen::Startup, _) => Redraw::None,
+
+            // From main screen
+            (MenuScreen::Main, Action::Trip) => self.go_to(MenuScreen::Trip),
+            (MenuScreen::Main, Action::Settings) => self.go_to(MenuScreen::Settings),
+
+            // Back button on the sub screens
+            (MenuScreen::Trip, Action::Back) | (MenuScreen::Settings, Action::Back) => {
+                self.go_to(MenuScreen::Main)
+            }
+
+            (MenuScreen::Trip, action @ (Action::Up | Action::Down)) => {
+                self.scroll_trip(action, false)
+            }
+
+            // Brightness steps are live, and saved on the way out
+            (MenuScreen::Settings, Action::Up | Action::Down) => {
+                self.brightness = self.brightness.step(self.action(key) == Action::Up);
+                Redraw::Content
+            }
+            // So is the theme, the whole screen changes with it
+            (MenuScreen::Settings, Action::Theme) => {
+                self.theme = self.theme.next();
+                Redraw::Content
+            }
+            (MenuScreen::Settings, Action::Keys) => {
+                self.editor = KeymapEditor::new();
+                self.go_to(MenuScreen::Keymap)
+            }
+            (MenuScreen::Keymap, Action::Back) => {
+                self.save_keymap();
+                self.go_to(MenuScreen::Settings)
+            }
+            (MenuScreen::Settings, Action::Led) => {
+                self.calibration_editor = CalibrationEditor::new();
+                self.go_to(MenuScreen::Calibration)
+            }
+            (MenuScreen::Calibration, Action::Back) => {
+                self.save_calibration();
+                self.go_to(MenuScreen::Settings)
+            }
+            (MenuScreen::Calibration, _) => {
+                if self.calibration_editor.press(key, &mut self.calibration) {
+                    Redraw::Content
+                } else {
+                    Redraw::None
+                }
+            }
+            (MenuScreen::Diagnostics, Action::Back) => self.go_to(self.return_to),
+            (MenuScreen::Diagnostics, _) => Redraw::None,
+            (MenuScreen::Keymap, _) => {
+                if self.editor.press(key, &mut self.keymap) {
+                    Redraw::Content
+                } else {
+                    Redraw::None
+                }
+            }
+
+            // Any other key just moves the highlight
+            _ if self.layout.active_index != key => {
+                self.layout.active_index = key;
+                Redraw::Buttons
+            }
+            _ => Redraw::None,
+        }
+    }
+
+    /// Handle a chord that fired, returning what needs redrawing
+    ///
+    /// The factory reset chord is left to the caller, it wipes storage.
+    pub fn chord(&mut self, id: ChordId) -> Redraw {
+        match (self.screen, id) {
+            (MenuScreen::Startup, _) => Redraw::None,
+            (_, ChordId::Brightness) => self.cycle_brightness(),
+            (MenuScreen::Diagnostics, _) => Redraw::None,
+            (screen, id) if id == DIAGNOSTICS_CHORD.id => {
+                self.return_to = match screen {
+                    // Leaving the editors this way keeps their edits
+                    MenuScreen::Keymap => {
+                        self.save_keymap();
+                        MenuScreen::Settings
+                    }
+                    MenuScreen::Calibration => {
+                        self.save_calibration();
+                        MenuScreen::Settings
+                    }
+                    screen => screen,
+                };
+                self.go_to(MenuScreen::Diagnostics)
+            }
+            _ => Redraw::None,
+        }
+    }
+
+    /// Step the brightness up from any screen, from full back to Auto, and
+    /// keep it
+    fn cycle_brightness(&mut self) -> Redraw {
+        let next = self.brightness.step(true);
+        self.brightness = if next == self.brightness {
+            BrightnessMode::Auto
+        } else {
+            next
+        };
+        self.save_brightness();
+        Redraw::Content
+    }
+
+    /// Handle an auto-repeat of a held key, only scrolling keys repeat
+    pub fn repeat(&mut self, key: usize) -> Redraw {
+        match (self.screen, self.action(key)) {
+            // Held, the route description moves a page at a time
+            (MenuScreen::Trip, action @ (Action::Up | Action::Down)) => {
+                self.scroll_trip(action, true)
+            }
+            (_, action) if action.is_scroll() => self.press(key),
+            _ => Redraw::None,
+        }
+    }
+
+    fn scroll_trip(&mut self, action: Action, held: bool) -> Redraw {
+        let mut viewer = self.trip_viewer();
+        if viewer.press(action, held) {
+            self.trip_top = viewer.top();
+            Redraw::Content
+        } else {
+            Redraw::None
+        }
+    }
+
+    fn save_keymap(&mut self) {
+        if self.keymap.check().is_err() {
+            self.keymap = self.saved_keymap;
+        } else if self.keymap != self.saved_keymap {
+            self.saved_keymap = self.keymap;
+            self.keymap_changed = true;
+        }
+    }
+
+    fn save_calibration(&mut self) {
+        if self.calibration != self.saved_calibration {
+            self.saved_calibration = self.calibration;
+            self.calibration_changed = true;
+        }
+    }
+
+    fn save_brightness(&mut self) {
+        if self.brightness != self.saved_brightness {
+            self.saved_brightness = self.brightness;
+            self.brightness_changed = true;
+        }
+    }
+
+    fn save_theme(&mut self) {
+        if self.theme != self.saved_theme {
+            self.saved_theme = self.theme;
+            self.theme_changed = true;
+        }
+    }
+
+    fn go_to(&mut self, screen: MenuScreen) -> Redraw {
+        if self.screen == MenuScreen::Settings {
+            self.save_brightness();
+            self.save_theme();
+        }
+        self.screen = screen;
+        self.layout = ButtonLayout {
+            labels: self.keymap.labels(screen),
+            active_index: 0,
+        };
+        Redraw::Screen
+    }
+}
+
+impl Default for Menu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_ignored_during_startup() {
+        let mut menu = Menu::new();
+        assert_eq!(menu.press(1), Redraw::None);
+        assert_eq!(menu.finish_startup(), Redraw::Screen);
+        assert_eq!(menu.screen(), MenuScreen::Main);
+        assert_eq!(menu.layout().labels, MAIN_BUTTONS);
+        assert_eq!(menu.finish_startup(), Redraw::None);
+    }
+
+    #[test]
+    fn navigate_to_trip_and_back() {
+        let mut menu = Menu::new();
+        menu.finish_startup();
+
+        assert_eq!(menu.press(1), Redraw::Screen);
+        assert_eq!(menu.screen(), MenuScreen::Trip);
+        assert_eq!(menu.layout().labels, TRIP_BUTTONS);
+
+        assert_eq!(menu.press(2), Redraw::Buttons);
+        assert_eq!(menu.layout().active_index, 2);
+        assert_eq!(menu.press(2), Redraw::None);
+
+        assert_eq!(menu.press(0), Redraw::Screen);
+        assert_eq!(menu.screen(), MenuScreen::Main);
+        assert_eq!(menu.layout().active_index, 0);
+    }
+
+    #[test]
+    fn settings_back_and_out_of_range() {
+        let mut menu = Menu::new();
+        menu.finish_startup();
+        assert_eq!(menu.press(KEY_COUNT), Redraw::None);
+        assert_eq!(menu.press(2), Redraw::Screen);
+        assert_eq!(menu.screen(), MenuScreen::Settings);
+        assert_eq!(menu.press(0), Redraw::Screen);
+        assert_eq!(menu.screen(), MenuScreen::Main);
+    }
+
+    #[test]
+    fn long_text_pages_at_a_readable_pace() {
+        let page = MenuScreen::Trip.repeat_curve();
+        let value = MenuScreen::Settings.repeat_curve();
+        assert!(page.interval(0) > value.interval(0));
+        // However long the key is held, a page stays up a while
+        assert!((0..50).all(|n| page.interval(n) >= Duration::from_millis(400)));
+        assert!(page.interval(10) < page.interval(0));
+        // Up/Down only move the highlight on the main screen
+        assert_eq!(MenuScreen::Main.repeat_curve(), MENU_REPEAT);
+
+        // What the page curve paces on Trip
+        let mut menu = Menu::new();
+        menu.finish_startup();
+        menu.press(1);
+        menu.set_trip_text(&"Turn left, then right. ".repeat(20));
+        let rows = menu.trip_viewer().rows();
+        assert_eq!(menu.repeat(DOWN_KEY), Redraw::Content);
+        assert_eq!(menu.trip_viewer().top(), rows);
+        assert_eq!(menu.layout().active_index, 0);
+    }
+
+    #[test]
+    fn trip_text_scrolls_by_line_and_page() {
+        let mut menu = Menu::new();
+        menu.finish_startup();
+        menu.press(1);
+        // Nothing to scroll without a route
+        assert_eq!(menu.press(DOWN_KEY), Redraw::None);
+        assert_eq!(menu.layout().active_index, 0);
+
+        let route = "Walk to Hauptbahnhof, then take the S-Bahn towards \
+            Frankfurt-Niederrad. Change at Hauptwache for the U4 to \
+            Bockenheimer Warte, get off at the second stop and walk up \
+            Senckenberganlage.\nArrive 8:42, four minutes early.";
+        assert_eq!(menu.set_trip_text(route), Redraw::Content);
+        let rows = menu.trip_viewer().rows();
+        assert!(menu.trip_viewer().lines() > 2 * rows);
+
+        assert_eq!(menu.press(DOWN_KEY), Redraw::Content);
+        assert_eq!(menu.trip_viewer().top(), 1);
+        assert_eq!(menu.repeat(DOWN_KEY), Redraw::Content);
+        assert_eq!(menu.trip_viewer().top(), 1 + rows);
+        assert_eq!(menu.press(UP_KEY), Redraw::Content);
+        assert_eq!(menu.trip_viewer().top(), rows);
+        while menu.repeat(DOWN_KEY) == Redraw::Content {}
+        let (page, pages) = menu.trip_viewer().page();
+        assert_eq!(page, pages);
+
+        // The position is kept across screens, a new route starts at the top
+        menu.press(0);
+        menu.press(1);
+        assert_eq!(menu.trip_viewer().page().0, pages);
+        menu.press(0);
+        assert_eq!(menu.set_trip_text("Arrived"), Redraw::None);
+        assert_eq!(menu.trip_viewer().top(), 0);
+    }
+
+    #[test]
+    fn trip_text_is_cut_on_a_character() {
+        let mut menu = Menu::new();
+        let long: std::string::String = "ä".repeat(MAX_TRIP_TEXT);
+        menu.set_trip_text(&long);
+        assert_eq!(menu.trip_text().len(), MAX_TRIP_TEXT);
+        assert!(menu.trip_text().chars().all(|c| c == 'ä'));
+    }
+
+    #[test]
+    fn remapped_keys_follow_the_keymap() {
+        let mut menu = Menu::with_keymap(Keymap::mirrored());
+        menu.finish_startup();
+        assert_eq!(menu.layout().labels[0], "Down");
+
+        // Trip moved from key 2 to key 5
+        assert_eq!(menu.press(1), Redraw::Buttons);
+        assert_eq!(menu.press(4), Redraw::Screen);
+        assert_eq!(menu.screen(), MenuScreen::Trip);
+        assert_eq!(menu.press(5), Redraw::Screen);
+        assert_eq!(menu.screen(), MenuScreen::Main);
+    }
+
+    #[test]
+    fn only_scroll_keys_repeat() {
+        let mut menu = Menu::new();
+        menu.finish_startup();
+        assert_eq!(menu.repeat(1), Redraw::None);
+        assert_eq!(menu.screen(), MenuScreen::Main);
+        assert_eq!(menu.repeat(DOWN_KEY), Redraw::Buttons);
+    }
+
+    #[test]
+    fn keymap_edits_are_saved_on_back() {
+        let mut menu = Menu::new();
+        menu.finish_startup();
+        menu.press(2);
+        assert_eq!(menu.press(3), Redraw::Screen);
+        assert_eq!(menu.screen(), MenuScreen::Keymap);
+        assert_eq!(menu.layout().labels, KEYMAP_BUTTONS);
+
+        // Swap the whole layout for left-handed use
+        assert_eq!(menu.press(3), Redraw::Content);
+        assert_eq!(menu.take_keymap_change(), None);
+        assert_eq!(menu.press(0), Redraw::Screen);
+        assert_eq!(menu.screen(), MenuScreen::Settings);
+        assert_eq!(menu.layout().labels[5], "Back");
+        assert_eq!(menu.take_keymap_change(), Some(Keymap::mirrored()));
+        assert_eq!(menu.take_keymap_change(), None);
+    }
+
+    #[test]
+    fn broken_keymap_is_discarded() {
+        let mut menu = Menu::new();
+        menu.finish_startup();
+        menu.press(2);
+        menu.press(3);
+
+        // Turn the main screen's Settings key into something else
+        menu.press(DOWN_KEY);
+        menu.press(DOWN_KEY);
+        menu.press(2);
+        menu.press(0);
+        assert_eq!(menu.keymap(), &Keymap::new());
+        assert_eq!(menu.take_keymap_change(), None);
+    }
+
+    #[test]
+    fn settings_steps_brightness() {
+        let mut menu = Menu::new().with_brightness(BrightnessMode::Manual(50));
+        menu.finish_startup();
+        menu.press(2);
+        assert_eq!(menu.press(UP_KEY), Redraw::Content);
+        assert_eq!(menu.repeat(UP_KEY), Redraw::Content);
+        assert_eq!(menu.brightness(), BrightnessMode::Manual(100));
+        assert_eq!(menu.take_brightness_change(), None);
+
+        // Down past the dimmest step hands it back to the sensor
+        for _ in 0..6 {
+            menu.press(DOWN_KEY);
+        }
+        assert_eq!(menu.brightness(), BrightnessMode::Auto);
+        menu.press(0);
+        assert_eq!(menu.take_brightness_change(), Some(BrightnessMode::Auto));
+        assert_eq!(menu.take_brightness_change(), None);
+    }
+
+    #[test]
+    fn settings_cycles_themes_and_accents() {
+        let mut menu = Menu::new().with_theme(ThemeId::Light);
+        assert_eq!(
+            menu.set_accent(Some(RgbColor::new(200, 0, 255))),
+            Redraw::None
+        );
+        menu.finish_startup();
+        assert_eq!(menu.theme().background, Theme::LIGHT.background);
+        assert_ne!(menu.theme().button, Theme::LIGHT.button);
+
+        menu.press(2);
+        assert_eq!(menu.press(1), Redraw::Content);
+        assert_eq!(menu.theme_id(), ThemeId::HighContrast);
+        // High contrast ignores the accent, the primary key included
+        assert_eq!(menu.theme().button, Theme::HIGH_CONTRAST.button);
+        menu.press(0);
+        menu.press(3);
+        assert_eq!(menu.hint_colors()[1], Theme::HIGH_CONTRAST.primary_key);
+        assert_eq!(menu.take_theme_change(), Some(ThemeId::HighContrast));
+        assert_eq!(menu.take_theme_change(), None);
+
+        assert_eq!(menu.set_accent(None), Redraw::Content);
+        assert_eq!(menu.set_accent(None), Redraw::None);
+    }
+
+    #[test]
+    fn calibration_screen_shows_test_colors() {
+        let mut menu = Menu::new();
+        menu.finish_startup();
+        menu.press(2);
+        assert_eq!(menu.test_color(), None);
+        assert_eq!(menu.press(2), Redraw::Screen);
+        assert_eq!(menu.screen(), MenuScreen::Calibration);
+        assert_eq!(menu.layout().labels, CALIBRATION_BUTTONS);
+        assert_eq!(menu.test_color(), Some(crate::leds::colors::WHITE));
+        assert_eq!(menu.repeat(UP_KEY), Redraw::Content);
+
+        // Edits are live but only saved on the way out
+        assert_eq!(menu.calibration().leds[0].matrix[0][0], 264);
+        assert_eq!(menu.take_calibration_change(), None);
+        assert_eq!(menu.press(0), Redraw::Screen);
+        assert_eq!(menu.screen(), MenuScreen::Settings);
+        assert_eq!(menu.test_color(), None);
+        let saved = menu.take_calibration_change().unwrap();
+        assert_eq!(saved.leds[0].matrix[0][0], 264);
+        assert_eq!(menu.take_calibration_change(), None);
+    }
+
+    #[test]
+    fn brightness_chord_cycles_and_saves() {
+        let mut menu = Menu::new().with_brightness(BrightnessMode::Manual(75));
+        assert_eq!(menu.chord(ChordId::Brightness), Redraw::None);
+        menu.finish_startup();
+
+        assert_eq!(menu.chord(ChordId::Brightness), Redraw::Content);
+        assert_eq!(menu.brightness(), BrightnessMode::Manual(100));
+        assert_eq!(
+            menu.take_brightness_change(),
+            Some(BrightnessMode::Manual(100))
+        );
+        // Full wraps around to the sensor
+        menu.chord(ChordId::Brightness);
+        assert_eq!(menu.brightness(), BrightnessMode::Auto);
+        assert_eq!(menu.take_brightness_change(), Some(BrightnessMode::Auto));
+        assert_eq!(menu.screen(), MenuScreen::Main);
+        assert_eq!(menu.chord(ChordId::FactoryReset), Redraw::None);
+    }
+
+    #[test]
+    fn diagnostics_chord_opens_and_returns() {
+        let mut menu = Menu::new();
+        assert_eq!(menu.chord(DIAGNOSTICS_CHORD.id), Redraw::None);
+        menu.finish_startup();
+        menu.press(1);
+
+        assert_eq!(menu.chord(ChordId::App(7)), Redraw::None);
+        assert_eq!(menu.chord(DIAGNOSTICS_CHORD.id), Redraw::Screen);
+        assert_eq!(menu.screen(), MenuScreen::Diagnostics);
+        assert_eq!(menu.press(3), Redraw::None);
+        assert_eq!(menu.press(0), Redraw::Screen);
+        assert_eq!(menu.screen(), MenuScreen::Trip);
+    }
+
+    #[test]
+    fn key_stats_redraw_only_the_diagnostics_screen() {
+        let mut menu = Menu::new();
+        menu.finish_startup();
+        let mut stats = [KeyStats::new(); KEY_COUNT];
+        stats[2].presses = 12;
+        assert_eq!(menu.set_key_stats(stats), Redraw::None);
+        assert_eq!(menu.key_stats()[2].presses, 12);
+
+        menu.chord(DIAGNOSTICS_CHORD.id);
+        assert_eq!(menu.set_key_stats(stats), Redraw::None);
+        stats[2].presses = 13;
+        assert_eq!(menu.set_key_stats(stats), Redraw::Content);
+        // An accent change redraws it with the same counters
+        assert_eq!(
+            menu.set_accent(Some(RgbColor::new(0, 0, 255))),
+            Redraw::Content
+        );
+        assert_eq!(menu.key_stats()[2].presses, 13);
+    }
+
+    #[test]
+    fn diagnostics_chord_is_not_reserved() {
+        let mut registry = crate::buttons::ChordRegistry::new();
+        assert_eq!(registry.bind(DIAGNOSTICS_CHORD), Ok(()));
+    }
+
+    #[test]
+    fn hints_follow_labels_and_keymap() {
+        let mut menu = Menu::new();
+        assert_eq!(menu.hints(), [KeyHint::Off; KEY_COUNT]);
+
+        menu.finish_startup();
+        assert_eq!(menu.hints()[1], KeyHint::Primary);
+        assert_eq!(menu.hints()[0], KeyHint::Enabled);
+
+        menu.press(1);
+        assert_eq!(menu.hints()[0], KeyHint::Back);
+        assert_eq!(menu.hints()[1], KeyHint::Primary);
+
+        // Empty labels go dark, Back keeps its color on every screen
+        menu.chord(DIAGNOSTICS_CHORD.id);
+        let mut expected = [KeyHint::Off; KEY_COUNT];
+        expected[0] = KeyHint::Back;
+        assert_eq!(menu.hints(), expected);
+
+        let mut menu = Menu::with_keymap(Keymap::mirrored());
+        menu.finish_startup();
+        menu.press(4);
+        assert_eq!(menu.hints()[5], KeyHint::Back);
+        assert_eq!(menu.hints()[4], KeyHint::Primary);
+    }
+}
@@ -0,0 +1,331 @@
+//! Animation timelines
+//! Keyframed values over time with easing, independent of the frame rate
+//!
+//! A [`Timeline`] is anchored at an [`Instant`] and only ever asked for its
+//! value at another `Instant`, so a late or skipped frame shows the right
+//! value instead of slowing the whole animation down.
+
+use embassy_time::{Duration, Instant};
+use embedded_graphics::prelude::Point;
+use heapless::Vec;
+
+use crate::leds::RgbColor;
+
+/// Most keyframes in one timeline
+pub const MAX_KEYFRAMES: usize = 8;
+
+/// How progress through a segment maps to progress of the value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Easing {
+    /// Constant speed
+    #[default]
+    Linear,
+    /// Quadratic, slow at both ends
+    EaseInOut,
+    /// Cubic ease-out, fast start and a long gentle stop
+    Cubic,
+    /// Overshoots the end and bounces to rest
+    Bounce,
+}
+
+impl Easing {
+    /// Eased progress for `t` in `0.0..=1.0`, clamped outside that
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    let u = 1.0 - t;
+                    1.0 - 2.0 * u * u
+                }
+            }
+            Easing::Cubic => {
+                let u = 1.0 - t;
+                1.0 - u * u * u
+            }
+            Easing::Bounce => bounce(t),
+        }
+    }
+}
+
+/// Ease-out bounce made of four parabolas
+fn bounce(t: f32) -> f32 {
+    const N: f32 = 7.5625;
+    const D: f32 = 2.75;
+    if t < 1.0 / D {
+        N * t * t
+    } else if t < 2.0 / D {
+        let t = t - 1.5 / D;
+        N * t * t + 0.75
+    } else if t < 2.5 / D {
+        let t = t - 2.25 / D;
+        N * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / D;
+        N * t * t + 0.984375
+    }
+}
+
+/// Values a timeline can animate
+pub trait Lerp: Copy {
+    /// Value `t` of the way from `self` to `to`
+    fn lerp(self, to: Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(self, to: Self, t: f32) -> Self {
+        self + (to - self) * t
+    }
+}
+
+impl Lerp for i32 {
+    fn lerp(self, to: Self, t: f32) -> Self {
+        (self as f32).lerp(to as f32, t) as i32
+    }
+}
+
+impl Lerp for u8 {
+    fn lerp(self, to: Self, t: f32) -> Self {
+        // Bounce overshoots a little, keep inside the channel range
+        (self as f32).lerp(to as f32, t).clamp(0.0, 255.0) as u8
+    }
+}
+
+impl Lerp for RgbColor {
+    fn lerp(self, to: Self, t: f32) -> Self {
+        RgbColor::new(
+            self.r.lerp(to.r, t),
+            self.g.lerp(to.g, t),
+            self.b.lerp(to.b, t),
+        )
+    }
+}
+
+impl Lerp for Point {
+    fn lerp(self, to: Self, t: f32) -> Self {
+        Point::new(self.x.lerp(to.x, t), self.y.lerp(to.y, t))
+    }
+}
+
+/// A value at a point in time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe<T> {
+    /// Offset from the start of the timeline
+    pub at: Duration,
+    pub value: T,
+    /// Easing of the segment that ends at this keyframe
+    pub easing: Easing,
+}
+
+/// What happens after the last keyframe
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Playback {
+    /// Hold the last value
+    Once,
+    /// Jump back to the first keyframe
+    Loop,
+    /// Play backwards to the first keyframe, then forwards again
+    PingPong,
+}
+
+/// Keyframed animation of one value
+#[derive(Debug, Clone)]
+pub struct Timeline<T> {
+    keyframes: Vec<Keyframe<T>, MAX_KEYFRAMES>,
+    playback: Playback,
+    start: Instant,
+}
+
+impl<T: Lerp> Timeline<T> {
+    /// Empty timeline starting at `start`
+    pub const fn new(start: Instant, playback: Playback) -> Self {
+        Self {
+            keyframes: Vec::new(),
+            playback,
+            start,
+        }
+    }
+
+    /// Single eased move from one value to another
+    pub fn tween(start: Instant, from: T, to: T, duration: Duration, easing: Easing) -> Self {
+        let mut timeline = Self::new(start, Playback::Once);
+        // Two keyframes always fit
+        let _ = timeline.push(Duration::from_ticks(0), from, Easing::Linear);
+        let _ = timeline.push(duration, to, easing);
+        timeline
+    }
+
+    /// Append a keyframe, they have to come in time order
+    pub fn push(&mut self, at: Duration, value: T, easing: Easing) -> Result<(), &'static str> {
+        if self.keyframes.last().is_some_and(|last| at < last.at) {
+            return Err("Keyframes out of order");
+        }
+        self.keyframes
+            .push(Keyframe { at, value, easing })
+            .map_err(|_| "Too many keyframes")
+    }
+
+    /// When the timeline started
+    pub fn start(&self) -> Instant {
+        self.start
+    }
+
+    /// Start over from `now`
+    pub fn restart(&mut self, now: Instant) {
+        self.start = now;
+    }
+
+    /// Time from the start to the last keyframe
+    pub fn duration(&self) -> Duration {
+        self.keyframes
+            .last()
+            .map_or(Duration::from_ticks(0), |last| last.at)
+    }
+
+    /// Whether a one-shot timeline has reached its last keyframe
+    pub fn is_finished(&self, now: Instant) -> bool {
+        self.playback == Playback::Once && now >= self.start + self.duration()
+    }
+
+    /// Value at `now`, `None` without keyframes
+    pub fn value_at(&self, now: Instant) -> Option<T> {
+        let first = self.keyframes.first()?;
+        let elapsed = now.saturating_duration_since(self.start).as_ticks();
+        let total = self.duration().as_ticks();
+
+        let t = match self.playback {
+            _ if total == 0 => elapsed,
+            Playback::Once => elapsed.min(total),
+            Playback::Loop => elapsed % total,
+            Playback::PingPong => {
+                let t = elapsed % (2 * total);
+                if t > total {
+                    2 * total - t
+                } else {
+                    t
+                }
+            }
+        };
+
+        let mut from = first;
+        for to in self.keyframes.iter() {
+            let end = to.at.as_ticks();
+            if t <= end {
+                let start = from.at.as_ticks();
+                if end == start {
+                    return Some(to.value);
+                }
+                let progress = (t - start) as f32 / (end - start) as f32;
+                return Some(from.value.lerp(to.value, to.easing.apply(progress)));
+            }
+            from = to;
+        }
+        Some(from.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(t: u64) -> Duration {
+        Duration::from_millis(t)
+    }
+
+    fn at(t: u64) -> Instant {
+        Instant::from_millis(t)
+    }
+
+    #[test]
+    fn easing_end_points() {
+        for easing in [
+            Easing::Linear,
+            Easing::EaseInOut,
+            Easing::Cubic,
+            Easing::Bounce,
+        ] {
+            assert_eq!(easing.apply(0.0), 0.0, "{easing:?}");
+            assert!((easing.apply(1.0) - 1.0).abs() < 1e-4, "{easing:?}");
+        }
+        assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+        assert!(Easing::EaseInOut.apply(0.1) < 0.1);
+        assert!(Easing::Cubic.apply(0.1) > 0.25);
+        assert_eq!(Easing::Linear.apply(2.0), 1.0);
+    }
+
+    #[test]
+    fn bounce_comes_back_down() {
+        let peak = Easing::Bounce.apply(1.0 / 2.75);
+        let dip = Easing::Bounce.apply(1.5 / 2.75);
+        assert!((peak - 1.0).abs() < 1e-4);
+        assert!(dip < peak);
+    }
+
+    #[test]
+    fn value_depends_only_on_time() {
+        let tween = Timeline::tween(at(1000), 0i32, 100, ms(200), Easing::Linear);
+        assert_eq!(tween.value_at(at(500)), Some(0));
+        assert_eq!(tween.value_at(at(1100)), Some(50));
+        // Skipping frames lands on the same value
+        assert_eq!(tween.value_at(at(1150)), Some(75));
+        assert_eq!(tween.value_at(at(9000)), Some(100));
+        assert!(tween.is_finished(at(1200)));
+        assert!(!tween.is_finished(at(1199)));
+    }
+
+    #[test]
+    fn keyframes_loop_and_ping_pong() {
+        let mut timeline = Timeline::new(at(0), Playback::Loop);
+        timeline.push(ms(0), 0u8, Easing::Linear).unwrap();
+        timeline.push(ms(100), 200, Easing::Linear).unwrap();
+        timeline.push(ms(200), 100, Easing::Linear).unwrap();
+        assert_eq!(timeline.value_at(at(50)), Some(100));
+        assert_eq!(timeline.value_at(at(150)), Some(150));
+        assert_eq!(timeline.value_at(at(250)), Some(100));
+        assert!(!timeline.is_finished(at(10_000)));
+
+        let mut timeline = Timeline::new(at(0), Playback::PingPong);
+        timeline.push(ms(0), 0i32, Easing::Linear).unwrap();
+        timeline.push(ms(100), 100, Easing::Linear).unwrap();
+        assert_eq!(timeline.value_at(at(150)), Some(50));
+        assert_eq!(timeline.value_at(at(200)), Some(0));
+    }
+
+    #[test]
+    fn keyframes_must_be_in_order() {
+        let mut timeline = Timeline::new(at(0), Playback::Once);
+        assert_eq!(timeline.value_at(at(0)), None);
+        timeline.push(ms(100), 1.0f32, Easing::Linear).unwrap();
+        assert_eq!(
+            timeline.push(ms(50), 2.0, Easing::Linear),
+            Err("Keyframes out of order")
+        );
+        // Before the first keyframe the first value holds
+        assert_eq!(timeline.value_at(at(10)), Some(1.0));
+    }
+
+    #[test]
+    fn colors_and_points_interpolate() {
+        let fade = Timeline::tween(
+            at(0),
+            RgbColor::new(0, 0, 0),
+            RgbColor::new(200, 100, 0),
+            ms(100),
+            Easing::Linear,
+        );
+        assert_eq!(fade.value_at(at(50)), Some(RgbColor::new(100, 50, 0)));
+
+        let slide = Timeline::tween(
+            at(0),
+            Point::new(0, 0),
+            Point::new(160, 0),
+            ms(100),
+            Easing::Cubic,
+        );
+        let x = slide.value_at(at(50)).unwrap().x;
+        assert!(x > 80 && x < 160);
+    }
+}
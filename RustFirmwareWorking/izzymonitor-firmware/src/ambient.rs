@@ -0,0 +1,132 @@
+//! Adaptive brightness
+//! Follows the Qwiic light sensor with the LCD backlight and the LEDs
+//!
+//! The backlight is PWM on LEDC timer 0. Without a sensor, auto brightness
+//! stays at full, as the panel was before it had one.
+
+use core::cell::Cell;
+use core::future::pending;
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+use esp_hal::gpio::AnyPin;
+use esp_hal::i2c::master::I2c;
+use esp_hal::ledc::channel::{self, ChannelHW, ChannelIFace};
+use esp_hal::ledc::timer::{self, TimerIFace};
+use esp_hal::ledc::{LSGlobalClkSource, Ledc, LowSpeed};
+use esp_hal::peripherals::LEDC;
+use esp_hal::prelude::*;
+use esp_hal::Blocking;
+use izzymonitor_core::ambient::{Brightness, BrightnessMode, Levels, LightSensor};
+use log::warn;
+use static_cell::StaticCell;
+
+use crate::leds;
+
+/// The light sensor on the Qwiic port
+pub type Sensor = LightSensor<I2c<'static, Blocking>>;
+
+/// Backlight PWM frequency, well above what a camera or an eye picks up
+pub const BACKLIGHT_PWM_KHZ: u32 = 24;
+
+/// Time between sensor readings
+const SAMPLE_PERIOD: Duration = Duration::from_millis(250);
+
+static BACKLIGHT_TIMER: StaticCell<timer::Timer<'static, LowSpeed>> = StaticCell::new();
+
+/// Brightness setting changes from the Settings screen
+static MODE: Signal<CriticalSectionRawMutex, BrightnessMode> = Signal::new();
+
+/// Smoothed light and the levels in use, for diagnostics
+static STATE: Mutex<CriticalSectionRawMutex, Cell<(Option<f32>, Levels)>> =
+    Mutex::new(Cell::new((None, Levels::FULL)));
+
+/// The LCD backlight on a PWM channel
+pub struct Backlight {
+    channel: channel::Channel<'static, LowSpeed>,
+}
+
+impl Backlight {
+    /// Set up the PWM, starting at full brightness
+    pub fn new(ledc: LEDC, pin: AnyPin) -> Result<Self, &'static str> {
+        let mut ledc = Ledc::new(ledc);
+        ledc.set_global_slow_clock(LSGlobalClkSource::APBClk);
+
+        let timer = BACKLIGHT_TIMER.init(ledc.timer::<LowSpeed>(timer::Number::Timer0));
+        timer
+            .configure(timer::config::Config {
+                duty: timer::config::Duty::Duty8Bit,
+                clock_source: timer::LSClockSource::APBClk,
+                frequency: BACKLIGHT_PWM_KHZ.kHz(),
+            })
+            .map_err(|_| "Failed to set up backlight timer")?;
+        let timer: &'static timer::Timer<'static, LowSpeed> = timer;
+
+        let mut channel = ledc.channel(channel::Number::Channel0, pin);
+        channel
+            .configure(channel::config::Config {
+                timer,
+                duty_pct: 100,
+                pin_config: channel::config::PinConfig::PushPull,
+            })
+            .map_err(|_| "Failed to set up backlight channel")?;
+        Ok(Self { channel })
+    }
+
+    /// Set the duty, 255 is full
+    pub fn set(&self, level: u8) {
+        self.channel.set_duty_hw(u32::from(level));
+    }
+}
+
+/// Use a new brightness setting
+pub fn set_mode(mode: BrightnessMode) {
+    MODE.signal(mode);
+}
+
+/// The smoothed light in lux, `None` without a sensor, and the levels in use
+pub fn state() -> (Option<f32>, Levels) {
+    STATE.lock(Cell::get)
+}
+
+/// Task reading the sensor and setting the backlight and LED levels
+#[embassy_executor::task]
+pub async fn brightness_task(
+    mut sensor: Option<Sensor>,
+    backlight: Backlight,
+    mode: BrightnessMode,
+) {
+    let mut brightness = Brightness::new(mode);
+    let mut shown = None;
+
+    loop {
+        if let Some(sensor) = sensor.as_mut() {
+            match sensor.lux() {
+                Ok(lux) => brightness.sample(lux),
+                Err(e) => warn!("{}", e),
+            }
+        }
+
+        let levels = brightness.levels();
+        if shown != Some(levels) {
+            backlight.set(levels.backlight);
+            leds::set_brightness(levels.leds);
+            shown = Some(levels);
+        }
+        STATE.lock(|s| s.set((brightness.lux(), levels)));
+
+        let sample = async {
+            if sensor.is_some() {
+                Timer::after(SAMPLE_PERIOD).await
+            } else {
+                pending().await
+            }
+        };
+        if let Either::Second(mode) = select(sample, MODE.wait()).await {
+            brightness.set_mode(mode);
+        }
+    }
+}
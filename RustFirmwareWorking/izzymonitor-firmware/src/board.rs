@@ -0,0 +1,116 @@
+//! Board pin-map profiles
+//! Every driver takes its GPIOs from the profile selected at build time
+//!
+//! The Izzymonitor PCB is the default. Build with `--features board-devkitc`
+//! for the ESP32-S3 DevKitC breadboard setup.
+
+use esp_hal::gpio::AnyPin;
+use izzymonitor_core::leds::LedModel;
+use izzymonitor_core::KEY_COUNT;
+
+pub use izzymonitor_core::leds::power::{SK6805, WS2812B};
+
+/// Pins for the ST7735 on SPI2 and its backlight
+pub struct DisplayPins {
+    pub sck: AnyPin,
+    pub mosi: AnyPin,
+    pub cs: AnyPin,
+    pub dc: AnyPin,
+    pub rst: AnyPin,
+    pub backlight: AnyPin,
+}
+
+/// Every pin the firmware drivers need
+pub struct Board {
+    pub name: &'static str,
+    pub display: DisplayPins,
+    /// Kailh keys, left to right, active low
+    pub keys: [AnyPin; KEY_COUNT],
+    /// Data line of the key LED chain
+    pub led: AnyPin,
+    /// Current profile of the LEDs on the chain
+    pub led_model: LedModel,
+    /// Data line of the external strip, also MOSI for clocked strips
+    pub strip_data: AnyPin,
+    /// Clock line of an APA102/SK9822 strip, unused by one-wire strips
+    pub strip_clock: AnyPin,
+    /// Qwiic I2C data, for the light sensor
+    pub qwiic_sda: AnyPin,
+    /// Qwiic I2C clock
+    pub qwiic_scl: AnyPin,
+}
+
+/// Split the board pins out of `esp_hal::init`'s peripherals
+///
+/// Izzymonitor PCB: display on GPIO36-40, keys on GPIO14/21/47/48/45/35,
+/// SK6805 key LEDs on GPIO16, backlight on GPIO46, the strip header on
+/// GPIO17 (data) and GPIO18 (clock) and Qwiic on GPIO1 (SDA) and GPIO2 (SCL).
+#[cfg(not(feature = "board-devkitc"))]
+#[macro_export]
+macro_rules! take_board {
+    ($p:ident) => {
+        $crate::board::Board {
+            name: "Izzymonitor PCB",
+            display: $crate::board::DisplayPins {
+                sck: esp_hal::gpio::Pin::degrade($p.GPIO36),
+                mosi: esp_hal::gpio::Pin::degrade($p.GPIO37),
+                cs: esp_hal::gpio::Pin::degrade($p.GPIO38),
+                dc: esp_hal::gpio::Pin::degrade($p.GPIO39),
+                rst: esp_hal::gpio::Pin::degrade($p.GPIO40),
+                backlight: esp_hal::gpio::Pin::degrade($p.GPIO46),
+            },
+            keys: [
+                esp_hal::gpio::Pin::degrade($p.GPIO14),
+                esp_hal::gpio::Pin::degrade($p.GPIO21),
+                esp_hal::gpio::Pin::degrade($p.GPIO47),
+                esp_hal::gpio::Pin::degrade($p.GPIO48),
+                esp_hal::gpio::Pin::degrade($p.GPIO45),
+                esp_hal::gpio::Pin::degrade($p.GPIO35),
+            ],
+            led: esp_hal::gpio::Pin::degrade($p.GPIO16),
+            led_model: $crate::board::SK6805,
+            strip_data: esp_hal::gpio::Pin::degrade($p.GPIO17),
+            strip_clock: esp_hal::gpio::Pin::degrade($p.GPIO18),
+            qwiic_sda: esp_hal::gpio::Pin::degrade($p.GPIO1),
+            qwiic_scl: esp_hal::gpio::Pin::degrade($p.GPIO2),
+        }
+    };
+}
+
+/// Split the board pins out of `esp_hal::init`'s peripherals
+///
+/// ESP32-S3 DevKitC breadboard: display on GPIO12/13/11/10/9 (SCK/MOSI/CS/
+/// DC/RST), keys on GPIO4-7/15/17, WS2812B LEDs on GPIO18, backlight on
+/// GPIO8, a strip on GPIO1 (data) and GPIO2 (clock) and a Qwiic breakout on
+/// GPIO16 (SDA) and GPIO21 (SCL). Strapping and USB pins are left free.
+#[cfg(feature = "board-devkitc")]
+#[macro_export]
+macro_rules! take_board {
+    ($p:ident) => {
+        $crate::board::Board {
+            name: "ESP32-S3 DevKitC",
+            display: $crate::board::DisplayPins {
+                sck: esp_hal::gpio::Pin::degrade($p.GPIO12),
+                mosi: esp_hal::gpio::Pin::degrade($p.GPIO13),
+                cs: esp_hal::gpio::Pin::degrade($p.GPIO11),
+                dc: esp_hal::gpio::Pin::degrade($p.GPIO10),
+                rst: esp_hal::gpio::Pin::degrade($p.GPIO9),
+                backlight: esp_hal::gpio::Pin::degrade($p.GPIO8),
+            },
+            keys: [
+                esp_hal::gpio::Pin::degrade($p.GPIO4),
+                esp_hal::gpio::Pin::degrade($p.GPIO5),
+                esp_hal::gpio::Pin::degrade($p.GPIO6),
+                esp_hal::gpio::Pin::degrade($p.GPIO7),
+                esp_hal::gpio::Pin::degrade($p.GPIO15),
+                esp_hal::gpio::Pin::degrade($p.GPIO17),
+            ],
+            led: esp_hal::gpio::Pin::degrade($p.GPIO18),
+            led_model: $crate::board::WS2812B,
+            strip_data: esp_hal::gpio::Pin::degrade($p.GPIO1),
+            strip_clock: esp_hal::gpio::Pin::degrade($p.GPIO2),
+            qwiic_sda: esp_hal::gpio::Pin::degrade($p.GPIO16),
+            qwiic_scl: esp_hal::gpio::Pin::degrade($p.GPIO21),
+        }
+    };
+}
@@ -0,0 +1,135 @@
+//! Button handling module
+//! Interrupt-driven, debounced key events for the six Kailh keys
+
+use core::cell::Cell;
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::{Instant, Timer};
+use esp_hal::gpio::Input;
+use izzymonitor_core::buttons::{ChordDetector, Debouncer, KeyEventQueue, RepeatSignal};
+use izzymonitor_core::KEY_COUNT;
+use log::{info, warn};
+
+pub use izzymonitor_core::buttons::{
+    next_event, wait_for_button_press, ButtonChannel, ButtonEvent, ButtonState, ButtonStates,
+    ButtonSubscriber, ChordId, ChordRegistry, DebounceConfig, KeyEvent, KeyEventKind, KeyStats,
+    RepeatCurve, KEY_NAMES,
+};
+
+/// Raw key events on their way to the chord task
+static KEY_EVENTS: KeyEventQueue = KeyEventQueue::new();
+
+/// Repeat curve updates for each key task
+static REPEAT_CURVES: [RepeatSignal; KEY_COUNT] = [const { RepeatSignal::new() }; KEY_COUNT];
+
+/// Latest statistics of every key, updated by the key tasks
+static KEY_STATS: Mutex<CriticalSectionRawMutex, Cell<[KeyStats; KEY_COUNT]>> =
+    Mutex::new(Cell::new([KeyStats::new(); KEY_COUNT]));
+
+/// Every button event, delivered in order to each subscriber
+pub static BUTTON_EVENTS: ButtonChannel = ButtonChannel::new();
+
+/// Subscribe to the key events, one subscriber per consuming task
+pub fn subscribe() -> ButtonSubscriber<'static> {
+    BUTTON_EVENTS
+        .subscriber()
+        .expect("too many button event subscribers")
+}
+
+/// Change how a held key repeats, from its next press on
+pub fn set_repeat_curve(key: usize, curve: RepeatCurve) {
+    if let Some(signal) = REPEAT_CURVES.get(key) {
+        signal.signal(curve);
+    }
+}
+
+/// Snapshot of the press, bounce and hold statistics of every key
+pub fn key_stats() -> [KeyStats; KEY_COUNT] {
+    KEY_STATS.lock(Cell::get)
+}
+
+/// Watch one key, spawned once per key
+///
+/// Edge interrupts wake the task and the debouncer's deadlines drive the
+/// settle window, long press and repeat timing. The key is active low.
+#[embassy_executor::task(pool_size = KEY_COUNT)]
+pub async fn key_task(mut pin: Input<'static>, key: usize, config: DebounceConfig) {
+    let mut debouncer = Debouncer::new(key, config);
+    let mut pressed = false;
+
+    loop {
+        // Waiting for the opposite level returns at once if we missed an edge
+        let edge = async {
+            if pressed {
+                pin.wait_for_high().await
+            } else {
+                pin.wait_for_low().await
+            }
+        };
+
+        match debouncer.next_deadline() {
+            Some(deadline) => {
+                select(edge, Timer::at(deadline)).await;
+            }
+            None => edge.await,
+        }
+
+        if let Some(curve) = REPEAT_CURVES[key].try_take() {
+            debouncer.set_repeat(curve);
+        }
+
+        let now = Instant::now();
+        let level = pin.is_low();
+        if level != pressed {
+            pressed = level;
+            debouncer.edge(level, now);
+        }
+
+        let events = debouncer.poll(now);
+        KEY_STATS.lock(|stats| {
+            let mut all = stats.get();
+            all[key] = *debouncer.stats();
+            stats.set(all);
+        });
+
+        for event in events {
+            if event.kind == KeyEventKind::Stuck {
+                warn!(
+                    "{} is stuck, ignoring it until released",
+                    KEY_NAMES[event.key]
+                );
+            } else {
+                info!("{} {:?}", KEY_NAMES[event.key], event.kind);
+            }
+            KEY_EVENTS.send(event).await;
+        }
+    }
+}
+
+/// Turn raw key events into button events, recognising chords
+#[embassy_executor::task]
+pub async fn chord_task(registry: ChordRegistry) {
+    let publisher = BUTTON_EVENTS
+        .publisher()
+        .expect("too many button event publishers");
+    let mut detector = ChordDetector::new(registry);
+
+    loop {
+        let events = match detector.next_deadline() {
+            Some(deadline) => match select(KEY_EVENTS.receive(), Timer::at(deadline)).await {
+                Either::First(event) => detector.event(event),
+                Either::Second(()) => detector.poll(Instant::now()),
+            },
+            None => detector.event(KEY_EVENTS.receive().await),
+        };
+
+        for event in events {
+            if let ButtonEvent::Chord(chord) = event {
+                info!("chord {:?}", chord.id);
+            }
+            publisher.publish(event).await;
+        }
+    }
+}
@@ -0,0 +1,24 @@
+//! Wall clock
+//! Time of day for quiet hours, set over the console until the backend sets it
+
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::Instant;
+use izzymonitor_core::clock::{TimeOfDay, WallClock};
+
+/// The clock, unknown until set after every boot
+static CLOCK: Mutex<CriticalSectionRawMutex, Cell<Option<WallClock>>> = Mutex::new(Cell::new(None));
+
+/// Set the time of day
+pub fn set(time: TimeOfDay) {
+    CLOCK.lock(|c| c.set(Some(WallClock::new(time, Instant::now()))));
+}
+
+/// Time of day now, `None` if it was never set
+pub fn now() -> Option<TimeOfDay> {
+    CLOCK
+        .lock(Cell::get)
+        .map(|clock| clock.time_at(Instant::now()))
+}
@@ -0,0 +1,230 @@
+//! Serial console
+//! Line commands over the USB serial port for field diagnostics
+
+use embedded_io_async::Read;
+use esp_hal::usb_serial_jtag::UsbSerialJtagRx;
+use esp_hal::Async;
+use esp_println::{print, println};
+use heapless::String;
+use izzymonitor_core::clock::TimeOfDay;
+use izzymonitor_core::leds::format::MAX_STRIP_LEDS;
+use izzymonitor_core::leds::pattern::{PatternCode, MAX_PATTERN_SIZE};
+use izzymonitor_core::leds::power::{MAX_LIMIT_MA, MIN_LIMIT_MA};
+use izzymonitor_core::leds::{
+    Notification, PixelFormat, QuietHours, RgbColor, StoredPattern, StripConfig,
+};
+use izzymonitor_core::menu::MAX_TRIP_TEXT;
+use log::error;
+
+use crate::buttons::{self, KEY_NAMES};
+use crate::{ambient, clock, display, leds, storage};
+
+/// Longest command line, longer input is dropped. Room for `pattern put`
+/// with a full pattern in hex.
+const LINE_LENGTH: usize = 48 + 2 * MAX_PATTERN_SIZE;
+
+/// Read commands from the serial port and answer them
+#[embassy_executor::task]
+pub async fn console_task(mut rx: UsbSerialJtagRx<'static, Async>) {
+    let mut line: String<LINE_LENGTH> = String::new();
+    let mut overflow = false;
+    let mut buf = [0u8; 16];
+
+    loop {
+        let len = match rx.read(&mut buf).await {
+            Ok(len) => len,
+            Err(e) => {
+                error!("Console read error: {:?}", e);
+                continue;
+            }
+        };
+
+        for &byte in &buf[..len] {
+            match byte {
+                b'\r' | b'\n' => {
+                    if !overflow {
+                        run(line.trim());
+                    }
+                    line.clear();
+                    overflow = false;
+                }
+                _ => overflow |= line.push(char::from(byte)).is_err(),
+            }
+        }
+    }
+}
+
+fn run(line: &str) {
+    let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+    match (command, arg.trim()) {
+        ("", _) => {}
+        ("keys", _) => {
+            for (key, stats) in buttons::key_stats().iter().enumerate() {
+                println!("{}: {}", KEY_NAMES[key], stats);
+            }
+        }
+        ("power", "") => {
+            let (keys, strip) = leds::power_report();
+            println!("LEDs: {}", keys);
+            if let Some(strip) = strip {
+                println!("Strip: {}", strip);
+            }
+        }
+        ("power", limit) => match limit.parse::<u16>() {
+            Ok(limit) if (MIN_LIMIT_MA..=MAX_LIMIT_MA).contains(&limit) => {
+                leds::set_limit_ma(limit);
+                if let Err(e) = storage::update(|config| config.led_limit_ma = limit) {
+                    error!("Config save error: {}", e);
+                }
+                println!("LED budget {} mA", limit);
+            }
+            _ => println!("LED budget is {} to {} mA", MIN_LIMIT_MA, MAX_LIMIT_MA),
+        },
+        ("strip", "") => {
+            let strip = storage::current().strip;
+            println!("Strip: {} LEDs, {}", strip.len, strip.format.name());
+        }
+        ("strip", arg) => match parse_strip(arg) {
+            Some(strip) => {
+                if let Err(e) = storage::update(|config| config.strip = strip) {
+                    error!("Config save error: {}", e);
+                }
+                println!("Strip saved, restart to apply");
+            }
+            None => println!(
+                "usage: strip <grb|rgb|grbw|rgbw|apa102> <0-{}>",
+                MAX_STRIP_LEDS
+            ),
+        },
+        ("pattern", arg) => pattern(arg),
+        ("notify", arg) => notify(arg),
+        ("quiet", "") => println!("Quiet hours: {}", storage::current().quiet_hours),
+        ("quiet", arg) => match QuietHours::parse(arg) {
+            Some(quiet_hours) => {
+                if let Err(e) = storage::update(|config| config.quiet_hours = quiet_hours) {
+                    error!("Config save error: {}", e);
+                }
+                println!("Quiet hours: {}", quiet_hours);
+            }
+            None => println!("usage: quiet <HH:MM-HH:MM|off>"),
+        },
+        ("light", _) => {
+            let (lux, levels) = ambient::state();
+            match lux {
+                Some(lux) => print!("{} lux, ", lux as u32),
+                None => print!("No light sensor, "),
+            }
+            println!(
+                "{}: backlight {}, LEDs {}",
+                storage::current().brightness,
+                levels.backlight,
+                levels.leds
+            );
+        }
+        ("time", "") => match clock::now() {
+            Some(time) => println!("{}", time),
+            None => println!("Clock not set"),
+        },
+        ("time", arg) => match TimeOfDay::parse(arg) {
+            Some(time) => clock::set(time),
+            None => println!("usage: time [HH:MM]"),
+        },
+        ("trip", "off") => display::set_trip_text(String::new()),
+        ("trip", "") => println!("usage: trip <text|off>, \\n starts a new line"),
+        ("trip", text) => display::set_trip_text(trip_text(text)),
+        ("accent", "off") => display::set_accent(None),
+        ("accent", hex) => match parse_hex(hex).as_deref() {
+            Some(&[r, g, b]) => display::set_accent(Some(RgbColor::new(r, g, b))),
+            _ => println!("usage: accent <rrggbb|off>"),
+        },
+        _ => println!(
+            "commands: keys, power [mA], strip [format leds], pattern, notify, quiet, time, light, accent, trip"
+        ),
+    }
+}
+
+fn pattern(arg: &str) {
+    let (action, arg) = arg.split_once(' ').unwrap_or((arg, ""));
+    match (action, arg.trim()) {
+        ("", _) => {
+            for name in storage::pattern_names() {
+                println!("{}", name);
+            }
+        }
+        ("put", arg) => {
+            let (name, hex) = arg.split_once(' ').unwrap_or((arg, ""));
+            let Some(code) = parse_hex(hex.trim()) else {
+                println!("usage: pattern put <name> <hex>");
+                return;
+            };
+            match StoredPattern::new(name, &code).and_then(|p| storage::store_pattern(&p)) {
+                Ok(()) => println!("Pattern {} stored", name),
+                Err(e) => println!("Pattern rejected: {}", e),
+            }
+        }
+        ("play", name) => match storage::find_pattern(name) {
+            Some(pattern) => leds::play_pattern(Some(pattern.code)),
+            None => println!("No pattern {}", name),
+        },
+        ("stop", _) => leds::play_pattern(None),
+        ("rm", name) => match storage::remove_pattern(name) {
+            Ok(true) => println!("Pattern {} removed", name),
+            Ok(false) => println!("No pattern {}", name),
+            Err(e) => error!("Pattern remove error: {}", e),
+        },
+        _ => println!("pattern [put <name> <hex> | play <name> | stop | rm <name>]"),
+    }
+}
+
+fn notify(arg: &str) {
+    let (name, off) = arg.split_once(' ').unwrap_or((arg, ""));
+    match (Notification::from_name(name), off.trim()) {
+        (Some(notification), "") => leds::notify(notification),
+        (Some(notification), "off") => leds::clear_notification(notification),
+        _ => {
+            print!("usage: notify <");
+            for (i, notification) in Notification::ALL.iter().enumerate() {
+                print!("{}{}", if i == 0 { "" } else { "|" }, notification.name());
+            }
+            println!("> [off]");
+        }
+    }
+}
+
+/// Bytes from a hex string, `None` if it isn't hex or is too long
+fn parse_hex(hex: &str) -> Option<PatternCode> {
+    if hex.is_empty() || hex.len() % 2 != 0 {
+        return None;
+    }
+    let mut code = PatternCode::new();
+    for pair in hex.as_bytes().chunks(2) {
+        let pair = core::str::from_utf8(pair).ok()?;
+        code.push(u8::from_str_radix(pair, 16).ok()?).ok()?;
+    }
+    Some(code)
+}
+
+/// A route description from the console, `\n` for a line break, cut to fit
+fn trip_text(text: &str) -> String<MAX_TRIP_TEXT> {
+    let mut out = String::new();
+    for (i, part) in text.split("\\n").enumerate() {
+        let newline = (i > 0).then_some('\n');
+        for c in newline.into_iter().chain(part.chars()) {
+            if out.push(c).is_err() {
+                return out;
+            }
+        }
+    }
+    out
+}
+
+fn parse_strip(arg: &str) -> Option<StripConfig> {
+    let (format, len) = arg.split_once(' ')?;
+    let format = PixelFormat::from_name(format)?;
+    let len = len
+        .trim()
+        .parse::<u8>()
+        .ok()
+        .filter(|&len| len <= MAX_STRIP_LEDS)?;
+    Some(StripConfig { format, len })
+}
@@ -0,0 +1,247 @@
+//! Display module for ST7735S 1.8" LCD
+//! Brings up the panel and runs the menu on it
+//!
+//! Screens are drawn into a frame in RAM, and every flush sends the panel
+//! only the tiles that changed.
+
+use embassy_futures::select::{select4, Either4};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer};
+use embedded_hal_bus::spi::{ExclusiveDevice, NoDelay};
+use esp_hal::delay::Delay;
+use esp_hal::gpio::Output;
+use esp_hal::reset::software_reset;
+use esp_hal::spi::master::Spi;
+use esp_hal::Blocking;
+use heapless::String;
+use izzymonitor_core::display::{screen_wipe, Frame, PartialRedraw, SCREEN_HEIGHT, SCREEN_WIDTH};
+use izzymonitor_core::leds::RgbColor;
+use izzymonitor_core::menu::{Menu, MenuScreen, Redraw, MAX_TRIP_TEXT};
+use izzymonitor_core::KEY_COUNT;
+use log::{error, warn};
+use st7735_lcd::{Orientation, ST7735};
+use static_cell::ConstStaticCell;
+
+use crate::buttons::{self, ButtonEvent, ChordId, KeyEventKind, RepeatCurve};
+use crate::storage::{self, Config};
+use crate::{ambient, leds};
+
+/// The ST7735 on the panel SPI bus
+pub type Lcd = ST7735<
+    ExclusiveDevice<Spi<'static, Blocking>, Output<'static>, NoDelay>,
+    Output<'static>,
+    Output<'static>,
+>;
+
+/// The display driver
+pub type Display = izzymonitor_core::display::Display<PartialRedraw<'static, Lcd>>;
+
+/// The screen as drawn, 40 KiB, too big for the task arena
+static FRAME: ConstStaticCell<Frame> = ConstStaticCell::new(Frame::new());
+
+/// Accent color of the agent personality, for the display task
+static ACCENT: Signal<CriticalSectionRawMutex, Option<RgbColor>> = Signal::new();
+
+/// Take on an agent personality's accent color, `None` for the theme's own
+pub fn set_accent(accent: Option<RgbColor>) {
+    ACCENT.signal(accent);
+}
+
+/// Route description for the Trip screen, for the display task
+static TRIP_TEXT: Signal<CriticalSectionRawMutex, String<MAX_TRIP_TEXT>> = Signal::new();
+
+/// Show a route description on the Trip screen, empty for none
+pub fn set_trip_text(text: String<MAX_TRIP_TEXT>) {
+    TRIP_TEXT.signal(text);
+}
+
+/// Initialize the display
+pub fn init(
+    spi: Spi<'static, Blocking>,
+    cs: Output<'static>,
+    dc: Output<'static>,
+    rst: Output<'static>,
+) -> Result<Display, &'static str> {
+    let device =
+        ExclusiveDevice::new_no_delay(spi, cs).map_err(|_| "Failed to set up SPI device")?;
+
+    // Create ST7735 display driver
+    let mut st7735 = ST7735::new(device, dc, rst, true, false, SCREEN_WIDTH, SCREEN_HEIGHT);
+
+    st7735
+        .init(&mut Delay::new())
+        .map_err(|_| "Failed to initialize display")?;
+
+    // Set display orientation - landscape mode
+    st7735
+        .set_orientation(&Orientation::Landscape)
+        .map_err(|_| "Failed to set display orientation")?;
+
+    let mut display = Display::new(PartialRedraw::new(st7735, FRAME.take()));
+    display.clear()?;
+    display.flush()?;
+    Ok(display)
+}
+
+/// Time between wipe steps, a slow SPI bus just means bigger steps
+const WIPE_FRAME: Duration = Duration::from_millis(16);
+
+/// How often the diagnostics screen redraws its counters
+const STATS_REFRESH: Duration = Duration::from_millis(500);
+
+/// Uncover the menu's new screen left to right, at the same speed whatever
+/// the frame rate
+///
+/// The screen is drawn once and every step sends the changed tiles the wipe
+/// has reached, so each goes over SPI once.
+async fn wipe_to(lcd: &mut Display, menu: &Menu) -> Result<(), &'static str> {
+    lcd.draw_menu(menu)?;
+    let wipe = screen_wipe(Instant::now());
+    loop {
+        let now = Instant::now();
+        lcd.flush_wipe(wipe.value_at(now).unwrap_or(SCREEN_WIDTH as i32))?;
+        if wipe.is_finished(now) {
+            return Ok(());
+        }
+        Timer::after(WIPE_FRAME).await;
+    }
+}
+
+/// Erase the settings and patterns and start over with the defaults
+fn factory_reset() {
+    warn!("Factory reset");
+    if let Err(e) = storage::erase() {
+        error!("Factory reset failed: {}", e);
+        return;
+    }
+    software_reset();
+}
+
+/// Task for display management
+#[embassy_executor::task]
+pub async fn display_task(mut lcd: Display, config: Config) {
+    // Start with the startup screen
+    let mut menu = Menu::with_keymap(config.keymap)
+        .with_calibration(config.calibration)
+        .with_brightness(config.brightness)
+        .with_theme(config.theme);
+    if let Err(e) = lcd.draw_menu(&menu).and_then(|()| lcd.flush()) {
+        error!("Display error: {}", e);
+    }
+
+    // Wait a moment on the startup screen, keys pressed meanwhile are ignored
+    Timer::after(Duration::from_millis(2000)).await;
+    let mut events = buttons::subscribe();
+    let mut redraw = menu.finish_startup();
+
+    loop {
+        // However the diagnostics screen comes to be drawn, it shows the
+        // counters as they are now
+        let diagnostics = menu.screen() == MenuScreen::Diagnostics;
+        if diagnostics && redraw.is_full() {
+            menu.set_key_stats(buttons::key_stats());
+        }
+
+        let result = match redraw {
+            Redraw::None => Ok(()),
+            Redraw::Buttons => lcd.draw_buttons(menu.layout()),
+            // Edits on a screen only send the tiles they change
+            Redraw::Content => lcd.draw_menu(&menu),
+            Redraw::Screen => wipe_to(&mut lcd, &menu).await,
+        }
+        .and_then(|()| lcd.flush());
+        if let Err(e) = result {
+            error!("Display error: {}", e);
+        }
+
+        if redraw.is_full() {
+            leds::KEY_HINTS.signal(menu.hint_colors());
+            leds::show_test_color(menu.test_color());
+            // Brightness steps, on Settings or by chord, show as they are made
+            ambient::set_mode(menu.brightness());
+            // Calibration edits show up on the keys as they are made
+            if menu.screen() == MenuScreen::Calibration {
+                leds::set_calibration(*menu.calibration());
+            }
+        }
+
+        // Each screen scrolls at its own pace, on whichever keys are Up/Down
+        if redraw == Redraw::Screen {
+            for key in 0..KEY_COUNT {
+                let curve = if menu.action(key).is_scroll() {
+                    menu.screen().repeat_curve()
+                } else {
+                    RepeatCurve::default()
+                };
+                buttons::set_repeat_curve(key, curve);
+            }
+        }
+
+        // The diagnostics screen keeps its counters live
+        let refresh = async {
+            if diagnostics {
+                Timer::after(STATS_REFRESH).await
+            } else {
+                core::future::pending().await
+            }
+        };
+        let event = match select4(
+            buttons::next_event(&mut events),
+            ACCENT.wait(),
+            TRIP_TEXT.wait(),
+            refresh,
+        )
+        .await
+        {
+            Either4::First(event) => event,
+            Either4::Second(accent) => {
+                redraw = menu.set_accent(accent);
+                continue;
+            }
+            Either4::Third(text) => {
+                redraw = menu.set_trip_text(&text);
+                continue;
+            }
+            Either4::Fourth(()) => {
+                redraw = menu.set_key_stats(buttons::key_stats());
+                continue;
+            }
+        };
+
+        // Hand every press to the menu, held Up/Down keep scrolling
+        redraw = match event {
+            ButtonEvent::Key(event) => match event.kind {
+                KeyEventKind::Press => menu.press(event.key),
+                KeyEventKind::Repeat => menu.repeat(event.key),
+                _ => Redraw::None,
+            },
+            ButtonEvent::Chord(chord) if chord.id == ChordId::FactoryReset => {
+                factory_reset();
+                Redraw::None
+            }
+            ButtonEvent::Chord(chord) => menu.chord(chord.id),
+        };
+
+        if let Some(keymap) = menu.take_keymap_change() {
+            if let Err(e) = storage::update(|config| config.keymap = keymap) {
+                error!("Config save error: {}", e);
+            }
+        }
+        if let Some(calibration) = menu.take_calibration_change() {
+            if let Err(e) = storage::update(|config| config.calibration = calibration) {
+                error!("Config save error: {}", e);
+            }
+        }
+        if let Some(brightness) = menu.take_brightness_change() {
+            if let Err(e) = storage::update(|config| config.brightness = brightness) {
+                error!("Config save error: {}", e);
+            }
+        }
+        if let Some(theme) = menu.take_theme_change() {
+            if let Err(e) = storage::update(|config| config.theme = theme) {
+                error!("Config save error: {}", e);
+            }
+        }
+    }
+}
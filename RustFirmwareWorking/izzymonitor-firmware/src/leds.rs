@@ -0,0 +1,385 @@
+//! LED control module
+//! Drives the WS2812B/SK6805 key LEDs through the RMT peripheral
+//!
+//! An optional external strip on the enclosure follows the board-wide
+//! effects, see [`strip`].
+
+use core::cell::Cell;
+
+use embassy_futures::select::{select4, Either4};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::channel::Channel as CommandQueue;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer};
+use esp_hal::rmt::Channel;
+use esp_hal::Blocking;
+use izzymonitor_core::buttons::KeySet;
+use izzymonitor_core::leds::encoding::{buffer_size, encode, BitCodes, END_MARKER};
+use izzymonitor_core::leds::format::MAX_STRIP_LEDS;
+use izzymonitor_core::leds::pattern::PatternCode;
+use izzymonitor_core::leds::{
+    Calibration, Calibrator, Effect, EffectStack, LayerId, LedFrame, LedOutput, Notification,
+    Notifier, Pattern, PixelFormat, PowerBudget, PowerReport, Priority, RgbColor,
+};
+use izzymonitor_core::KEY_COUNT;
+use log::{error, warn};
+use static_cell::ConstStaticCell;
+
+use crate::buttons::{self, ButtonEvent, KeyEventKind};
+use crate::{clock, storage};
+
+pub mod rmt;
+pub mod strip;
+
+use rmt::OneWireTx;
+use strip::StripOutput;
+
+pub use izzymonitor_core::leds::colors;
+
+/// Hint colors of the screen on display, in its theme, the LEDs follow on
+/// every navigation
+pub static KEY_HINTS: Signal<CriticalSectionRawMutex, LedFrame> = Signal::new();
+
+/// Settings changes for the LED task
+enum LedCommand {
+    /// New current budget in mA
+    Limit(u16),
+    /// New color correction
+    Calibration(Calibration),
+    /// Show one color on every key, or go back to the effects
+    TestColor(Option<RgbColor>),
+    /// Play pattern bytecode, or stop the one playing
+    Pattern(Option<PatternCode>),
+    /// Raise or clear a notification
+    Notify(Notification, bool),
+    /// Scale for every color, 255 is full
+    Brightness(u8),
+}
+
+/// Queued settings changes, applied in order
+static LED_COMMANDS: CommandQueue<CriticalSectionRawMutex, LedCommand, 4> = CommandQueue::new();
+
+const NO_REPORT: PowerReport = PowerReport {
+    requested_ma: 0,
+    drawn_ma: 0,
+    limit_ma: 0,
+};
+
+/// Estimated draw of the last frame on the keys and on the strip, for diagnostics
+static POWER_REPORT: Mutex<CriticalSectionRawMutex, Cell<(PowerReport, Option<PowerReport>)>> =
+    Mutex::new(Cell::new((NO_REPORT, None)));
+
+/// Estimated current draw of the frame on the key LEDs and on the strip, if any
+pub fn power_report() -> (PowerReport, Option<PowerReport>) {
+    POWER_REPORT.lock(Cell::get)
+}
+
+/// Change the LED current budget, the next frame is held to it
+pub fn set_limit_ma(limit_ma: u16) {
+    send(LedCommand::Limit(limit_ma));
+}
+
+/// Change the color correction, the next frame uses it
+pub fn set_calibration(calibration: Calibration) {
+    send(LedCommand::Calibration(calibration));
+}
+
+/// Light every key in one color over all effects, `None` to stop
+pub fn show_test_color(color: Option<RgbColor>) {
+    send(LedCommand::TestColor(color));
+}
+
+/// Play an uploaded pattern until its duration cap, `None` to stop it
+///
+/// The bytecode is checked again before it plays, a bad pattern is logged
+/// and dropped.
+pub fn play_pattern(code: Option<PatternCode>) {
+    send(LedCommand::Pattern(code));
+}
+
+/// Scale every LED color by `level / 255`, the ambient light sets this
+pub fn set_brightness(level: u8) {
+    send(LedCommand::Brightness(level));
+}
+
+/// Start showing a notification, see [`Notifier`] for what each looks like
+pub fn notify(notification: Notification) {
+    send(LedCommand::Notify(notification, true));
+}
+
+/// Stop showing a notification because what it was about is over
+pub fn clear_notification(notification: Notification) {
+    send(LedCommand::Notify(notification, false));
+}
+
+/// Whether notification lights should stay dark now, never while the clock is unset
+fn is_quiet() -> bool {
+    let quiet_hours = storage::current().quiet_hours;
+    clock::now().is_some_and(|time| quiet_hours.contains(time))
+}
+
+fn send(command: LedCommand) {
+    if LED_COMMANDS.try_send(command).is_err() {
+        warn!("LED command queue full, dropping a settings change");
+    }
+}
+
+/// RMT source clock, one tick is 12.5 ns with a clock divider of 1
+pub const RMT_CLOCK_MHZ: u32 = 80;
+
+/// Longest chain a controller drives
+pub const MAX_LEDS: usize = MAX_STRIP_LEDS as usize;
+
+/// Pulse codes of two key LED frames, any format, with their end markers
+pub static KEY_CODES: ConstStaticCell<[u32; 2 * buffer_size(KEY_COUNT)]> =
+    ConstStaticCell::new([END_MARKER; 2 * buffer_size(KEY_COUNT)]);
+/// Pulse codes of two strip frames, 15 KiB, too big for the task arena
+pub static STRIP_CODES: ConstStaticCell<[u32; 2 * buffer_size(MAX_LEDS)]> =
+    ConstStaticCell::new([END_MARKER; 2 * buffer_size(MAX_LEDS)]);
+
+/// The RMT channel the key LEDs hang off
+pub type LedChannel = Channel<Blocking, 0>;
+/// Controller of the key LEDs
+pub type KeyLeds = LedController<0>;
+
+/// LED controller for one-wire WS2812B/SK6812 style LEDs on an RMT channel
+///
+/// A frame is encoded whole while the one before is still going out, and
+/// sent from the RMT interrupt, see [`rmt`]. `show` returns once its frame
+/// has started, 30 µs per RGB LED and 40 µs per RGBW one before it is done.
+pub struct LedController<const CH: u8> {
+    tx: OneWireTx<CH>,
+    format: PixelFormat,
+    bits: BitCodes,
+    calibrator: Calibrator,
+    budget: PowerBudget,
+    report: PowerReport,
+}
+
+impl<const CH: u8> LedController<CH> {
+    /// Create a new LED controller with a color correction and a current budget
+    ///
+    /// `codes` holds two encoded frames, LEDs past what fits in half of it
+    /// stay dark.
+    pub fn new(
+        channel: Channel<Blocking, CH>,
+        codes: &'static mut [u32],
+        format: PixelFormat,
+        calibration: &Calibration,
+        budget: PowerBudget,
+    ) -> Result<Self, &'static str> {
+        let bits =
+            BitCodes::for_format(format, RMT_CLOCK_MHZ).ok_or("Clocked LEDs need an SPI chain")?;
+        Ok(Self {
+            tx: OneWireTx::new(channel, codes)?,
+            format,
+            bits,
+            calibrator: Calibrator::new(calibration),
+            budget,
+            report: PowerReport::default(),
+        })
+    }
+
+    /// Change the color correction
+    pub fn set_calibration(&mut self, calibration: &Calibration) {
+        self.calibrator = Calibrator::new(calibration);
+    }
+
+    /// Change the current budget
+    pub fn set_limit_ma(&mut self, limit_ma: u16) {
+        self.budget.set_limit_ma(limit_ma);
+    }
+
+    /// Estimated draw of the last frame shown
+    pub fn report(&self) -> PowerReport {
+        self.report
+    }
+}
+
+impl<const CH: u8> LedOutput for LedController<CH> {
+    type Error = &'static str;
+
+    /// Send a frame to the LEDs, colors past [`MAX_LEDS`] are ignored
+    async fn show(&mut self, colors: &[RgbColor]) -> Result<(), &'static str> {
+        let len = colors.len().min(MAX_LEDS);
+        let mut frame = [RgbColor::default(); MAX_LEDS];
+        frame[..len].copy_from_slice(&colors[..len]);
+        let frame = &mut frame[..len];
+
+        // Correct for the LED and keycap first, the budget sees what is sent
+        self.calibrator.apply(frame);
+        // Dim frames that would pull more than the supply can give
+        self.report = self.budget.apply(frame);
+
+        let len = encode(frame, self.format, self.bits, self.tx.next_buffer());
+        if len <= 1 {
+            return Ok(());
+        }
+        self.tx.send(len).await
+    }
+}
+
+/// Time between frames while an effect is animating
+const FRAME_PERIOD: Duration = Duration::from_millis(20);
+/// How long the startup rainbow runs
+const STARTUP_RAINBOW: Duration = Duration::from_millis(2000);
+/// Time between wake-ups with nothing moving, to follow quiet hours
+const IDLE_PERIOD: Duration = Duration::from_secs(60);
+
+/// Task running the LED effects: screen hints, white on pressed keys and
+/// notifications
+#[embassy_executor::task]
+pub async fn led_animation_task(mut controller: KeyLeds, mut strip: Option<StripOutput>) {
+    let mut events = buttons::subscribe();
+    let mut effects = EffectStack::new();
+    let mut notifier = Notifier::new(storage::current().keymap);
+    let mut pressed: [Option<LayerId>; KEY_COUNT] = [None; KEY_COUNT];
+    let mut test_color: Option<LayerId> = None;
+    let mut brightness = u8::MAX;
+
+    let rainbow = Effect::Rainbow {
+        period: STARTUP_RAINBOW,
+    };
+    let now = Instant::now();
+    if let Err(e) = effects.push_for(
+        Priority::Notification,
+        KeySet::ALL,
+        rainbow,
+        now,
+        STARTUP_RAINBOW,
+    ) {
+        error!("LED effect error: {}", e);
+    }
+
+    loop {
+        let now = Instant::now();
+        effects.retire(now);
+        if let Err(e) = notifier.apply(&mut effects, is_quiet(), now) {
+            error!("LED effect error: {}", e);
+        }
+        let frame = effects.render(now).map(|color| color.scale(brightness));
+        if let Err(e) = controller.show(&frame).await {
+            error!("LED update error: {}", e);
+        }
+        let report = controller.report();
+
+        let strip_report = match strip.as_mut() {
+            Some(strip) => {
+                let mut chain = [colors::OFF; MAX_LEDS];
+                let chain = &mut chain[..strip.leds()];
+                effects.render_chain(now, chain);
+                for color in chain.iter_mut() {
+                    *color = color.scale(brightness);
+                }
+                // The strip gets what the keys leave of the budget
+                let left = u32::from(report.limit_ma).saturating_sub(report.drawn_ma);
+                strip.set_limit_ma(left as u16);
+                if let Err(e) = strip.show(chain).await {
+                    error!("LED strip error: {}", e);
+                }
+                Some(strip.report())
+            }
+            None => None,
+        };
+        POWER_REPORT.lock(|r| r.set((report, strip_report)));
+
+        // Tick while something moves, otherwise just look at the clock now and then
+        let tick = async {
+            if effects.is_animated() {
+                Timer::after(FRAME_PERIOD).await
+            } else {
+                Timer::after(IDLE_PERIOD).await
+            }
+        };
+
+        let event = match select4(
+            buttons::next_event(&mut events),
+            KEY_HINTS.wait(),
+            LED_COMMANDS.receive(),
+            tick,
+        )
+        .await
+        {
+            Either4::First(ButtonEvent::Key(event)) => event,
+            Either4::Second(hints) => {
+                show_hints(&mut effects, &hints);
+                // The key map may have changed with the screen
+                notifier.set_keymap(storage::current().keymap);
+                continue;
+            }
+            Either4::Third(command) => {
+                match command {
+                    LedCommand::Limit(limit_ma) => controller.set_limit_ma(limit_ma),
+                    LedCommand::Calibration(calibration) => {
+                        controller.set_calibration(&calibration)
+                    }
+                    LedCommand::TestColor(color) => {
+                        if let Some(id) = test_color.take() {
+                            effects.remove(id);
+                        }
+                        if let Some(color) = color {
+                            let solid = Effect::Solid(color);
+                            let now = Instant::now();
+                            match effects.push(Priority::Notification, KeySet::ALL, solid, now) {
+                                Ok(id) => test_color = Some(id),
+                                Err(e) => error!("LED effect error: {}", e),
+                            }
+                        }
+                    }
+                    LedCommand::Pattern(Some(code)) => match Pattern::decode(&code) {
+                        // Agent patterns answer the user, so they sit with key feedback
+                        Ok(pattern) => {
+                            let now = Instant::now();
+                            if let Err(e) = effects.play(Priority::Feedback, pattern, now) {
+                                error!("LED effect error: {}", e);
+                            }
+                        }
+                        Err(e) => error!("LED pattern error: {}", e),
+                    },
+                    LedCommand::Pattern(None) => {
+                        effects.stop_pattern();
+                    }
+                    LedCommand::Notify(notification, true) => notifier.raise(notification),
+                    LedCommand::Notify(notification, false) => notifier.clear(notification),
+                    LedCommand::Brightness(level) => brightness = level,
+                }
+                continue;
+            }
+            _ => continue,
+        };
+
+        match event.kind {
+            KeyEventKind::Press if notifier.acknowledge(event.key, is_quiet()) => {}
+            KeyEventKind::Press => {
+                let keys = KeySet::of(&[event.key]);
+                let white = Effect::Solid(colors::WHITE);
+                match effects.push(Priority::Feedback, keys, white, event.at) {
+                    Ok(id) => pressed[event.key] = Some(id),
+                    Err(e) => error!("LED effect error: {}", e),
+                }
+            }
+            KeyEventKind::Release => {
+                if let Some(id) = pressed[event.key].take() {
+                    effects.remove(id);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Replace the hint layers, keys without a hint stay dark
+fn show_hints(effects: &mut EffectStack, hints: &LedFrame) {
+    effects.clear(Priority::Hint);
+    let now = Instant::now();
+    for (key, &color) in hints.iter().enumerate() {
+        if color == colors::OFF {
+            continue;
+        }
+        let keys = KeySet::of(&[key]);
+        if let Err(e) = effects.push(Priority::Hint, keys, Effect::Solid(color), now) {
+            error!("LED effect error: {}", e);
+        }
+    }
+}
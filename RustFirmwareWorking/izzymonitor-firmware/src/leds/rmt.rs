@@ -0,0 +1,254 @@
+//! One-wire LED transmitter
+//! Feeds pulse codes to an RMT channel from the RMT interrupt
+//!
+//! A channel's memory block holds 48 codes, far less than a frame. The block
+//! is filled up front and sent in wrap mode: every time the hardware is
+//! through half of it, the threshold interrupt refills that half from the
+//! frame buffer while the other half goes out. A refill has 30 µs before the
+//! line would stall, so a chain of any length goes out without a gap and the
+//! executor keeps running. The end of the frame is signalled from the same
+//! interrupt.
+//!
+//! esp-hal sets the channel up for its pin and clock, the transmitter then
+//! drives the channel's registers itself.
+
+use core::cell::Cell;
+use core::ops::Deref;
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{with_timeout, Duration, Instant, Timer};
+use esp_hal::handler;
+use esp_hal::interrupt::{self, Priority};
+use esp_hal::peripherals::{Interrupt, RMT};
+use esp_hal::rmt::Channel;
+use esp_hal::Blocking;
+
+/// TX channels of the ESP32-S3
+const CHANNELS: usize = 4;
+/// Codes in a channel's memory block
+const BLOCK_SIZE: usize = 48;
+/// Codes the interrupt refills at a time
+const HALF_BLOCK: usize = BLOCK_SIZE / 2;
+/// Channel memory as the CPU sees it
+const RAM_START: usize = 0x6001_6800;
+
+/// Low time after a frame before the LEDs latch it, WS2812B V5 needs 280 µs
+const RESET_TIME: Duration = Duration::from_micros(300);
+
+type Registers = <RMT as Deref>::Target;
+
+/// A frame going out on a channel
+#[derive(Clone, Copy)]
+struct Frame {
+    codes: *const u32,
+    len: usize,
+    // First code not yet in the channel memory
+    next: usize,
+}
+
+// SAFETY: the pointer is into a `'static` buffer the transmitter doesn't
+// touch until the frame is done, only the interrupt reads through it
+unsafe impl Send for Frame {}
+
+/// Frame on the wire of each channel, for the refills
+static FRAMES: [Mutex<CriticalSectionRawMutex, Cell<Option<Frame>>>; CHANNELS] =
+    [const { Mutex::new(Cell::new(None)) }; CHANNELS];
+
+/// When each channel's frame ended, or that it failed
+static FINISHED: [Signal<CriticalSectionRawMutex, Result<Instant, ()>>; CHANNELS] =
+    [const { Signal::new() }; CHANNELS];
+
+/// Sends frames of pulse codes on RMT channel `CH`, the next one is encoded
+/// while the last one is on the wire
+pub struct OneWireTx<const CH: u8> {
+    // Keeps the pin and clock setup, the registers are driven directly
+    _channel: Channel<Blocking, CH>,
+    buffers: [&'static mut [u32]; 2],
+    // The buffer the next frame goes in, the other one may be on the wire
+    next: usize,
+    // Codes on the wire, until the frame is seen to end
+    sending: Option<usize>,
+}
+
+impl<const CH: u8> OneWireTx<CH> {
+    /// Take over a channel configured for the LED pin, `codes` is split into
+    /// two frame buffers
+    pub fn new(
+        channel: Channel<Blocking, CH>,
+        codes: &'static mut [u32],
+    ) -> Result<Self, &'static str> {
+        const { assert!((CH as usize) < CHANNELS, "not a TX channel") };
+        // SAFETY: the handler only touches channels that have a frame to send
+        unsafe { interrupt::bind_interrupt(Interrupt::RMT, on_rmt.handler()) };
+        interrupt::enable(Interrupt::RMT, on_rmt.priority())
+            .map_err(|_| "Failed to enable the RMT interrupt")?;
+
+        let (front, back) = codes.split_at_mut(codes.len() / 2);
+        Ok(Self {
+            _channel: channel,
+            buffers: [front, back],
+            next: 0,
+            sending: None,
+        })
+    }
+
+    /// Buffer to encode the next frame into, never the one on the wire
+    pub fn next_buffer(&mut self) -> &mut [u32] {
+        self.buffers[self.next]
+    }
+
+    /// Start sending the first `len` codes of [`next_buffer`](Self::next_buffer)
+    /// once the frame before has latched
+    ///
+    /// Returns when the frame has started. An error is about the frame
+    /// before, which failed or never ended; the channel is stopped and this
+    /// frame is sent all the same.
+    pub async fn send(&mut self, len: usize) -> Result<(), &'static str> {
+        let result = self.finish().await;
+        let codes = &self.buffers[self.next][..len];
+        start(CH, codes);
+        self.sending = Some(len);
+        self.next ^= 1;
+        result
+    }
+
+    /// Wait for the frame on the wire to end and latch
+    async fn finish(&mut self) -> Result<(), &'static str> {
+        let Some(len) = self.sending.take() else {
+            return Ok(());
+        };
+        // A bit takes 1.25 µs, a frame that runs past twice that is stuck
+        let timeout = Duration::from_micros(len as u64 * 5 / 2) + Duration::from_millis(1);
+        match with_timeout(timeout, FINISHED[usize::from(CH)].wait()).await {
+            Ok(Ok(end)) => {
+                Timer::at(end + RESET_TIME).await;
+                Ok(())
+            }
+            Ok(Err(())) => {
+                stop(CH);
+                Err("Failed to transmit LED data")
+            }
+            Err(_) => {
+                stop(CH);
+                Err("LED transmission timed out")
+            }
+        }
+    }
+}
+
+fn registers() -> &'static Registers {
+    // SAFETY: the RMT registers live as long as the chip, each channel's
+    // are only written by its transmitter and the interrupt
+    unsafe { &*RMT::PTR }
+}
+
+/// Memory block of a channel
+fn block(ch: u8) -> *mut u32 {
+    (RAM_START + usize::from(ch) * BLOCK_SIZE * 4) as *mut u32
+}
+
+/// Fill the channel memory with the start of a frame and send it
+fn start(ch: u8, codes: &[u32]) {
+    let rmt = registers();
+    let first = codes.len().min(BLOCK_SIZE);
+    // The interrupt enables are shared by all channels, and a refill must
+    // not come before the frame is in place
+    FRAMES[usize::from(ch)].lock(|frame| {
+        for (i, &code) in codes[..first].iter().enumerate() {
+            // SAFETY: within the channel's block, which is idle
+            unsafe { block(ch).add(i).write_volatile(code) };
+        }
+        frame.set(Some(Frame {
+            codes: codes.as_ptr(),
+            len: codes.len(),
+            next: first,
+        }));
+        FINISHED[usize::from(ch)].reset();
+
+        rmt.int_clr().write(|w| {
+            w.ch_tx_end(ch).set_bit();
+            w.ch_tx_err(ch).set_bit();
+            w.ch_tx_thr_event(ch).set_bit()
+        });
+        rmt.int_ena().modify(|_, w| {
+            w.ch_tx_end(ch).set_bit();
+            w.ch_tx_err(ch).set_bit();
+            w.ch_tx_thr_event(ch).set_bit()
+        });
+        rmt.ch_tx_lim(ch)
+            .modify(|_, w| unsafe { w.tx_lim().bits(HALF_BLOCK as u16) });
+        rmt.ch_tx_conf0(ch).modify(|_, w| {
+            w.mem_tx_wrap_en().set_bit();
+            w.tx_conti_mode().clear_bit();
+            w.conf_update().set_bit()
+        });
+        rmt.ch_tx_conf0(ch).modify(|_, w| {
+            w.mem_rd_rst().set_bit();
+            w.apb_mem_rst().set_bit();
+            w.tx_start().set_bit()
+        });
+        rmt.ch_tx_conf0(ch).modify(|_, w| w.conf_update().set_bit());
+    });
+}
+
+/// Stop a channel and forget its frame, the next one starts afresh
+fn stop(ch: u8) {
+    let rmt = registers();
+    FRAMES[usize::from(ch)].lock(|frame| {
+        rmt.int_ena().modify(|_, w| {
+            w.ch_tx_end(ch).clear_bit();
+            w.ch_tx_err(ch).clear_bit();
+            w.ch_tx_thr_event(ch).clear_bit()
+        });
+        rmt.int_clr().write(|w| {
+            w.ch_tx_end(ch).set_bit();
+            w.ch_tx_err(ch).set_bit();
+            w.ch_tx_thr_event(ch).set_bit()
+        });
+        rmt.ch_tx_conf0(ch).modify(|_, w| w.tx_stop().set_bit());
+        rmt.ch_tx_conf0(ch).modify(|_, w| w.conf_update().set_bit());
+        frame.set(None);
+    });
+}
+
+/// Copy the next half block of a frame over the half the hardware is done with
+fn refill(ch: u8) {
+    FRAMES[usize::from(ch)].lock(|frame| {
+        let Some(mut sending) = frame.get() else {
+            return;
+        };
+        // The first refill goes at the start of the block, then they alternate
+        let offset = sending.next / HALF_BLOCK % 2 * HALF_BLOCK;
+        let count = (sending.len - sending.next).min(HALF_BLOCK);
+        // SAFETY: the buffer stays put and unwritten until the frame is done
+        let codes = unsafe { core::slice::from_raw_parts(sending.codes, sending.len) };
+        for (i, &code) in codes[sending.next..][..count].iter().enumerate() {
+            // SAFETY: within the half of the block the hardware has sent
+            unsafe { block(ch).add(offset + i).write_volatile(code) };
+        }
+        sending.next += count;
+        frame.set(Some(sending));
+    });
+}
+
+/// Refills channel memory and ends frames
+#[handler(priority = Priority::Priority3)]
+fn on_rmt() {
+    let status = registers().int_st().read();
+    for ch in 0..CHANNELS as u8 {
+        if status.ch_tx_thr_event(ch).bit_is_set() {
+            registers()
+                .int_clr()
+                .write(|w| w.ch_tx_thr_event(ch).set_bit());
+            refill(ch);
+        }
+        let failed = status.ch_tx_err(ch).bit_is_set();
+        if failed || status.ch_tx_end(ch).bit_is_set() {
+            stop(ch);
+            let end = if failed { Err(()) } else { Ok(Instant::now()) };
+            FINISHED[usize::from(ch)].signal(end);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! External LED strip
+//! One-wire strips on a second RMT channel, APA102/SK9822 on SPI3
+//!
+//! The strip shows the board-wide effect layers stretched over its length
+//! and gets whatever the key LEDs leave of the current budget.
+
+use embedded_hal_async::spi::SpiBus;
+use esp_hal::spi::master::Spi;
+use esp_hal::Async;
+use izzymonitor_core::leds::format::{apa102_frame_size, encode_apa102};
+use izzymonitor_core::leds::{LedOutput, PowerBudget, PowerReport, RgbColor};
+
+use super::{LedController, MAX_LEDS};
+
+/// Bus frequency of a clocked strip, well inside what APA102 and SK9822 take
+pub const APA102_CLOCK_MHZ: u32 = 8;
+
+const FRAME_SIZE: usize = apa102_frame_size(MAX_LEDS);
+
+/// APA102/SK9822 strip on an SPI data and clock pair
+pub struct Apa102Controller {
+    spi: Spi<'static, Async>,
+    buffer: [u8; FRAME_SIZE],
+    budget: PowerBudget,
+    report: PowerReport,
+}
+
+impl Apa102Controller {
+    /// Create a controller on an SPI bus set up for the strip
+    pub fn new(spi: Spi<'static, Async>, budget: PowerBudget) -> Self {
+        Self {
+            spi,
+            buffer: [0; FRAME_SIZE],
+            budget,
+            report: PowerReport::default(),
+        }
+    }
+}
+
+impl LedOutput for Apa102Controller {
+    type Error = esp_hal::spi::Error;
+
+    async fn show(&mut self, colors: &[RgbColor]) -> Result<(), Self::Error> {
+        let mut frame = [RgbColor::new(0, 0, 0); MAX_LEDS];
+        let len = colors.len().min(MAX_LEDS);
+        frame[..len].copy_from_slice(&colors[..len]);
+        let frame = &mut frame[..len];
+        self.report = self.budget.apply(frame);
+
+        // The clock latches the data, so there is no reset time to wait out
+        let len = encode_apa102(frame, &mut self.buffer);
+        self.spi.write(&self.buffer[..len]).await
+    }
+}
+
+/// The external strip, on whichever bus its format needs
+pub enum StripOutput {
+    OneWire(LedController<1>, u8),
+    Clocked(Apa102Controller, u8),
+}
+
+impl StripOutput {
+    /// LEDs on the strip
+    pub fn leds(&self) -> usize {
+        match self {
+            StripOutput::OneWire(_, len) | StripOutput::Clocked(_, len) => usize::from(*len),
+        }
+    }
+
+    /// Change the strip's share of the current budget, never below the minimum
+    pub fn set_limit_ma(&mut self, limit_ma: u16) {
+        match self {
+            StripOutput::OneWire(controller, _) => controller.set_limit_ma(limit_ma),
+            StripOutput::Clocked(controller, _) => controller.budget.set_limit_ma(limit_ma),
+        }
+    }
+
+    /// Estimated current draw of the last frame
+    pub fn report(&self) -> PowerReport {
+        match self {
+            StripOutput::OneWire(controller, _) => controller.report(),
+            StripOutput::Clocked(controller, _) => controller.report,
+        }
+    }
+}
+
+impl LedOutput for StripOutput {
+    type Error = &'static str;
+
+    async fn show(&mut self, colors: &[RgbColor]) -> Result<(), Self::Error> {
+        match self {
+            StripOutput::OneWire(controller, _) => controller.show(colors).await,
+            StripOutput::Clocked(controller, _) => controller
+                .show(colors)
+                .await
+                .map_err(|_| "SPI write failed"),
+        }
+    }
+}
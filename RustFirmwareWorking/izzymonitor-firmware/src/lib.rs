@@ -1 +1,13 @@
+//! IzzyMonitor firmware
+//! Board adapters that run the `izzymonitor-core` panel logic on the ESP32-S3
+
 #![no_std]
+
+pub mod ambient;
+pub mod board;
+pub mod buttons;
+pub mod clock;
+pub mod console;
+pub mod display;
+pub mod leds;
+pub mod storage;
@@ -0,0 +1,131 @@
+//! Configuration storage
+//! Keeps the persistent config image and the LED patterns in reserved flash
+//! sectors
+
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embedded_storage::{ReadStorage, Storage};
+use esp_storage::FlashStorage;
+use heapless::Vec;
+use izzymonitor_core::leds::pattern::{PatternName, PATTERN_SLOT_SIZE};
+use izzymonitor_core::leds::StoredPattern;
+use log::{info, warn};
+
+pub use izzymonitor_core::config::{Config, CONFIG_SIZE};
+
+/// Flash offset of the config image, the start of the default `nvs` partition
+pub const CONFIG_OFFSET: u32 = 0x9000;
+
+/// Flash offset of the LED pattern slots, the sector after the config
+pub const PATTERN_OFFSET: u32 = 0xa000;
+/// Patterns kept at the same time, one sector's worth
+pub const PATTERN_SLOTS: usize = 8;
+
+/// The config in use, shared by every task that changes a setting
+static CONFIG: Mutex<CriticalSectionRawMutex, Cell<Config>> = Mutex::new(Cell::new(Config::new()));
+
+/// Read the stored config, falling back to defaults
+pub fn load() -> Config {
+    let mut image = [0; CONFIG_SIZE];
+    let config = if FlashStorage::new().read(CONFIG_OFFSET, &mut image).is_err() {
+        warn!("Failed to read config, using defaults");
+        Config::default()
+    } else {
+        Config::decode(&image).unwrap_or_else(|e| {
+            info!("{}, using defaults", e);
+            Config::default()
+        })
+    };
+    CONFIG.lock(|c| c.set(config));
+    config
+}
+
+/// The config in use
+pub fn current() -> Config {
+    CONFIG.lock(Cell::get)
+}
+
+/// Change a setting and write the whole config back to flash
+pub fn update(change: impl FnOnce(&mut Config)) -> Result<(), &'static str> {
+    let config = CONFIG.lock(|c| {
+        let mut config = c.get();
+        change(&mut config);
+        c.set(config);
+        config
+    });
+    save(&config)
+}
+
+/// Write the config to flash
+pub fn save(config: &Config) -> Result<(), &'static str> {
+    let image = config.encode()?;
+    FlashStorage::new()
+        .write(CONFIG_OFFSET, &image)
+        .map_err(|_| "Failed to write config")
+}
+
+/// Forget every setting and stored pattern, for a factory reset
+pub fn erase() -> Result<(), &'static str> {
+    let config = Config::default();
+    save(&config)?;
+    CONFIG.lock(|c| c.set(config));
+    for slot in 0..PATTERN_SLOTS {
+        if read_pattern_slot(slot).is_some() {
+            write_pattern_slot(slot, &[0xff; PATTERN_SLOT_SIZE])?;
+        }
+    }
+    Ok(())
+}
+
+/// Read one pattern slot, `None` if it is empty or corrupt
+fn read_pattern_slot(slot: usize) -> Option<StoredPattern> {
+    let mut image = [0; PATTERN_SLOT_SIZE];
+    let offset = PATTERN_OFFSET + (slot * PATTERN_SLOT_SIZE) as u32;
+    FlashStorage::new().read(offset, &mut image).ok()?;
+    StoredPattern::from_bytes(&image).ok()
+}
+
+fn write_pattern_slot(slot: usize, image: &[u8; PATTERN_SLOT_SIZE]) -> Result<(), &'static str> {
+    let offset = PATTERN_OFFSET + (slot * PATTERN_SLOT_SIZE) as u32;
+    FlashStorage::new()
+        .write(offset, image)
+        .map_err(|_| "Failed to write pattern")
+}
+
+/// Names of the stored patterns
+pub fn pattern_names() -> Vec<PatternName, PATTERN_SLOTS> {
+    (0..PATTERN_SLOTS)
+        .filter_map(read_pattern_slot)
+        .map(|pattern| pattern.name)
+        .collect()
+}
+
+/// Look a stored pattern up by name
+pub fn find_pattern(name: &str) -> Option<StoredPattern> {
+    (0..PATTERN_SLOTS)
+        .filter_map(read_pattern_slot)
+        .find(|pattern| pattern.name == name)
+}
+
+/// Store a pattern, replacing the one of the same name
+pub fn store_pattern(pattern: &StoredPattern) -> Result<(), &'static str> {
+    let slot = pattern_slot(&pattern.name)
+        .or_else(|| (0..PATTERN_SLOTS).find(|&slot| read_pattern_slot(slot).is_none()))
+        .ok_or("No free pattern slot")?;
+    write_pattern_slot(slot, &pattern.to_bytes())
+}
+
+/// Delete a stored pattern, returning whether there was one
+pub fn remove_pattern(name: &str) -> Result<bool, &'static str> {
+    let Some(slot) = pattern_slot(name) else {
+        return Ok(false);
+    };
+    write_pattern_slot(slot, &[0xff; PATTERN_SLOT_SIZE])?;
+    Ok(true)
+}
+
+fn pattern_slot(name: &str) -> Option<usize> {
+    (0..PATTERN_SLOTS).find(|&slot| read_pattern_slot(slot).is_some_and(|p| p.name == name))
+}
@@ -0,0 +1,225 @@
+//! BDF fonts
+//! Reads the glyph bitmaps of X11 bitmap fonts as they are
+//!
+//! Only what drawing needs is read: the font's ascent and descent, and each
+//! glyph's encoding, advance, bounding box and bitmap rows.
+
+use crate::{field, Font, Glyph, Subset};
+
+/// Values after a keyword
+fn numbers<const N: usize>(rest: &str, keyword: &str) -> Result<[i32; N], String> {
+    let values: Vec<i32> = rest
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("invalid {keyword}"))?;
+    values
+        .get(..N)
+        .and_then(|values| values.try_into().ok())
+        .ok_or_else(|| format!("{keyword} needs {N} values"))
+}
+
+/// A glyph as read, before it is placed on the line
+#[derive(Default)]
+struct Char {
+    code: Option<u32>,
+    advance: i32,
+    bbx: [i32; 4],
+    rows: Vec<u64>,
+}
+
+/// Parse a BDF font, keeping the glyphs in the subset
+pub fn parse(text: &str, subset: &Subset) -> Result<Font, String> {
+    let mut ascent = None;
+    let mut descent = None;
+    let mut bounding_box = [0; 4];
+    let mut glyphs = Vec::new();
+    let mut current: Option<Char> = None;
+    let mut in_bitmap = false;
+
+    for (number, line) in text.lines().enumerate() {
+        let context = |e: String| format!("line {}: {e}", number + 1);
+        let line = line.trim();
+        let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+
+        if in_bitmap {
+            let glyph = current
+                .as_mut()
+                .ok_or_else(|| context("bitmap outside a glyph".into()))?;
+            if keyword != "ENDCHAR" {
+                let row = u64::from_str_radix(line, 16)
+                    .map_err(|_| context(format!("invalid bitmap row '{line}'")))?;
+                // Rows are padded to whole bytes, left aligned
+                glyph.rows.push(row << (64 - 4 * line.len() as u32));
+                continue;
+            }
+        }
+
+        match keyword {
+            "FONTBOUNDINGBOX" => bounding_box = numbers(rest, keyword).map_err(context)?,
+            "FONT_ASCENT" => ascent = Some(numbers::<1>(rest, keyword).map_err(context)?[0]),
+            "FONT_DESCENT" => descent = Some(numbers::<1>(rest, keyword).map_err(context)?[0]),
+            "STARTCHAR" => {
+                current = Some(Char {
+                    advance: bounding_box[0],
+                    bbx: bounding_box,
+                    ..Char::default()
+                })
+            }
+            "ENCODING" => {
+                let [code] = numbers(rest, keyword).map_err(context)?;
+                if let Some(glyph) = current.as_mut() {
+                    // -1 is a glyph without a code point
+                    glyph.code = u32::try_from(code).ok();
+                }
+            }
+            "DWIDTH" => {
+                let [x, _] = numbers(rest, keyword).map_err(context)?;
+                if let Some(glyph) = current.as_mut() {
+                    glyph.advance = x;
+                }
+            }
+            "BBX" => {
+                let bbx = numbers(rest, keyword).map_err(context)?;
+                if let Some(glyph) = current.as_mut() {
+                    glyph.bbx = bbx;
+                }
+            }
+            "BITMAP" => in_bitmap = true,
+            "ENDCHAR" => {
+                in_bitmap = false;
+                let glyph = current
+                    .take()
+                    .ok_or_else(|| context("ENDCHAR outside a glyph".into()))?;
+                glyphs.push(glyph);
+            }
+            _ => {}
+        }
+    }
+
+    // Fonts without the properties use the bounding box
+    let [_, height, _, y] = bounding_box;
+    let descent = descent.unwrap_or(-y);
+    let ascent = ascent.unwrap_or(height + y);
+    let baseline = u8::try_from(ascent).map_err(|_| "font ascent out of range")?;
+    let line_height = u8::try_from(ascent + descent).map_err(|_| "font height out of range")?;
+
+    let mut kept = Vec::new();
+    for glyph in glyphs {
+        let Some(c) = glyph.code.and_then(char::from_u32) else {
+            continue;
+        };
+        if !subset.contains(c) && c != crate::REPLACEMENT {
+            continue;
+        }
+        let [width, height, x, y] = glyph.bbx;
+        if glyph.rows.len() != height.max(0) as usize || !(0..=64).contains(&width) {
+            return Err(format!(
+                "glyph U+{:04X} doesn't match its bounding box",
+                u32::from(c)
+            ));
+        }
+        let bits = glyph
+            .rows
+            .iter()
+            .flat_map(|row| (0..width).map(move |col| row & (1 << (63 - col)) != 0))
+            .collect();
+        kept.push(Glyph {
+            c,
+            advance: field(c, glyph.advance, "advance")?,
+            x: field(c, x, "offset")?,
+            // The box's bottom is `y` above the baseline
+            y: field(c, ascent - y - height, "offset")?,
+            width: field(c, width, "width")?,
+            height: field(c, height, "height")?,
+            bits,
+        });
+    }
+    Ok(Font::new(line_height, baseline, kept))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FONT: &str = "STARTFONT 2.1
+FONT -misc-tiny-medium-r-normal--8-80-75-75-c-50-iso10646-1
+SIZE 8 75 75
+FONTBOUNDINGBOX 5 8 0 -2
+STARTPROPERTIES 2
+FONT_ASCENT 6
+FONT_DESCENT 2
+ENDPROPERTIES
+CHARS 3
+STARTCHAR A
+ENCODING 65
+SWIDTH 500 0
+DWIDTH 5 0
+BBX 4 5 0 0
+BITMAP
+60
+90
+F0
+90
+90
+ENDCHAR
+STARTCHAR adieresis
+ENCODING 228
+DWIDTH 5 0
+BBX 4 6 0 0
+BITMAP
+90
+00
+70
+90
+90
+70
+ENDCHAR
+STARTCHAR g
+ENCODING 103
+DWIDTH 5 0
+BBX 4 5 0 -2
+BITMAP
+70
+90
+70
+10
+E0
+ENDCHAR
+ENDFONT
+";
+
+    #[test]
+    fn reads_glyphs_in_the_subset() {
+        let subset = Subset::parse(["ascii"]).unwrap();
+        let font = parse(FONT, &subset).unwrap();
+        assert_eq!((font.line_height, font.baseline), (8, 6));
+        // ä is outside the subset
+        let chars: Vec<char> = font.glyphs.iter().map(|glyph| glyph.c).collect();
+        assert_eq!(chars, ['A', 'g']);
+
+        let a = &font.glyphs[0];
+        assert_eq!((a.advance, a.x, a.y, a.width, a.height), (5, 0, 1, 4, 5));
+        assert_eq!(
+            &a.bits[..8],
+            [false, true, true, false, true, false, false, true]
+        );
+        // The descender reaches below the baseline
+        let g = &font.glyphs[1];
+        assert_eq!(g.y + g.height as i8, 8);
+
+        let font = parse(FONT, &Subset::parse(["latin1"]).unwrap()).unwrap();
+        assert_eq!(font.glyphs[0].c, 'ä');
+        assert_eq!(font.glyphs[0].y, 0);
+    }
+
+    #[test]
+    fn rejects_broken_glyphs() {
+        let broken = FONT.replace("BBX 4 5 0 0", "BBX 4 7 0 0");
+        assert!(parse(&broken, &Subset::parse(["ascii"]).unwrap()).is_err());
+        let broken = FONT.replace("F0", "XY");
+        assert!(parse(&broken, &Subset::parse(["ascii"]).unwrap())
+            .unwrap_err()
+            .starts_with("line 18"));
+    }
+}
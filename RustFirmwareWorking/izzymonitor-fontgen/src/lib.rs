@@ -0,0 +1,358 @@
+//! Font converter
+//! Turns BDF and TTF fonts into the bitmap fonts `izzymonitor-core` draws with
+//!
+//! Run from `izzymonitor-core`'s build script over the fonts listed in
+//! `fonts/fonts.txt`, one per line:
+//!
+//! ```text
+//! # name    source                    px   glyphs
+//! SANS_13   DejaVuSansCondensed.ttf   13   ascii latin1 greek U+2190-2193
+//! TERM_12   terminus-12.bdf           -    ascii latin1
+//! ```
+//!
+//! A BDF font has its size built in, so its size is `-`. Only the glyphs in
+//! the listed ranges end up in flash; characters outside them draw the
+//! replacement glyph, U+FFFD from the source or a hollow box if it has none.
+
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+pub mod bdf;
+pub mod ttf;
+
+/// Drawn for characters a font doesn't have
+pub const REPLACEMENT: char = '\u{fffd}';
+
+/// Named glyph ranges for the config
+pub const NAMED_RANGES: [(&str, RangeInclusive<u32>); 8] = [
+    ("ascii", 0x20..=0x7e),
+    ("latin1", 0xa0..=0xff),
+    ("latin-ext-a", 0x100..=0x17f),
+    ("latin-ext-b", 0x180..=0x24f),
+    ("greek", 0x370..=0x3ff),
+    ("cyrillic", 0x400..=0x4ff),
+    ("punctuation", 0x2000..=0x206f),
+    ("arrows", 0x2190..=0x21ff),
+];
+
+/// The characters to keep from a font
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Subset {
+    ranges: Vec<RangeInclusive<u32>>,
+}
+
+impl Subset {
+    /// Parse range names and `U+XXXX` or `U+XXXX-YYYY` ranges
+    pub fn parse<'a>(words: impl IntoIterator<Item = &'a str>) -> Result<Self, String> {
+        let mut ranges = Vec::new();
+        for word in words {
+            if let Some((_, range)) = NAMED_RANGES.iter().find(|(name, _)| *name == word) {
+                ranges.push(range.clone());
+                continue;
+            }
+            let hex = word
+                .strip_prefix("U+")
+                .ok_or_else(|| format!("unknown glyph range '{word}'"))?;
+            let (start, end) = hex.split_once('-').unwrap_or((hex, hex));
+            let parse = |hex: &str| {
+                u32::from_str_radix(hex, 16).map_err(|_| format!("invalid code point in '{word}'"))
+            };
+            let (start, end) = (parse(start)?, parse(end)?);
+            if start > end {
+                return Err(format!("empty glyph range '{word}'"));
+            }
+            ranges.push(start..=end);
+        }
+        Ok(Self { ranges })
+    }
+
+    /// Whether a character is kept
+    pub fn contains(&self, c: char) -> bool {
+        self.ranges
+            .iter()
+            .any(|range| range.contains(&u32::from(c)))
+    }
+
+    /// Every character kept, in order, once each
+    pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        let mut codes: Vec<u32> = self.ranges.iter().flat_map(Clone::clone).collect();
+        codes.sort_unstable();
+        codes.dedup();
+        codes.into_iter().filter_map(char::from_u32)
+    }
+}
+
+/// A glyph as one bit per pixel, row by row
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Glyph {
+    pub c: char,
+    /// Distance to the next glyph's origin
+    pub advance: u8,
+    /// Left of the bitmap from the origin
+    pub x: i8,
+    /// Top of the bitmap from the top of the line
+    pub y: i8,
+    pub width: u8,
+    pub height: u8,
+    pub bits: Vec<bool>,
+}
+
+/// A font converted to bitmaps
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Font {
+    /// Distance from one line to the next
+    pub line_height: u8,
+    /// Baseline from the top of the line
+    pub baseline: u8,
+    /// Sorted by character
+    pub glyphs: Vec<Glyph>,
+    pub replacement: Glyph,
+}
+
+impl Font {
+    /// Sort the glyphs and pick the replacement, drawing one if there is none
+    pub fn new(line_height: u8, baseline: u8, mut glyphs: Vec<Glyph>) -> Self {
+        glyphs.sort_by_key(|glyph| glyph.c);
+        glyphs.dedup_by_key(|glyph| glyph.c);
+        let replacement = match glyphs.iter().position(|glyph| glyph.c == REPLACEMENT) {
+            Some(index) => glyphs.remove(index),
+            None => hollow_box(line_height, baseline),
+        };
+        Self {
+            line_height,
+            baseline,
+            glyphs,
+            replacement,
+        }
+    }
+
+    /// Bytes the font takes in flash: bitmaps plus 12 bytes per glyph
+    pub fn size(&self) -> usize {
+        let bits: usize = self.glyphs.iter().map(|glyph| glyph.bits.len()).sum();
+        bits.div_ceil(8) + (self.glyphs.len() + 1) * 12
+    }
+}
+
+/// A glyph's value in the range of its field
+fn field<T: TryFrom<i32>>(c: char, value: i32, what: &str) -> Result<T, String> {
+    T::try_from(value).map_err(|_| format!("glyph U+{:04X} {what} out of range", u32::from(c)))
+}
+
+/// A box from the baseline up to cap height, for a font without U+FFFD
+fn hollow_box(line_height: u8, baseline: u8) -> Glyph {
+    let height = (baseline * 3 / 4).max(3);
+    let width = (line_height / 2).max(3);
+    let bits = (0..height)
+        .flat_map(|row| {
+            (0..width).map(move |col| row == 0 || row == height - 1 || col == 0 || col == width - 1)
+        })
+        .collect();
+    Glyph {
+        c: REPLACEMENT,
+        advance: width + 2,
+        x: 1,
+        y: (baseline - height) as i8,
+        width,
+        height,
+        bits,
+    }
+}
+
+/// One line of the font list
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontSpec {
+    /// Name of the static in the generated code
+    pub name: String,
+    /// File name, relative to the list
+    pub source: String,
+    /// Pixel size for a TTF, `None` for a BDF
+    pub size: Option<f32>,
+    pub subset: Subset,
+}
+
+/// Parse a font list
+pub fn parse_config(text: &str) -> Result<Vec<FontSpec>, String> {
+    let mut specs = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut words = line.split_whitespace();
+        let Some(name) = words.next() else {
+            continue;
+        };
+        let context = |e: String| format!("line {}: {e}", number + 1);
+        let (Some(source), Some(size)) = (words.next(), words.next()) else {
+            return Err(context("expected name, source, size and glyphs".into()));
+        };
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+        {
+            return Err(context(format!(
+                "font name '{name}' is not an UPPER_CASE name"
+            )));
+        }
+        let size = match size {
+            "-" => None,
+            size => Some(
+                size.parse::<f32>()
+                    .map_err(|_| context(format!("invalid size '{size}'")))?,
+            ),
+        };
+        let subset = Subset::parse(words).map_err(context)?;
+        if subset.ranges.is_empty() {
+            return Err(context(format!("no glyphs listed for {name}")));
+        }
+        specs.push(FontSpec {
+            name: name.into(),
+            source: source.into(),
+            size,
+            subset,
+        });
+    }
+    Ok(specs)
+}
+
+/// Load a font by its file extension
+pub fn load(path: &Path, size: Option<f32>, subset: &Subset) -> Result<Font, String> {
+    let data = std::fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+    let font = match (extension.to_ascii_lowercase().as_str(), size) {
+        ("bdf", None) => bdf::parse(&String::from_utf8_lossy(&data), subset),
+        ("bdf", Some(_)) => Err("a BDF font has a fixed size, give its size as '-'".into()),
+        ("ttf" | "otf", Some(size)) => ttf::rasterize(&data, size, subset),
+        ("ttf" | "otf", None) => Err("a TTF font needs a pixel size".into()),
+        _ => Err("expected a .bdf, .ttf or .otf font".into()),
+    };
+    font.map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// Pack bits eight to a byte, first bit highest
+fn pack(bits: impl Iterator<Item = bool>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for (i, bit) in bits.enumerate() {
+        if i % 8 == 0 {
+            bytes.push(0);
+        }
+        if bit {
+            *bytes.last_mut().unwrap() |= 0x80 >> (i % 8);
+        }
+    }
+    bytes
+}
+
+fn glyph_source(glyph: &Glyph, offset: usize) -> String {
+    format!(
+        "Glyph::new({:?}, {}, {}, {}, {}, {}, {offset})",
+        glyph.c, glyph.advance, glyph.x, glyph.y, glyph.width, glyph.height
+    )
+}
+
+/// Rust source for a `BitmapFont` static
+pub fn generate(name: &str, source: &str, font: &Font) -> String {
+    let mut out = String::new();
+    let glyphs = font.glyphs.iter().chain([&font.replacement]);
+    let mut offset = 0;
+    let mut entries = Vec::new();
+    for glyph in glyphs.clone() {
+        entries.push(glyph_source(glyph, offset));
+        offset += glyph.bits.len();
+    }
+    let replacement = entries.pop().unwrap();
+    let bitmap = pack(glyphs.flat_map(|glyph| glyph.bits.iter().copied()));
+
+    let _ = writeln!(
+        out,
+        "/// {source}: {} glyphs, {} px lines, {} bytes",
+        font.glyphs.len(),
+        font.line_height,
+        font.size()
+    );
+    let _ = writeln!(out, "pub static {name}: BitmapFont = BitmapFont::new(");
+    let _ = writeln!(out, "    &[");
+    for entry in entries {
+        let _ = writeln!(out, "        {entry},");
+    }
+    let _ = writeln!(out, "    ],");
+    let _ = writeln!(out, "    {replacement},");
+    let _ = writeln!(out, "    &[");
+    for chunk in bitmap.chunks(16) {
+        let bytes: Vec<String> = chunk.iter().map(|b| format!("0x{b:02x}")).collect();
+        let _ = writeln!(out, "        {},", bytes.join(", "));
+    }
+    let _ = writeln!(out, "    ],");
+    let _ = writeln!(out, "    {},", font.line_height);
+    let _ = writeln!(out, "    {},", font.baseline);
+    let _ = writeln!(out, ");");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_font_list() {
+        let specs = parse_config(
+            "# Fonts\n\
+             SANS_13 DejaVuSansCondensed.ttf 13 ascii U+00E4-00F6 # umlauts\n\
+             \n\
+             TERM_12 terminus.bdf - latin1\n",
+        )
+        .unwrap();
+        assert_eq!(specs.len(), 2);
+        assert_eq!(specs[0].size, Some(13.0));
+        assert!(specs[0].subset.contains('ö') && !specs[0].subset.contains('ø'));
+        assert_eq!(specs[1].size, None);
+        assert_eq!(specs[1].subset.chars().count(), 96);
+
+        assert!(parse_config("SANS x.ttf 13 klingon").is_err());
+        assert!(parse_config("sans x.ttf 13 ascii").is_err());
+        assert!(parse_config("SANS x.ttf 13").is_err());
+        assert!(parse_config("SANS x.ttf 13 U+00FF-00A0")
+            .unwrap_err()
+            .starts_with("line 1"));
+    }
+
+    #[test]
+    fn synthesizes_a_replacement_glyph() {
+        let font = Font::new(13, 10, Vec::new());
+        let replacement = &font.replacement;
+        assert_eq!(replacement.c, REPLACEMENT);
+        // Bottom row on the baseline
+        assert_eq!(replacement.y + replacement.height as i8, 10);
+        assert!(replacement.bits[..usize::from(replacement.width)]
+            .iter()
+            .all(|&bit| bit));
+    }
+
+    #[test]
+    fn packs_bits_high_first() {
+        assert_eq!(
+            pack([true, false, false, false, false, false, false, true, true].into_iter()),
+            [0x81, 0x80]
+        );
+        let font = Font::new(
+            8,
+            6,
+            vec![Glyph {
+                c: 'i',
+                advance: 2,
+                x: 0,
+                y: 1,
+                width: 1,
+                height: 5,
+                bits: vec![true, false, true, true, true],
+            }],
+        );
+        let source = generate("TINY", "tiny.bdf", &font);
+        assert!(source.contains("pub static TINY: BitmapFont"));
+        assert!(source.contains("Glyph::new('i', 2, 0, 1, 1, 5, 0)"));
+        // The replacement comes after the glyph's five bits
+        assert!(source.contains("Glyph::new('\u{fffd}', 6, 1, 2, 4, 4, 5)"));
+        assert!(source.contains("0xbf"));
+    }
+}
@@ -0,0 +1,85 @@
+//! TTF fonts
+//! Rasterizes outline fonts at one pixel size into one-bit glyphs
+//!
+//! Pixels at least half covered are set. There is no hinting, so sizes where
+//! the font's stems land on whole pixels look best; for DejaVu Sans that is
+//! 10, 11 and 13 px.
+
+use crate::{field, Font, Glyph, Subset, REPLACEMENT};
+
+/// Coverage from which a pixel is set
+const THRESHOLD: u8 = 128;
+
+/// Rasterize the glyphs in the subset at `size` pixels per em
+pub fn rasterize(data: &[u8], size: f32, subset: &Subset) -> Result<Font, String> {
+    let font = fontdue::Font::from_bytes(data, fontdue::FontSettings::default())
+        .map_err(|e| e.to_string())?;
+    let line = font
+        .horizontal_line_metrics(size)
+        .ok_or("font has no horizontal metrics")?;
+    let ascent = line.ascent.ceil() as i32;
+    let line_height = (line.ascent - line.descent).ceil() as i32;
+    let baseline = u8::try_from(ascent).map_err(|_| "font ascent out of range")?;
+    let line_height = u8::try_from(line_height).map_err(|_| "font height out of range")?;
+
+    let mut glyphs = Vec::new();
+    for c in subset.chars().chain([REPLACEMENT]) {
+        // Characters the font doesn't have are left to the replacement
+        if !font.has_glyph(c) {
+            continue;
+        }
+        let (metrics, coverage) = font.rasterize(c, size);
+        glyphs.push(Glyph {
+            c,
+            advance: field(c, metrics.advance_width.round() as i32, "advance")?,
+            x: field(c, metrics.xmin, "offset")?,
+            // The bitmap's bottom is `ymin` above the baseline
+            y: field(c, ascent - metrics.ymin - metrics.height as i32, "offset")?,
+            width: field(c, metrics.width as i32, "width")?,
+            height: field(c, metrics.height as i32, "height")?,
+            bits: coverage.iter().map(|&c| c >= THRESHOLD).collect(),
+        });
+    }
+    Ok(Font::new(line_height, baseline, glyphs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dejavu(size: f32, glyphs: &[&str]) -> Font {
+        let data = include_bytes!("../../izzymonitor-core/fonts/DejaVuSansCondensed.ttf");
+        rasterize(data, size, &Subset::parse(glyphs.iter().copied()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn rasterizes_latin_and_cyrillic() {
+        let font = dejavu(13.0, &["ascii", "latin1", "cyrillic"]);
+        assert!(
+            (14..=16).contains(&font.line_height),
+            "{}",
+            font.line_height
+        );
+        let glyph = |c: char| font.glyphs.iter().find(|glyph| glyph.c == c).unwrap();
+
+        // Proportional: i is narrower than W
+        assert!(glyph('i').advance < glyph('W').advance);
+        // Umlaut dots sit above the x-height, the descender below the baseline
+        assert!(glyph('ü').y < glyph('u').y);
+        let g = glyph('g');
+        assert!(g.y as u8 + g.height > font.baseline);
+        // Ж is there, and drawn
+        assert!(glyph('Ж').bits.iter().any(|&bit| bit));
+        // DejaVu has its own replacement glyph
+        assert!(font.replacement.bits.iter().any(|&bit| bit));
+        assert_eq!(font.replacement.c, REPLACEMENT);
+    }
+
+    #[test]
+    fn subset_sets_the_size() {
+        let small = dejavu(11.0, &["ascii"]);
+        let large = dejavu(11.0, &["ascii", "latin1", "latin-ext-a"]);
+        assert_eq!(small.glyphs.len(), 95);
+        assert!(large.size() > 2 * small.size());
+    }
+}
@@ -0,0 +1,202 @@
+//! LED pattern encoder
+//! Turns a text description of a key animation into pattern bytecode
+//!
+//! Usage: `izzymonitor-pattern FILE [NAME]`. Prints the bytecode in hex, or
+//! with a name the `pattern put` line for the device console. The pattern is
+//! checked the same way the device checks it before anything is printed.
+//!
+//! ```text
+//! duration 6000              # how long the pattern plays, in ms
+//! track all loop             # keys 1-6 or all, once/loop/pingpong, passes
+//! key 0 000000 linear        # time in ms, hex color, easing
+//! key 800 3050ff ease        # easings: linear, ease, cubic, bounce
+//! key 1600 000000 ease
+//! track 2,5 pingpong 3
+//! key 0 000000 linear
+//! key 300 40a000 cubic
+//! ```
+
+use std::process::ExitCode;
+
+use embassy_time::Duration;
+use izzymonitor_core::buttons::KeySet;
+use izzymonitor_core::leds::pattern::PatternCode;
+use izzymonitor_core::leds::{Pattern, PatternEncoder, RgbColor};
+use izzymonitor_core::timeline::{Easing, Playback};
+use izzymonitor_core::KEY_COUNT;
+
+fn parse_keys(arg: &str) -> Result<KeySet, String> {
+    if arg == "all" {
+        return Ok(KeySet::ALL);
+    }
+    let mut keys = KeySet::EMPTY;
+    for key in arg.split(',') {
+        match key.parse::<usize>() {
+            Ok(n) if (1..=KEY_COUNT).contains(&n) => keys.insert(n - 1),
+            _ => {
+                return Err(format!(
+                    "invalid key '{key}', expected 1-{KEY_COUNT} or all"
+                ))
+            }
+        }
+    }
+    Ok(keys)
+}
+
+fn parse_playback(arg: &str) -> Result<Playback, String> {
+    match arg {
+        "once" => Ok(Playback::Once),
+        "loop" => Ok(Playback::Loop),
+        "pingpong" => Ok(Playback::PingPong),
+        _ => Err(format!("invalid playback '{arg}'")),
+    }
+}
+
+fn parse_easing(arg: &str) -> Result<Easing, String> {
+    match arg {
+        "linear" => Ok(Easing::Linear),
+        "ease" => Ok(Easing::EaseInOut),
+        "cubic" => Ok(Easing::Cubic),
+        "bounce" => Ok(Easing::Bounce),
+        _ => Err(format!("invalid easing '{arg}'")),
+    }
+}
+
+fn parse_color(arg: &str) -> Result<RgbColor, String> {
+    let value = u32::from_str_radix(arg.trim_start_matches('#'), 16)
+        .ok()
+        .filter(|_| arg.trim_start_matches('#').len() == 6)
+        .ok_or_else(|| format!("invalid color '{arg}', expected rrggbb"))?;
+    let [_, r, g, b] = value.to_be_bytes();
+    Ok(RgbColor::new(r, g, b))
+}
+
+fn parse_number<T: std::str::FromStr>(arg: Option<&str>, what: &str) -> Result<T, String> {
+    let arg = arg.ok_or(format!("missing {what}"))?;
+    arg.parse().map_err(|_| format!("invalid {what} '{arg}'"))
+}
+
+/// Encode a whole pattern description
+fn encode(text: &str) -> Result<PatternCode, String> {
+    let mut encoder: Option<PatternEncoder> = None;
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut words = line.split_whitespace();
+        let Some(first) = words.next() else {
+            continue;
+        };
+        let at_line = |e: String| format!("line {}: {e}", number + 1);
+
+        if first == "duration" {
+            if encoder.is_some() {
+                return Err(at_line("duration given twice".into()));
+            }
+            let ms = parse_number(words.next(), "duration").map_err(at_line)?;
+            encoder = Some(PatternEncoder::new(Duration::from_millis(ms)));
+            continue;
+        }
+        let encoder = encoder
+            .as_mut()
+            .ok_or_else(|| at_line("duration has to come first".into()))?;
+
+        match first {
+            "track" => {
+                let keys = parse_keys(words.next().unwrap_or("")).map_err(at_line)?;
+                let playback = parse_playback(words.next().unwrap_or("")).map_err(at_line)?;
+                let repeats = match words.next() {
+                    Some(n) => parse_number(Some(n), "pass count").map_err(at_line)?,
+                    None => 0,
+                };
+                encoder
+                    .track(keys, playback, repeats)
+                    .map_err(|e| at_line(e.into()))?;
+            }
+            "key" => {
+                let ms = parse_number(words.next(), "keyframe time").map_err(at_line)?;
+                let color = parse_color(words.next().unwrap_or("")).map_err(at_line)?;
+                let easing = parse_easing(words.next().unwrap_or("linear")).map_err(at_line)?;
+                encoder
+                    .keyframe(Duration::from_millis(ms), color, easing)
+                    .map_err(|e| at_line(e.into()))?;
+            }
+            _ => return Err(at_line(format!("unknown command '{first}'"))),
+        }
+    }
+
+    let code = encoder.ok_or("empty pattern")?.finish();
+    Pattern::decode(&code).map_err(|e| format!("device would reject it: {e}"))?;
+    Ok(code)
+}
+
+fn run() -> Result<(), String> {
+    let mut args = std::env::args().skip(1);
+    let path = args
+        .next()
+        .ok_or("usage: izzymonitor-pattern FILE [NAME]")?;
+    let text = std::fs::read_to_string(&path).map_err(|e| format!("cannot open {path}: {e}"))?;
+    let code = encode(&text)?;
+    let hex: String = code.iter().map(|byte| format!("{byte:02x}")).collect();
+
+    match args.next() {
+        Some(name) => println!("pattern put {name} {hex}"),
+        None => println!("{hex}"),
+    }
+    eprintln!("{} bytes", code.len());
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("izzymonitor-pattern: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_the_documented_example() {
+        let text = "
+            duration 6000
+            track all loop
+            key 0 000000 linear
+            key 800 3050ff ease   # swell
+            key 1600 000000 ease
+            track 2,5 pingpong 3
+            key 0 000000
+            key 300 40a000 cubic
+        ";
+        let code = encode(text).unwrap();
+        assert_eq!(code[..6], *b"IZP\x01\x70\x17");
+        // Header, two tracks, five keyframes
+        assert_eq!(code.len(), 6 + 2 * 4 + 5 * 7);
+        assert_eq!(code[6..10], [1, 0x3f, 1, 0]);
+        assert_eq!(code[31..35], [1, 0b10010, 2, 3]);
+    }
+
+    #[test]
+    fn reports_bad_lines() {
+        assert_eq!(
+            encode("track all loop").unwrap_err(),
+            "line 1: duration has to come first"
+        );
+        assert_eq!(
+            encode("duration 500\ntrack 7 loop").unwrap_err(),
+            "line 2: invalid key '7', expected 1-6 or all"
+        );
+        assert_eq!(
+            encode("duration 500\ntrack all loop\nkey 0 fff linear").unwrap_err(),
+            "line 3: invalid color 'fff', expected rrggbb"
+        );
+        assert_eq!(
+            encode("duration 500\ntrack all loop\nkey 0 ffffff\nkey 40 000000").unwrap_err(),
+            "device would reject it: Pattern flashes too fast"
+        );
+    }
+}
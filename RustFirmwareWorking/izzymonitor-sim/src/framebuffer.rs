@@ -0,0 +1,61 @@
+//! In-memory framebuffer
+//! A 160×128 Rgb565 draw target standing in for the ST7735
+
+use core::convert::Infallible;
+
+use embedded_graphics::{pixelcolor::Rgb565, prelude::*, Pixel};
+use izzymonitor_core::display::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// Framebuffer with the same size and color format as the panel
+#[derive(Clone)]
+pub struct Framebuffer {
+    pixels: Vec<Rgb565>,
+}
+
+impl Framebuffer {
+    /// Create a black framebuffer
+    pub fn new() -> Self {
+        Self {
+            pixels: vec![Rgb565::BLACK; (SCREEN_WIDTH * SCREEN_HEIGHT) as usize],
+        }
+    }
+
+    /// Get the color of one pixel
+    pub fn pixel(&self, x: u32, y: u32) -> Rgb565 {
+        self.pixels[(y * SCREEN_WIDTH + x) as usize]
+    }
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OriginDimensions for Framebuffer {
+    fn size(&self) -> Size {
+        Size::new(SCREEN_WIDTH, SCREEN_HEIGHT)
+    }
+}
+
+impl DrawTarget for Framebuffer {
+    type Color = Rgb565;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        // Clip like the real panel does
+        for Pixel(point, color) in pixels {
+            if point.x >= 0
+                && point.y >= 0
+                && (point.x as u32) < SCREEN_WIDTH
+                && (point.y as u32) < SCREEN_HEIGHT
+            {
+                self.pixels[(point.y as u32 * SCREEN_WIDTH + point.x as u32) as usize] = color;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,95 @@
+//! Desktop simulator for the panel UI
+//! Renders the same screens as the firmware into PNG frames, no ESP32 needed
+//!
+//! Usage: `izzymonitor-sim [--out DIR] [--scale N] [SCRIPT]`
+//!
+//! Without a script, commands are read from stdin one line at a time, so
+//! typing `2` and Enter presses key 2. See `script.rs` for the format.
+
+mod framebuffer;
+mod script;
+mod sim;
+
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use sim::Simulator;
+
+struct Args {
+    out_dir: PathBuf,
+    scale: u32,
+    script: Option<PathBuf>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        out_dir: PathBuf::from("sim-frames"),
+        scale: 3,
+        script: None,
+    };
+
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--out" => args.out_dir = iter.next().ok_or("--out needs a directory")?.into(),
+            "--scale" => {
+                let scale = iter.next().ok_or("--scale needs a number")?;
+                args.scale = scale
+                    .parse()
+                    .map_err(|_| format!("invalid scale '{scale}'"))?;
+            }
+            "-h" | "--help" => {
+                return Err("usage: izzymonitor-sim [--out DIR] [--scale N] [SCRIPT]".into())
+            }
+            _ if args.script.is_none() => args.script = Some(arg.into()),
+            _ => return Err(format!("unexpected argument '{arg}'")),
+        }
+    }
+
+    Ok(args)
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let mut sim = Simulator::new(&args.out_dir, args.scale)?;
+
+    let input: Box<dyn BufRead> = match &args.script {
+        Some(path) => Box::new(BufReader::new(
+            std::fs::File::open(path)
+                .map_err(|e| format!("cannot open {}: {e}", path.display()))?,
+        )),
+        None => Box::new(BufReader::new(std::io::stdin())),
+    };
+
+    for (number, line) in input.lines().enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        let commands =
+            script::parse_line(&line).map_err(|e| format!("line {}: {e}", number + 1))?;
+
+        for command in &commands {
+            sim.run(command)?;
+        }
+
+        if args.script.is_none() && !commands.is_empty() {
+            let layout = sim.menu().layout();
+            println!(
+                "{:?} [{}] active: {}",
+                sim.menu().screen(),
+                layout.labels.join("|"),
+                layout.active_index + 1
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match parse_args().and_then(run) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{error}");
+            ExitCode::FAILURE
+        }
+    }
+}
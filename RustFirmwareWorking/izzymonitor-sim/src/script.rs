@@ -0,0 +1,121 @@
+//! Input scripts
+//! Maps keyboard keys and script lines onto the six panel keys
+//!
+//! Each line is one command, `#` starts a comment:
+//!
+//! ```text
+//! press 2      # press and release key 2 (keys are numbered 1-6)
+//! hold 5       # press key 5 and keep it down
+//! release 5
+//! wait 500     # advance the simulated clock in milliseconds
+//! snap trip    # write the current frame as trip.png
+//! 2q           # bare keys: 1-6 or the home row q w e r t y
+//! ```
+
+use izzymonitor_core::KEY_COUNT;
+
+/// Keyboard row mapped to the six keys, left to right
+const KEY_ROW: [char; KEY_COUNT] = ['q', 'w', 'e', 'r', 't', 'y'];
+
+/// One simulator input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Press(usize),
+    Hold(usize),
+    Release(usize),
+    Wait(u64),
+    Snap(String),
+}
+
+/// Translate a keyboard character to a zero-based key index
+pub fn key_for_char(c: char) -> Option<usize> {
+    match c {
+        '1'..='6' => Some(c as usize - '1' as usize),
+        _ => KEY_ROW.iter().position(|&k| k == c.to_ascii_lowercase()),
+    }
+}
+
+fn parse_key(arg: Option<&str>) -> Result<usize, String> {
+    let arg = arg.ok_or("missing key number")?;
+    match arg.parse::<usize>() {
+        Ok(n) if (1..=KEY_COUNT).contains(&n) => Ok(n - 1),
+        _ => Err(format!("invalid key '{arg}', expected 1-{KEY_COUNT}")),
+    }
+}
+
+/// Parse one script line into zero or more commands
+pub fn parse_line(line: &str) -> Result<Vec<Command>, String> {
+    let line = line.split('#').next().unwrap_or("").trim();
+    let mut words = line.split_whitespace();
+
+    let Some(first) = words.next() else {
+        return Ok(Vec::new());
+    };
+
+    let command = match first {
+        "press" => Command::Press(parse_key(words.next())?),
+        "hold" => Command::Hold(parse_key(words.next())?),
+        "release" => Command::Release(parse_key(words.next())?),
+        "wait" => {
+            let ms = words.next().ok_or("missing wait time")?;
+            Command::Wait(
+                ms.parse()
+                    .map_err(|_| format!("invalid wait time '{ms}'"))?,
+            )
+        }
+        "snap" => Command::Snap(words.next().ok_or("missing snapshot name")?.to_string()),
+        _ => {
+            // Bare key presses, e.g. "2q"
+            return line
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .map(|c| {
+                    key_for_char(c)
+                        .map(Command::Press)
+                        .ok_or_else(|| format!("unknown command '{first}'"))
+                })
+                .collect();
+        }
+    };
+
+    if let Some(extra) = words.next() {
+        return Err(format!("unexpected argument '{extra}'"));
+    }
+    Ok(vec![command])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands_and_comments() {
+        assert_eq!(parse_line("press 2 # trip"), Ok(vec![Command::Press(1)]));
+        assert_eq!(parse_line("wait 250"), Ok(vec![Command::Wait(250)]));
+        assert_eq!(
+            parse_line("snap main"),
+            Ok(vec![Command::Snap("main".into())])
+        );
+        assert_eq!(parse_line("   # nothing"), Ok(vec![]));
+    }
+
+    #[test]
+    fn bare_keys_map_to_presses() {
+        assert_eq!(
+            parse_line("1 wY"),
+            Ok(vec![
+                Command::Press(0),
+                Command::Press(1),
+                Command::Press(5)
+            ])
+        );
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert!(parse_line("press 7").is_err());
+        assert!(parse_line("hold").is_err());
+        assert!(parse_line("jump").is_err());
+        assert!(parse_line("wait 1 2").is_err());
+    }
+}
@@ -0,0 +1,192 @@
+//! Simulated panel
+//! Runs the core menu, button and LED logic against the framebuffer
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use embedded_graphics::pixelcolor::Rgb888;
+use embedded_graphics::prelude::*;
+use izzymonitor_core::buttons::{ButtonState, ButtonTracker};
+use izzymonitor_core::display::{Display, SCREEN_HEIGHT, SCREEN_WIDTH};
+use izzymonitor_core::leds::{key_frame, Calibrator, LedFrame};
+use izzymonitor_core::menu::{Menu, Redraw};
+use izzymonitor_core::KEY_COUNT;
+
+use crate::framebuffer::Framebuffer;
+use crate::script::Command;
+
+/// How long the startup screen stays up, as in the firmware display task
+const STARTUP_MS: u64 = 2000;
+
+/// Width of the LED strip drawn to the right of the screen
+const STRIP_WIDTH: u32 = 24;
+const LED_SIZE: u32 = 16;
+
+/// Panel state plus the simulated clock
+pub struct Simulator {
+    menu: Menu,
+    display: Display<Framebuffer>,
+    trackers: [ButtonTracker; KEY_COUNT],
+    clock_ms: u64,
+    out_dir: PathBuf,
+    scale: u32,
+    frame: usize,
+}
+
+impl Simulator {
+    /// Boot into the startup screen, writing frames into `out_dir`
+    pub fn new(out_dir: &Path, scale: u32) -> Result<Self, String> {
+        std::fs::create_dir_all(out_dir)
+            .map_err(|e| format!("cannot create {}: {e}", out_dir.display()))?;
+
+        let mut sim = Self {
+            menu: Menu::new(),
+            display: Display::new(Framebuffer::new()),
+            trackers: [ButtonTracker::new(); KEY_COUNT],
+            clock_ms: 0,
+            out_dir: out_dir.to_path_buf(),
+            scale: scale.max(1),
+            frame: 0,
+        };
+        sim.display.draw_menu(&sim.menu)?;
+        sim.dump_frame()?;
+        Ok(sim)
+    }
+
+    /// Current LED colors, as the LED animation task would show them
+    pub fn leds(&self) -> LedFrame {
+        let states: [ButtonState; KEY_COUNT] = core::array::from_fn(|i| self.trackers[i].state());
+        // The calibration test color goes over everything else
+        let mut frame = match self.menu.test_color() {
+            Some(color) => [color; KEY_COUNT],
+            None => key_frame(&self.menu.hint_colors(), &states),
+        };
+        Calibrator::new(self.menu.calibration()).apply(&mut frame);
+        frame
+    }
+
+    /// Get the simulated screen
+    pub fn framebuffer(&self) -> &Framebuffer {
+        self.display.target()
+    }
+
+    /// Get the menu state
+    pub fn menu(&self) -> &Menu {
+        &self.menu
+    }
+
+    /// Apply one input command
+    pub fn run(&mut self, command: &Command) -> Result<(), String> {
+        match command {
+            Command::Press(key) => {
+                self.set_key(*key, true)?;
+                self.set_key(*key, false)
+            }
+            Command::Hold(key) => self.set_key(*key, true),
+            Command::Release(key) => self.set_key(*key, false),
+            Command::Wait(ms) => self.advance(*ms),
+            Command::Snap(name) => self.write_png(&self.out_dir.join(format!("{name}.png"))),
+        }
+    }
+
+    fn advance(&mut self, ms: u64) -> Result<(), String> {
+        let before = self.clock_ms;
+        self.clock_ms += ms;
+        if before < STARTUP_MS && self.clock_ms >= STARTUP_MS {
+            let redraw = self.menu.finish_startup();
+            self.redraw(redraw)?;
+        }
+        Ok(())
+    }
+
+    fn set_key(&mut self, key: usize, pressed: bool) -> Result<(), String> {
+        // A release settles back to idle on the following sample
+        while let Some(state) = self.trackers[key].update(pressed) {
+            if state == ButtonState::Pressed {
+                let redraw = self.menu.press(key);
+                self.redraw(redraw)?;
+            }
+            if state != ButtonState::Idle {
+                self.dump_frame()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn redraw(&mut self, redraw: Redraw) -> Result<(), String> {
+        match redraw {
+            Redraw::None => Ok(()),
+            Redraw::Buttons => self
+                .display
+                .draw_buttons(self.menu.layout())
+                .map_err(Into::into),
+            Redraw::Content | Redraw::Screen => {
+                self.display.draw_menu(&self.menu).map_err(Into::into)
+            }
+        }
+    }
+
+    fn dump_frame(&mut self) -> Result<(), String> {
+        let path = self.out_dir.join(format!("frame_{:04}.png", self.frame));
+        self.frame += 1;
+        self.write_png(&path)
+    }
+
+    /// Render the screen and the LED strip into one RGB image
+    pub fn render(&self) -> (u32, u32, Vec<u8>) {
+        let width = SCREEN_WIDTH + STRIP_WIDTH;
+        let height = SCREEN_HEIGHT;
+        let mut image = vec![0u8; (width * height * 3) as usize];
+
+        let mut put = |x: u32, y: u32, color: Rgb888| {
+            let i = ((y * width + x) * 3) as usize;
+            image[i..i + 3].copy_from_slice(&[color.r(), color.g(), color.b()]);
+        };
+
+        let fb = self.framebuffer();
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                put(x, y, Rgb888::from(fb.pixel(x, y)));
+            }
+        }
+
+        // Key LEDs top to bottom, key 1 first
+        let gap = (SCREEN_HEIGHT - LED_SIZE * KEY_COUNT as u32) / (KEY_COUNT as u32 + 1);
+        for (i, led) in self.leds().iter().enumerate() {
+            let top = gap + i as u32 * (LED_SIZE + gap);
+            let left = SCREEN_WIDTH + (STRIP_WIDTH - LED_SIZE) / 2;
+            for y in top..top + LED_SIZE {
+                for x in left..left + LED_SIZE {
+                    put(x, y, Rgb888::new(led.r, led.g, led.b));
+                }
+            }
+        }
+
+        (width, height, image)
+    }
+
+    fn write_png(&self, path: &Path) -> Result<(), String> {
+        let (width, height, image) = self.render();
+        let scale = self.scale;
+
+        // Nearest-neighbour upscale so small pixels stay crisp
+        let mut scaled = Vec::with_capacity(image.len() * (scale * scale) as usize);
+        for y in 0..height * scale {
+            for x in 0..width * scale {
+                let i = (((y / scale) * width + x / scale) * 3) as usize;
+                scaled.extend_from_slice(&image[i..i + 3]);
+            }
+        }
+
+        let file =
+            File::create(path).map_err(|e| format!("cannot create {}: {e}", path.display()))?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), width * scale, height * scale);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&scaled))
+            .map_err(|e| format!("cannot write {}: {e}", path.display()))
+    }
+}